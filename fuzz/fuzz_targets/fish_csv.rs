@@ -0,0 +1,21 @@
+//Run with `cargo fuzz run fish_csv` from the repo root, needs nightly
+//Whatever comes in, the parser has to give back a fish worth drawing or an error, never a panic
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x11_make_a_fish::fish_csv;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(fish_str) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(fish) = fish_csv::parse(fish_str) {
+        assert!(!fish.is_empty());
+        for line in &fish {
+            assert!(!line.is_empty());
+            assert!(line.iter().all(|&(x, y)| x.is_finite() && y.is_finite()));
+        }
+        //Whatever we write has to parse again
+        fish_csv::parse(&fish_csv::write(&fish)).unwrap();
+    }
+});
@@ -0,0 +1,257 @@
+use crate::FishError;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
+
+//X servers listen on 6000 + display number
+pub const X_TCP_PORT_BASE: u16 = 6000;
+
+//A parsed X display address, from any of the ways people write them:
+//  host             display 0, screen 0
+//  host:1.2         display 1, screen 2
+//  [2001:db8::1]:0  IPv6 needs brackets to have a display number
+//  2001:db8::1      bare IPv6 is all host, display 0
+//  tcp/host:0       protocol prefix, like Xlib takes
+//  unix:0 or :0     local socket, only makes sense for the CLI
+//  /tmp/.X11-unix/X0                          a socket by its path, same
+//  /private/tmp/com.apple.launchd.x/org.xquartz:0  what DISPLAY looks like under XQuartz
+//  @/tmp/.X11-unix/X0                         Linux abstract namespace, the way ss shows them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayAddress {
+    //Empty for local unix sockets
+    pub host: String,
+    pub display: u16,
+    pub screen: u16,
+    //Set when the socket was named outright instead of going by the display number
+    pub socket: Option<LocalSocket>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalSocket {
+    Path(PathBuf),
+    Abstract(String),
+}
+
+fn bad(address: &str, why: &str) -> FishError {
+    FishError::BadParams(format!("bad address {:?}: {}", address, why))
+}
+
+impl DisplayAddress {
+    pub fn parse(address: &str) -> Result<Self, FishError> {
+        let original = address;
+        let address = address.trim();
+        if address.is_empty() {
+            return Err(bad(original, "it's empty"));
+        }
+        if let Some(name) = address.strip_prefix('@') {
+            return Ok(DisplayAddress::socket(LocalSocket::Abstract(name.to_string()), socket_display(name)));
+        }
+        //launchd paths have a : in them, so this has to come before looking for one
+        if address.starts_with('/') {
+            return Ok(DisplayAddress::socket(LocalSocket::Path(PathBuf::from(address)), socket_display(address)));
+        }
+
+        //Protocol prefix, which decides what the host part means
+        let (protocol, rest) = match address.split_once('/') {
+            Some((protocol, rest)) => (Some(protocol.to_ascii_lowercase()), rest),
+            None => (None, address),
+        };
+        let local = match protocol.as_deref() {
+            None | Some("tcp") | Some("inet") | Some("inet6") => false,
+            Some("unix") | Some("local") => true,
+            Some(protocol) => return Err(bad(original, &format!("don't know the {:?} protocol", protocol))),
+        };
+
+        let (host, display) = if let Some(bracketed) = rest.strip_prefix('[') {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| bad(original, "IPv6 address is missing its closing ]"))?;
+            host.parse::<Ipv6Addr>()
+                .map_err(|_| bad(original, &format!("{:?} isn't an IPv6 address", host)))?;
+            match after {
+                "" => (host, None),
+                after => (
+                    host,
+                    Some(after.strip_prefix(':').ok_or_else(|| bad(original, "expected : after ]"))?),
+                ),
+            }
+        } else if rest.parse::<Ipv6Addr>().is_ok() {
+            (rest, None)
+        } else {
+            match rest.rsplit_once(':') {
+                Some((host, display)) if !host.contains(':') => (host, Some(display)),
+                Some(_) => return Err(bad(original, "IPv6 addresses with a display number need [brackets]")),
+                None => (rest, None),
+            }
+        };
+
+        let (display, screen) = match display {
+            None => (0, 0),
+            Some(display) => {
+                let (display, screen) = display.split_once('.').unwrap_or((display, "0"));
+                let display: u16 = display
+                    .parse()
+                    .map_err(|_| bad(original, &format!("{:?} isn't a display number", display)))?;
+                let screen: u16 = screen
+                    .parse()
+                    .map_err(|_| bad(original, &format!("{:?} isn't a screen number", screen)))?;
+                (display, screen)
+            }
+        };
+        if display > u16::MAX - X_TCP_PORT_BASE {
+            return Err(bad(original, "display number is too big"));
+        }
+
+        let host = match (local, host) {
+            (true, _) | (false, "unix") => String::new(),
+            (false, host) => {
+                if host.chars().any(|c| c.is_whitespace() || c == '/' || c == '@') {
+                    return Err(bad(original, &format!("{:?} isn't a host name", host)));
+                }
+                host.to_ascii_lowercase()
+            }
+        };
+
+        Ok(DisplayAddress {
+            host,
+            display,
+            screen,
+            socket: None,
+        })
+    }
+
+    fn socket(socket: LocalSocket, display: u16) -> Self {
+        DisplayAddress {
+            host: String::new(),
+            display,
+            screen: 0,
+            socket: Some(socket),
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.host.is_empty()
+    }
+
+    pub fn tcp_port(&self) -> u16 {
+        X_TCP_PORT_BASE + self.display
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        match &self.socket {
+            Some(LocalSocket::Path(path)) => path.clone(),
+            _ => PathBuf::from(format!("/tmp/.X11-unix/X{}", self.display)),
+        }
+    }
+}
+
+//X0 for the usual sockets, :0 on the end for launchd ones, and display 0 for anything else
+fn socket_display(path: &str) -> u16 {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rsplit_once(':')
+        .map(|(_, display)| display)
+        .or_else(|| name.strip_prefix('X'))
+        .and_then(|display| display.parse().ok())
+        .unwrap_or(0)
+}
+
+//Back to the host:display.screen form x11rb and Xlib understand, or the socket as it was given
+impl fmt::Display for DisplayAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.socket {
+            Some(LocalSocket::Path(path)) => write!(f, "{}", path.display()),
+            Some(LocalSocket::Abstract(name)) => write!(f, "@{}", name),
+            None => write!(f, "{}:{}.{}", self.host, self.display, self.screen),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(address: &str) -> (String, u16, u16) {
+        let address = DisplayAddress::parse(address).unwrap_or_else(|err| panic!("{:?}: {}", address, err));
+        (address.host, address.display, address.screen)
+    }
+
+    #[test]
+    fn parses_tcp_addresses() {
+        let cases = [
+            ("fish.example.com", "fish.example.com", 0, 0),
+            (" Fish.Example.com:1.2 ", "fish.example.com", 1, 2),
+            ("10.0.0.1:7", "10.0.0.1", 7, 0),
+            ("[2001:db8::1]:3", "2001:db8::1", 3, 0),
+            ("[2001:db8::1]:3.1", "2001:db8::1", 3, 1),
+            ("[2001:db8::1]", "2001:db8::1", 0, 0),
+            ("2001:db8::1", "2001:db8::1", 0, 0),
+            //A bare IPv6 address that ends in what looks like a display number is still all host
+            ("2001:db8::1:0", "2001:db8::1:0", 0, 0),
+            ("::1", "::1", 0, 0),
+            ("tcp/fish:2", "fish", 2, 0),
+            ("INET6/[::1]:1", "::1", 1, 0),
+            ("host:59535", "host", 59535, 0),
+        ];
+        for (address, host, display, screen) in cases {
+            assert_eq!(parsed(address), (host.to_string(), display, screen), "{:?}", address);
+        }
+        assert_eq!(DisplayAddress::parse("host:59535").unwrap().tcp_port(), 65535);
+        assert_eq!(DisplayAddress::parse("[::1]:2").unwrap().tcp_port(), 6002);
+    }
+
+    #[test]
+    fn parses_local_addresses() {
+        for address in [":0", "unix:0", "unix/anything:0", "local/fish:0"] {
+            let parsed = DisplayAddress::parse(address).unwrap();
+            assert!(parsed.is_local(), "{:?}", address);
+            assert_eq!(parsed.socket_path(), PathBuf::from("/tmp/.X11-unix/X0"));
+        }
+        assert_eq!(DisplayAddress::parse(":3.1").unwrap().socket_path(), PathBuf::from("/tmp/.X11-unix/X3"));
+
+        let path = DisplayAddress::parse("/tmp/.X11-unix/X4").unwrap();
+        assert_eq!(path.socket, Some(LocalSocket::Path(PathBuf::from("/tmp/.X11-unix/X4"))));
+        assert_eq!(path.display, 4);
+        let launchd = DisplayAddress::parse("/private/tmp/com.apple.launchd.x/org.xquartz:0").unwrap();
+        assert!(launchd.is_local());
+        assert_eq!(launchd.display, 0);
+        let abstract_ = DisplayAddress::parse("@/tmp/.X11-unix/X1").unwrap();
+        assert_eq!(abstract_.socket, Some(LocalSocket::Abstract("/tmp/.X11-unix/X1".to_string())));
+        assert_eq!(abstract_.display, 1);
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        let cases = [
+            "",
+            "   ",
+            "[2001:db8::1",
+            "[2001:db8::1]0",
+            "[fish.example.com]:0",
+            "1:2:3:0",
+            "host:x",
+            "host:",
+            "host:0.x",
+            "host:-1",
+            "host:59536",
+            "host:70000",
+            "ftp/host:0",
+            "fish example:0",
+            "fish@example:0",
+        ];
+        for address in cases {
+            assert!(
+                matches!(DisplayAddress::parse(address), Err(FishError::BadParams(_))),
+                "{:?} should be rejected",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn prints_back_the_same_address() {
+        for address in ["fish.example.com:1.2", "10.0.0.1:3.0", "/tmp/.X11-unix/X0", "@/tmp/.X11-unix/X1"] {
+            let parsed = DisplayAddress::parse(address).unwrap();
+            assert_eq!(parsed.to_string(), address);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::FishError;
+
+//Keys a caller can put in x-api-key, for deployments that set XFISH_API_KEYS (comma separated)
+//Without any, the fish service is open to everyone like it always was
+pub struct ApiKeys {
+    keys: Vec<Vec<u8>>,
+}
+
+impl ApiKeys {
+    pub fn parse(keys: &str) -> Self {
+        ApiKeys {
+            keys: keys
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| key.as_bytes().to_vec())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    //No key is a 401, a key we don't know is a 403
+    //Every key gets compared in full, so how long it takes says nothing about how close a guess was
+    pub fn check(&self, given: Option<&str>) -> Result<(), FishError> {
+        let Some(given) = given else {
+            return Err(FishError::AuthRejected("this fish service needs an x-api-key".to_string()));
+        };
+        let given = given.trim().as_bytes();
+        let known = self
+            .keys
+            .iter()
+            .fold(false, |known, key| known | (key.len() == given.len() && openssl::memcmp::eq(key, given)));
+        if known {
+            Ok(())
+        } else {
+            Err(FishError::AccessDenied("that x-api-key isn't one of ours".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert!(ApiKeys::parse("").is_empty());
+        assert!(ApiKeys::parse(" , ,").is_empty());
+        assert_eq!(ApiKeys::parse(" one, two ,,three").keys.len(), 3);
+    }
+
+    #[test]
+    fn accepts_known_keys() {
+        let keys = ApiKeys::parse("first-key, second-key");
+        assert!(keys.check(Some("first-key")).is_ok());
+        assert!(keys.check(Some(" second-key ")).is_ok());
+    }
+
+    #[test]
+    fn missing_key_is_unauthorized() {
+        let keys = ApiKeys::parse("first-key");
+        assert!(matches!(keys.check(None), Err(FishError::AuthRejected(_))));
+    }
+
+    #[test]
+    fn rejects_keys_we_dont_know() {
+        let keys = ApiKeys::parse("first-key, second-key");
+        let wrong = ["", "   ", "first-ke", "first-key2", "FIRST-KEY", "first-key, second-key", "second-keyfirst-key"];
+        for given in wrong {
+            assert!(matches!(keys.check(Some(given)), Err(FishError::AccessDenied(_))), "{:?}", given);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::guard::PixmapGuard;
+use crate::hints;
+use crate::input::{self, Keymap};
+use crate::surface::Surface;
+use crate::{
+    fit_transform, paint_pixmap, shape, DrawOptions, Ending, Error, Fish, FishError, Pen, Scene, XFishSession,
+};
+use crate::POLL_INTERVAL;
+use std::borrow::Cow;
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt, Point, Visibility, Window};
+use x11rb::protocol::Event;
+
+//About 30 frames a second, smooth enough for a fish
+const TICK: Duration = Duration::from_millis(33);
+//Pixels per tick
+const SWIM_SPEED: f64 = 3.0;
+//How far up and down the fish bobs, and how fast
+const BOB_HEIGHT: f64 = 6.0;
+const BOB_RATE: f64 = 0.1;
+//Chasing the pointer is slower than swimming laps, and slows down more on the way in so the fish doesn't overshoot
+const CHASE_SPEED: f64 = 2.0;
+const CHASE_EASING: f64 = 0.05;
+//Don't turn around for a pointer that's only just behind, or the fish flips back and forth on top of it
+const TURN_DISTANCE: f64 = 8.0;
+
+//Where the fish is in the tank and which way it's going
+pub(crate) struct Swimmer {
+    x: f64,
+    //Up or down from the middle of the tank, only moves when there's a pointer to chase
+    y: f64,
+    ticks: u64,
+    facing_left: bool,
+}
+
+impl Swimmer {
+    pub(crate) fn new() -> Self {
+        Swimmer {
+            x: 0.0,
+            y: 0.0,
+            ticks: 0,
+            facing_left: false,
+        }
+    }
+
+    //The fish gets half the tank each way, so there's room to swim
+    pub(crate) fn fish_size((width, height): (u16, u16)) -> (u16, u16) {
+        ((width / 2).max(1), (height / 2).max(1))
+    }
+
+    //Move along, turning around at the walls, or head for the pointer if there is one in the tank
+    pub(crate) fn step(&mut self, tank: (u16, u16), pointer: Option<(i16, i16)>) {
+        let fish_size = Self::fish_size(tank);
+        let room = (tank.0 - fish_size.0) as f64;
+        let headroom = (tank.1 - fish_size.1) as f64 / 2.0;
+        self.ticks += 1;
+        let Some((pointer_x, pointer_y)) = pointer else {
+            self.x += if self.facing_left { -SWIM_SPEED } else { SWIM_SPEED };
+            if self.x >= room {
+                self.x = room;
+                self.facing_left = true;
+            } else if self.x <= 0.0 {
+                self.x = 0.0;
+                self.facing_left = false;
+            }
+            //Drift back to the middle once there's nothing to chase
+            self.y -= self.y.clamp(-CHASE_SPEED, CHASE_SPEED);
+            return;
+        };
+
+        //Aim the middle of the fish at the pointer
+        let dx = pointer_x as f64 - (self.x + fish_size.0 as f64 / 2.0);
+        let dy = pointer_y as f64 - (tank.1 as f64 / 2.0 + self.y);
+        let distance = dx.hypot(dy);
+        if distance > 0.0 {
+            let speed = (distance * CHASE_EASING).min(CHASE_SPEED);
+            self.x += dx / distance * speed;
+            self.y += dy / distance * speed;
+        }
+        if dx.abs() > TURN_DISTANCE {
+            self.facing_left = dx < 0.0;
+        }
+        self.x = self.x.clamp(0.0, room);
+        self.y = self.y.clamp(-headroom, headroom);
+    }
+
+    //The fish's lines where it is right now, flipped if it's swimming the other way
+    pub(crate) fn place(&self, fish: &Fish, tank: (u16, u16)) -> Vec<Vec<Point>> {
+        let fish_size = Self::fish_size(tank);
+        let (scale, offset_x, offset_y) = fit_transform(fish_size);
+        let bob = (self.ticks as f64 * BOB_RATE).sin() * BOB_HEIGHT;
+        let top = (tank.1 - fish_size.1) as f64 / 2.0 + self.y + bob;
+        fish.iter()
+            .map(|line| {
+                line.iter()
+                    .map(|&(x, y)| {
+                        let x = x * scale + offset_x;
+                        let x = if self.facing_left { fish_size.0 as f64 - x } else { x };
+                        Point {
+                            x: (x + self.x) as i16,
+                            y: (y * scale + offset_y + top) as i16,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl XFishSession {
+    //Keep the fish swimming until the window is closed
+    //Frames get drawn into a pixmap and copied over in one go, so nothing flickers
+    //Interactive tanks have the fish chase the pointer, and take the same clicks and keys as still fish
+    pub(crate) fn swim(
+        &self,
+        scene: &mut Scene,
+        options: &DrawOptions,
+        deadline: Instant,
+        surface: &Surface,
+        win_id: Window,
+        pen: &Pen,
+    ) -> Result<Ending, Error> {
+        let conn = &*self.conn;
+        let atoms = &self.atoms;
+        let keymap = if options.interactive {
+            Some(Keymap::new(conn)?)
+        } else {
+            None
+        };
+
+        let mut tank = options.size;
+        let mut frame = PixmapGuard::new(conn, conn.generate_id()?);
+        conn.create_pixmap(surface.depth, frame.id, win_id, tank.0, tank.1)?;
+        let mut swimmer = Swimmer::new();
+        let mut pointer = None;
+        let mut next_tick = Instant::now();
+        //Frames nobody can see aren't worth drawing, apart from the first, which is the proof
+        let mut visible = true;
+
+        let _span = tracing::info_span!("event_loop", mode = "aquarium").entered();
+        loop {
+            if Instant::now() >= deadline {
+                println!("Ran out of time, taking the fish back");
+                break;
+            }
+            if self.cancelled() {
+                println!("Drawing was cancelled, taking the fish back");
+                break;
+            }
+
+            //Time for the next frame
+            if Instant::now() >= next_tick {
+                //The fish carries on swimming while hidden, it just doesn't get drawn
+                swimmer.step(pen.room(tank), pointer);
+                if visible || scene.report.is_none() {
+                    let lines = swimmer.place(&scene.fish, pen.room(tank));
+                    paint_pixmap(conn, surface, frame.id, pen, &lines, tank)?;
+                    //The window's shape has to swim along with the fish
+                    if options.shaped {
+                        shape::fit_window(conn, win_id, &lines, tank, options)?;
+                    }
+                    conn.copy_area(frame.id, win_id, pen.gc, 0, 0, 0, 0, tank.0, tank.1)?;
+                    conn.flush()?;
+                    //The first frame is as good a proof as any, the fish only moves from there
+                    self.finished(scene, options, win_id, frame.id, tank);
+                }
+                next_tick += TICK;
+                //If we fell behind, don't try to catch up by swimming at warp speed
+                if next_tick < Instant::now() {
+                    next_tick = Instant::now() + TICK;
+                }
+            }
+
+            //Deal with everything that happened since the last frame
+            let mut closed = false;
+            while let Some(event) = conn.poll_for_event()? {
+                match event {
+                    //Next frame covers the whole window anyway
+                    Event::Expose(_) => {}
+                    //Bigger tank, more room to swim
+                    Event::ConfigureNotify(event) if (event.width, event.height) != tank => {
+                        tank = (event.width.max(1), event.height.max(1));
+                        frame = PixmapGuard::new(conn, conn.generate_id()?);
+                        conn.create_pixmap(surface.depth, frame.id, win_id, tank.0, tank.1)?;
+                    }
+                    Event::MotionNotify(event) => pointer = Some((event.event_x, event.event_y)),
+                    //Back to laps once the pointer's gone
+                    Event::LeaveNotify(_) => pointer = None,
+                    //A new fish just carries on from wherever the old one was
+                    Event::ButtonPress(_) if options.interactive => {
+                        match self.new_fish.as_ref().map(|new_fish| new_fish()) {
+                            Some(Ok(fish)) => scene.fish = Cow::Owned(fish),
+                            Some(Err(err)) => println!("Couldn't get another fish: {}", err),
+                            None => {}
+                        }
+                    }
+                    Event::KeyPress(event) if options.interactive => {
+                        let keysym = keymap.as_ref().map_or(0, |keymap| keymap.keysym(event.detail));
+                        if keysym == input::KEY_Q {
+                            println!("q was pressed, closing");
+                            closed = true;
+                        } else if keysym == input::KEY_N {
+                            return Ok(Ending::Restyle);
+                        }
+                    }
+                    Event::SelectionRequest(event) => {
+                        if let Some(clipboard) = &scene.clipboard {
+                            clipboard.answer(conn, atoms, &event)?;
+                        }
+                    }
+                    Event::SelectionClear(_) => scene.clipboard = None,
+                    Event::ClientMessage(event) if hints::is_ping(atoms, &event) => {
+                        hints::answer_ping(conn, self.screen().root, event)?;
+                    }
+                    Event::ClientMessage(event) => {
+                        let data = event.data.as_data32();
+                        if event.format == 32 && event.window == win_id && data[0] == atoms.WM_DELETE_WINDOW {
+                            scene.closed_by_recipient();
+                            closed = true;
+                        }
+                    }
+                    Event::DestroyNotify(event) if event.window == win_id => {
+                        println!("Window was destroyed");
+                        return Ok(Ending::Destroyed);
+                    }
+                    Event::UnmapNotify(event) if event.window == win_id && !hints::is_iconic(conn, atoms, win_id) => {
+                        println!("Window was unmapped, taking that as closed");
+                        closed = true;
+                    }
+                    //Iconified, it'll be back
+                    Event::UnmapNotify(_) => visible = false,
+                    Event::MapNotify(_) => visible = true,
+                    Event::VisibilityNotify(event) => visible = event.state != Visibility::FULLY_OBSCURED,
+                    Event::Error(err) => return Err(FishError::from(err).into()),
+                    ev => println!("Got an unknown event: {:?}", ev),
+                }
+            }
+            if closed {
+                break;
+            }
+
+            //Sleep until the next frame, but not so long that closing the window feels slow
+            let until_tick = next_tick.saturating_duration_since(Instant::now());
+            thread::sleep(until_tick.min(POLL_INTERVAL));
+        }
+
+        Ok(Ending::Closed)
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Fish, FishError, FISH_CANVAS};
+use std::str::FromStr;
+
+//Wide enough to look like a fish, narrow enough for any terminal
+pub const DEFAULT_ASCII_WIDTH: usize = 72;
+//Any narrower and it's a smudge, any wider and it wraps on most screens
+pub const MIN_ASCII_WIDTH: usize = 16;
+pub const MAX_ASCII_WIDTH: usize = 240;
+//Terminal cells are about twice as tall as they are wide
+const CELL_ASPECT: f64 = 2.0;
+//Braille cells are 2 dots wide and 4 tall, that's where the extra detail comes from
+const BRAILLE_COLS: usize = 2;
+const BRAILLE_ROWS: usize = 4;
+const BRAILLE_BLANK: u32 = 0x2800;
+
+//What the fish gets drawn with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Charset {
+    //- | / and \, works everywhere
+    #[default]
+    Ascii,
+    //Braille dots, eight to a character, for terminals with a decent font
+    Unicode,
+}
+
+impl FromStr for Charset {
+    type Err = FishError;
+
+    fn from_str(charset: &str) -> Result<Self, Self::Err> {
+        match charset.trim().to_ascii_lowercase().as_str() {
+            "ascii" => Ok(Charset::Ascii),
+            "unicode" | "braille" => Ok(Charset::Unicode),
+            _ => Err(FishError::BadParams(format!("don't know the {:?} charset, try ascii or unicode", charset))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsciiOptions {
+    //In characters
+    pub width: usize,
+    pub charset: Charset,
+}
+
+impl Default for AsciiOptions {
+    fn default() -> Self {
+        AsciiOptions {
+            width: DEFAULT_ASCII_WIDTH,
+            charset: Charset::default(),
+        }
+    }
+}
+
+//Which character draws a step going this way, by how steep it is
+fn stroke_char(dx: f64, dy: f64) -> char {
+    let slope = dy.atan2(dx).to_degrees().rem_euclid(180.0);
+    match slope {
+        s if !(22.5..157.5).contains(&s) => '-',
+        s if s < 67.5 => '\\',
+        s if s < 112.5 => '|',
+        _ => '/',
+    }
+}
+
+//Walk every line of the fish across a grid of dots, `dot` gets each one it lands on and which way the line was going
+//`aspect` is how much taller a dot is than it is wide
+fn rasterize(fish: &Fish, (width, height): (usize, usize), aspect: f64, mut dot: impl FnMut(usize, usize, f64, f64)) {
+    let scale = width as f64 / FISH_CANVAS.0;
+    let place = |(x, y): (f64, f64)| (x * scale, y * scale / aspect);
+    for line in fish {
+        for pair in line.windows(2) {
+            let (start, end) = (place(pair[0]), place(pair[1]));
+            //Which way it goes is the whole segment's business, where it gets walked is only the bit on the grid
+            let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+            let Some(((x0, y0), (x1, y1))) = clip(start, end, (width as f64, height as f64)) else {
+                continue;
+            };
+            //One step per dot along whichever way the segment goes furthest, and never more than crossing the grid
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().clamp(1.0, (width + height) as f64) as usize;
+            for step in 0..=steps {
+                let t = step as f64 / steps as f64;
+                let (col, row) = ((x0 + (x1 - x0) * t).floor(), (y0 + (y1 - y0) * t).floor());
+                if (0.0..width as f64).contains(&col) && (0.0..height as f64).contains(&row) {
+                    dot(col as usize, row as usize, dx, dy * aspect);
+                }
+            }
+        }
+    }
+}
+
+//The part of a segment inside the grid, none if it misses the grid entirely
+//Liang-Barsky, so a segment out to 1e15 takes no longer to walk than one across the grid
+fn clip(
+    (x0, y0): (f64, f64),
+    (x1, y1): (f64, f64),
+    (width, height): (f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let (mut enter, mut leave) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, x0), (dx, width - x0), (-dy, y0), (dy, height - y0)] {
+        if p == 0.0 {
+            //Parallel to this edge, and on the wrong side of it
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            leave = leave.min(q / p);
+        }
+    }
+    (enter <= leave).then(|| ((x0 + dx * enter, y0 + dy * enter), (x0 + dx * leave, y0 + dy * leave)))
+}
+
+fn ascii_grid(fish: &Fish, width: usize, height: usize) -> Vec<Vec<char>> {
+    let mut grid = vec![vec![' '; width]; height];
+    rasterize(fish, (width, height), CELL_ASPECT, |col, row, dx, dy| {
+        grid[row][col] = stroke_char(dx, dy);
+    });
+    grid
+}
+
+//Each character is a 2x4 block of dots, which braille numbers 1 2 3 7 down the left and 4 5 6 8 down the right
+fn braille_grid(fish: &Fish, width: usize, height: usize) -> Vec<Vec<char>> {
+    const BITS: [[u32; BRAILLE_ROWS]; BRAILLE_COLS] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    let mut cells = vec![vec![0u32; width]; height];
+    let dots = (width * BRAILLE_COLS, height * BRAILLE_ROWS);
+    //Dots are square-ish once the cell's 2:1 and the braille's 2x4 cancel out
+    let aspect = CELL_ASPECT * BRAILLE_COLS as f64 / BRAILLE_ROWS as f64;
+    rasterize(fish, dots, aspect, |col, row, _, _| {
+        cells[row / BRAILLE_ROWS][col / BRAILLE_COLS] |= BITS[col % BRAILLE_COLS][row % BRAILLE_ROWS];
+    });
+    cells
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|bits| match bits {
+                    0 => ' ',
+                    bits => char::from_u32(BRAILLE_BLANK + bits).unwrap_or(' '),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+//The fish as text, for curl and other places without any pictures
+pub fn render_ascii(fish: &Fish, options: &AsciiOptions) -> String {
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let width = options.width.clamp(MIN_ASCII_WIDTH, MAX_ASCII_WIDTH);
+    let height = ((canvas_height / canvas_width * width as f64 / CELL_ASPECT).round() as usize).max(1);
+    let grid = match options.charset {
+        Charset::Ascii => ascii_grid(fish, width, height),
+        Charset::Unicode => braille_grid(fish, width, height),
+    };
+
+    //Nobody needs the trailing spaces, or the empty rows
+    let rows: Vec<String> = grid
+        .into_iter()
+        .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+        .collect();
+    let first = rows.iter().position(|row| !row.is_empty()).unwrap_or(0);
+    let last = rows.iter().rposition(|row| !row.is_empty()).map_or(0, |last| last + 1);
+    let mut text = rows[first..last.max(first)].join("\n");
+    text.push('\n');
+    text
+}
@@ -0,0 +1,63 @@
+use crate::metrics::host_hash;
+use crate::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Every delivery, for deployments that set XFISH_AUDIT_TABLE, so abuse can be looked into without digging through logs
+//Keyed on the hashed target with the time as the sort key, so one display's history is a single query
+//Rows go after XFISH_AUDIT_RETENTION_DAYS, with DynamoDB's TTL on "expires" doing the going
+pub struct AuditLog {
+    client: Client,
+    table: String,
+    retention: Duration,
+}
+
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+
+//One attempt at sending a fish somewhere
+pub struct AuditEntry<'a> {
+    //The display's address, only ever kept hashed
+    pub target: &'a str,
+    pub source: Option<&'a str>,
+    //"drawn", or the error code for whatever went wrong
+    pub outcome: &'static str,
+    pub fish_id: Option<&'a str>,
+}
+
+impl AuditLog {
+    pub async fn from_env() -> Option<Self> {
+        let table = std::env::var("XFISH_AUDIT_TABLE").ok()?;
+        let days = std::env::var("XFISH_AUDIT_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Some(AuditLog {
+            client: Client::new(&config),
+            table,
+            retention: Duration::from_secs(days * 24 * 60 * 60),
+        })
+    }
+
+    pub async fn record(&self, entry: &AuditEntry<'_>) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("target", AttributeValue::S(host_hash(entry.target)))
+            //Nanoseconds, so two fish to the same display can't land on the same row
+            .item("at", AttributeValue::N(now.as_nanos().to_string()))
+            .item("outcome", AttributeValue::S(entry.outcome.to_string()))
+            .item("expires", AttributeValue::N((now + self.retention).as_secs().to_string()));
+        if let Some(source) = entry.source {
+            request = request.item("source", AttributeValue::S(source.to_string()));
+        }
+        if let Some(fish_id) = entry.fish_id {
+            request = request.item("fish_id", AttributeValue::S(fish_id.to_string()));
+        }
+        request.send().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{Error, FishError};
+use std::path::{Path, PathBuf};
+use x11rb::errors::ConnectError;
+
+//The only auth scheme anyone actually uses
+pub const MIT_MAGIC_COOKIE: &[u8] = b"MIT-MAGIC-COOKIE-1";
+
+//Cookies come as hex, like `xauth list` prints them
+pub fn parse_cookie(cookie: &str) -> Result<Vec<u8>, Error> {
+    let cookie = cookie.trim();
+    if cookie.is_empty() || cookie.len() % 2 != 0 || !cookie.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(FishError::BadParams("auth cookie should be hex, like `xauth list` shows it".to_string()).into());
+    }
+    (0..cookie.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cookie[i..i + 2], 16).map_err(Error::from))
+        .collect()
+}
+
+//Xauthority families that mean this machine, rather than some host over TCP
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+
+//The cookie for a local display out of $XAUTHORITY or ~/.Xauthority, the way Xlib finds it
+//Any local entry for the display will do, we don't bother matching the hostname in it
+pub fn local_cookie(display: u16) -> Option<Vec<u8>> {
+    let path = std::env::var_os("XAUTHORITY")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".Xauthority")))?;
+    let data = std::fs::read(path).ok()?;
+    let wanted = display.to_string();
+    let mut rest = &data[..];
+    while !rest.is_empty() {
+        let family = take(&mut rest, 2)?;
+        let family = u16::from_be_bytes([family[0], family[1]]);
+        let _address = counted(&mut rest)?;
+        let number = counted(&mut rest)?;
+        let name = counted(&mut rest)?;
+        let cookie = counted(&mut rest)?;
+        let local = family == FAMILY_LOCAL || family == FAMILY_WILD;
+        if local && (number.is_empty() || number == wanted.as_bytes()) && name == MIT_MAGIC_COOKIE {
+            return Some(cookie.to_vec());
+        }
+    }
+    None
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if rest.len() < len {
+        return None;
+    }
+    let (taken, after) = rest.split_at(len);
+    *rest = after;
+    Some(taken)
+}
+
+//Everything in an Xauthority file is a big endian length and then that many bytes
+fn counted<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take(rest, 2)?;
+    take(rest, u16::from_be_bytes([len[0], len[1]]) as usize)
+}
+
+//Turn the X server saying no into something a person can do something about
+pub(crate) fn explain(err: ConnectError, had_cookie: bool) -> FishError {
+    match err {
+        ConnectError::SetupFailed(failed) if had_cookie => FishError::AuthRejected(format!(
+            "X server rejected the auth cookie: {}",
+            String::from_utf8_lossy(&failed.reason).trim()
+        )),
+        err => err.into(),
+    }
+}
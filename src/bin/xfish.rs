@@ -0,0 +1,342 @@
+use clap::Parser;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use x11_make_a_fish::find::WindowQuery;
+use x11_make_a_fish::hints::WindowType;
+use x11_make_a_fish::theme::Theme;
+use x11_make_a_fish::{
+    auth, creature, fish_csv, fit_to_canvas, generator, hints, parse_class, parse_window_id, school, style, upload, Cap,
+    DrawOptions, Error, Mode, Palette, Target, XFishSession, DEFAULT_MARGIN, DEFAULT_TITLE, LINE_DELAY, MAX_LINE_DELAY,
+    MAX_MARGIN, MAX_WINDOW_LIFETIME, MAX_WINDOW_SIZE, MIN_WINDOW_SIZE,
+};
+
+//Draw a fish on your own X display, no Lambda required
+#[derive(Parser)]
+#[command(name = "xfish", version, about = "Make a fish on an X11 display")]
+struct Args {
+    /// X11 display to draw on, like :0, /tmp/.X11-unix/X0 or @/tmp/.X11-unix/X0, defaults to $DISPLAY
+    #[arg(short, long)]
+    display: Option<String>,
+
+    /// Milliseconds between lines, more is slower, same as speed over HTTP
+    #[arg(short, long, default_value_t = LINE_DELAY.as_millis() as u64)]
+    speed: u64,
+
+    /// Window position as X,Y, the middle of the monitor if not given
+    #[arg(long, value_parser = parse_position)]
+    position: Option<(i16, i16)>,
+
+    /// Monitor to center the window on, numbered the way RandR lists them, the primary one by default
+    #[arg(long)]
+    monitor: Option<usize>,
+
+    /// Draw on the desktop background instead of in a window
+    #[arg(long)]
+    root: bool,
+
+    /// Draw on an existing window instead of opening one, by ID like xwininfo prints, e.g. 0x1e00003
+    #[arg(long, value_parser = parse_window_id)]
+    window: Option<u32>,
+
+    /// Draw on the first window with this in its title
+    #[arg(long)]
+    target_title: Option<String>,
+
+    /// Draw on the first window with this WM_CLASS instance or class, like "xterm" or "XTerm"
+    #[arg(long)]
+    target_class: Option<String>,
+
+    /// Keep the fish swimming around until the window is closed
+    #[arg(long)]
+    aquarium: bool,
+
+    /// Draw with your own mouse pointer into whatever window has focus, like a paint program
+    #[arg(long)]
+    xtest: bool,
+
+    /// Swim a tiny fish in the system tray instead of opening a window
+    #[arg(long)]
+    tray: bool,
+
+    /// Put the fish on the clipboard too, as SVG and text, while the window is up
+    #[arg(long)]
+    clipboard: bool,
+
+    /// Flash the taskbar once the fish is drawn
+    #[arg(long)]
+    attention: bool,
+
+    /// Draw the whole fish at once
+    #[arg(long)]
+    instant: bool,
+
+    /// See-through window background, needs a compositor
+    #[arg(long)]
+    transparent: bool,
+
+    /// Cut the window to the shape of the fish
+    #[arg(long)]
+    shape: bool,
+
+    /// Draw with plain core X lines instead of anti-aliasing through RENDER
+    #[arg(long)]
+    core: bool,
+
+    /// Line width in pixels, 0 for the thinnest line the server can draw
+    #[arg(long)]
+    line_width: Option<u16>,
+
+    /// Dashed lines, either "true" or a pattern of on/off lengths like "8,4"
+    #[arg(long)]
+    dash: Option<String>,
+
+    /// Line ends: butt, round or square
+    #[arg(long)]
+    cap_style: Option<Cap>,
+
+    /// Fill the fish in instead of drawing its outline
+    #[arg(long)]
+    fill: bool,
+
+    /// Colors to draw with: solid or rainbow
+    #[arg(long)]
+    palette: Option<Palette>,
+
+    /// Window size as WIDTHxHEIGHT
+    #[arg(long, default_value = "520x320", value_parser = parse_size)]
+    size: (u16, u16),
+
+    /// Fish color, either a name like "salmon" or hex like "#fa8072"
+    #[arg(short, long)]
+    color: Option<String>,
+
+    /// Background color, same kinds of color as --color
+    #[arg(long)]
+    bg: Option<String>,
+
+    /// Black background and white fish, same as --theme dark
+    #[arg(long)]
+    dark: bool,
+
+    /// Named look for the fish: light, dark, koi, goldfish or shark, other options still win over it
+    #[arg(long)]
+    theme: Option<Theme>,
+
+    /// Text to write under the fish
+    #[arg(long)]
+    caption: Option<String>,
+
+    /// X core font for the caption, like "-*-helvetica-bold-r-*-*-14-*-*-*-*-*-*-*"
+    #[arg(long)]
+    font: Option<String>,
+
+    /// Window title
+    #[arg(long)]
+    title: Option<String>,
+
+    /// WM_CLASS as "instance,Class", or one name for both
+    #[arg(long)]
+    class: Option<String>,
+
+    /// EWMH window type: normal, splash, dialog or utility
+    #[arg(long, default_value = "normal")]
+    window_type: WindowType,
+
+    /// EWMH states like "above,sticky", from above, sticky, skip_taskbar, fullscreen and demands_attention
+    #[arg(long)]
+    state: Option<String>,
+
+    /// Pop up without the window manager: no borders, exactly where asked, gone after the TTL
+    #[arg(long)]
+    popup: bool,
+
+    /// Fill the primary monitor with fish
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Click for another fish, n for the next theme, q to close
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Draw a fish on every screen of the display, not just the one in the address
+    #[arg(long)]
+    all_screens: bool,
+
+    /// Draw this CSV or JSON file instead of fetching a new fish
+    #[arg(short, long)]
+    file: Option<String>,
+
+    /// Stretch the drawing to fill the window instead of leaving it where it is on the 520x320 canvas
+    #[arg(long)]
+    fit: bool,
+
+    /// How much of the window --fit leaves empty around the drawing on each side, in percent
+    #[arg(long, default_value_t = DEFAULT_MARGIN)]
+    margin: f64,
+
+    /// Seed for the fish generator, the same seed gives the same fish
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// MIT-MAGIC-COOKIE-1 in hex, instead of the one in ~/.Xauthority
+    #[arg(long)]
+    cookie: Option<String>,
+
+    /// What to draw instead of a fish: jellyfish, whale or crab
+    #[arg(long, default_value = "fish")]
+    creature: String,
+
+    /// How many fish to draw
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+
+    /// Close the window after this many seconds, a day at most
+    #[arg(long)]
+    ttl: Option<u64>,
+}
+
+fn parse_size(size: &str) -> Result<(u16, u16), String> {
+    let (width, height) = size.split_once('x').ok_or("size should look like 520x320")?;
+    let width = width.trim().parse().map_err(|_| format!("bad width: {}", width))?;
+    let height = height.trim().parse().map_err(|_| format!("bad height: {}", height))?;
+    Ok((width, height))
+}
+
+fn parse_position(position: &str) -> Result<(i16, i16), String> {
+    let (x, y) = position.split_once(',').ok_or("position should look like 100,200")?;
+    let x = x.trim().parse().map_err(|_| format!("bad x: {}", x))?;
+    let y = y.trim().parse().map_err(|_| format!("bad y: {}", y))?;
+    Ok((x, y))
+}
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+
+    let Some(address) = args.display.or_else(|| std::env::var("DISPLAY").ok()) else {
+        return Err("no --display given and $DISPLAY is not set".into());
+    };
+    if args.speed > MAX_LINE_DELAY.as_millis() as u64 {
+        return Err(format!("speed has to be from 0 to {}", MAX_LINE_DELAY.as_millis()).into());
+    }
+    if !(0.0..=MAX_MARGIN).contains(&args.margin) {
+        return Err(format!("margin has to be from 0 to {}", MAX_MARGIN).into());
+    }
+
+    let creature = creature::find(&args.creature);
+    let mut fish = match &args.file {
+        Some(path) => upload::parse(&std::fs::read(path)?, None)?,
+        None => {
+            let Some(creature) = creature else {
+                let names = creature::names().join(", ");
+                return Err(format!("no {:?} creature, try one of: {}", args.creature, names).into());
+            };
+            let seed = args.seed.unwrap_or_else(generator::random_seed);
+            println!("Fish seed: {}", seed);
+            let fishes = (0..args.count.clamp(1, school::MAX_COUNT) as u64)
+                .map(|i| Ok(fish_csv::parse(&creature.generate_csv_blocking(seed.wrapping_add(i))?)?))
+                .collect::<Result<Vec<_>, Error>>()?;
+            school::arrange(&fishes)
+        }
+    };
+    if args.fit {
+        fit_to_canvas(&mut fish, args.margin);
+    }
+
+    let theme = match args.theme {
+        Some(theme) => theme,
+        None if args.dark => "dark".parse()?,
+        None => Theme::default(),
+    };
+    let options = DrawOptions {
+        target: if args.root { Target::Root } else { Target::Window },
+        mode: if args.xtest {
+            Mode::XTest
+        } else if args.aquarium {
+            Mode::Aquarium
+        } else {
+            Mode::Still
+        },
+        size: (
+            args.size.0.clamp(MIN_WINDOW_SIZE.0, MAX_WINDOW_SIZE.0),
+            args.size.1.clamp(MIN_WINDOW_SIZE.1, MAX_WINDOW_SIZE.1),
+        ),
+        position: args.position,
+        monitor: args.monitor,
+        line_delay: Duration::from_millis(args.speed),
+        instant: args.instant,
+        transparent: args.transparent,
+        shaped: args.shape,
+        popup: args.popup,
+        fullscreen: args.fullscreen,
+        interactive: args.interactive,
+        tray: args.tray,
+        clipboard: args.clipboard,
+        attention: args.attention,
+        window: args.window,
+        find: args
+            .target_title
+            .map(WindowQuery::Title)
+            .or(args.target_class.map(WindowQuery::Class)),
+        theme: theme.name,
+        anti_alias: !args.core,
+        line_width: args.line_width.unwrap_or(theme.line_width).min(style::MAX_LINE_WIDTH),
+        dashes: match args.dash.as_deref() {
+            Some(dash) => style::parse_dashes(dash)?,
+            None => theme.dashes.to_vec(),
+        },
+        cap: args.cap_style.unwrap_or(theme.cap),
+        filled: args.fill || theme.filled,
+        palette: args.palette.unwrap_or(theme.palette),
+        caption: args.caption,
+        font: args.font,
+        title: args.title.unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+        class: args.class.as_deref().map(parse_class).unwrap_or_else(|| DrawOptions::default().class),
+        window_type: args.window_type,
+        states: args.state.as_deref().map(hints::parse_states).transpose()?.unwrap_or_default(),
+        color: args
+            .color
+            .or_else(|| theme.color.filter(|_| args.bg.is_none()).map(str::to_string)),
+        background: args.bg.or_else(|| theme.background.map(str::to_string)),
+        ..DrawOptions::default()
+    };
+    //No Lambda breathing down our neck, so wait as long as the user likes, within reason
+    let deadline = match args.ttl {
+        Some(ttl) => Instant::now() + Duration::from_secs(ttl),
+        None => Instant::now() + MAX_WINDOW_LIFETIME,
+    };
+
+    let cookie = args.cookie.as_deref().map(auth::parse_cookie).transpose()?;
+    let session = match &cookie {
+        Some(cookie) => XFishSession::connect_with_cookie(&address, cookie)?,
+        None => XFishSession::connect(&address)?,
+    };
+    //Clicks get a new one of whatever was asked for, or a fish if the first one came from a file
+    let creature = creature.unwrap_or(creature::CREATURES[0]);
+    let fit = args.fit.then_some(args.margin);
+    let session = session.with_new_fish(Arc::new(move || {
+        let seed = generator::random_seed();
+        println!("Fish seed: {}", seed);
+        let mut fish = fish_csv::parse(&creature.generate_csv_blocking(seed)?)?;
+        if let Some(margin) = fit {
+            fit_to_canvas(&mut fish, margin);
+        }
+        Ok(fish)
+    }));
+    if !args.all_screens {
+        session.draw(&fish, &options, deadline)?;
+        return Ok(());
+    }
+    let results = session.draw_every_screen(&address, cookie.as_deref(), None, &fish, &options, deadline)?;
+    let screens = results.len();
+    let mut drawn = 0;
+    for (screen, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(_) => drawn += 1,
+            Err(err) => eprintln!("No fish on screen {}: {}", screen, err),
+        }
+    }
+    if drawn == 0 {
+        return Err(format!("couldn't draw on any of the {} screens", screens).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::DrawOptions;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::xproto::{Char2b, ConnectionExt, CreateGCAux, Drawable, Font, Gcontext};
+
+//What the Lambda writes under the fish unless told otherwise
+pub const DEFAULT_CAPTION: &str = "make a fish";
+//Every X server is supposed to have this one, so it's the fallback for fonts that don't exist
+pub const FALLBACK_FONT: &str = "fixed";
+//Room between the caption and the edges of the window
+const CAPTION_PADDING: u16 = 4;
+//ImageText8 takes at most 255 characters
+const MAX_CAPTION_LENGTH: usize = 255;
+
+//Some text under the fish, in a core font on the server
+//The GC is the caller's to free
+#[derive(Debug, Clone)]
+pub(crate) struct Caption {
+    pub gc: Gcontext,
+    //Latin-1, which is what core fonts and ImageText8 speak
+    text: Vec<u8>,
+    width: i16,
+    ascent: i16,
+    descent: i16,
+}
+
+//Core fonts and STRING properties only know Latin-1, anything past that becomes a question mark
+pub(crate) fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+//Opening a font that doesn't exist only fails when the server gets around to it, so wait and see
+fn open_font(conn: &impl Connection, name: &str) -> Result<Option<Font>, ReplyOrIdError> {
+    let font = conn.generate_id()?;
+    match conn.open_font(font, name.as_bytes())?.check() {
+        Ok(()) => Ok(Some(font)),
+        Err(err) => {
+            println!("Couldn't open font {:?}: {:?}", name, err);
+            Ok(None)
+        }
+    }
+}
+
+impl Caption {
+    //None when there's no caption, or no font to write it in
+    pub fn new(
+        conn: &impl Connection,
+        drawable: Drawable,
+        options: &DrawOptions,
+        foreground: u32,
+        background: u32,
+    ) -> Result<Option<Self>, ReplyOrIdError> {
+        let Some(caption) = options.caption.as_deref().filter(|caption| !caption.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let mut font = None;
+        if let Some(name) = options.font.as_deref() {
+            font = open_font(conn, name)?;
+        }
+        if font.is_none() {
+            font = open_font(conn, FALLBACK_FONT)?;
+        }
+        let Some(font) = font else {
+            return Ok(None);
+        };
+
+        let mut text = latin1(caption);
+        text.truncate(MAX_CAPTION_LENGTH);
+        let chars: Vec<Char2b> = text.iter().map(|&byte| Char2b { byte1: 0, byte2: byte }).collect();
+        let extents = conn.query_text_extents(font, &chars)?.reply()?;
+
+        //ImageText8 fills behind the text with the GC's background, which should match the window
+        let gc = conn.generate_id()?;
+        conn.create_gc(
+            gc,
+            drawable,
+            &CreateGCAux::default()
+                .foreground(foreground)
+                .background(background)
+                .font(font)
+                .graphics_exposures(0),
+        )?;
+        //The GC keeps the font alive for as long as it needs it
+        conn.close_font(font)?;
+
+        Ok(Some(Caption {
+            gc,
+            text,
+            width: extents.overall_width.clamp(0, i16::MAX as i32) as i16,
+            ascent: extents.font_ascent,
+            descent: extents.font_descent,
+        }))
+    }
+
+    //How much of the bottom of the window the caption takes up
+    pub fn height(&self) -> u16 {
+        (self.ascent + self.descent).max(0) as u16 + CAPTION_PADDING * 2
+    }
+
+    //Centered along the bottom of the drawable
+    pub fn draw(
+        &self,
+        conn: &impl Connection,
+        drawable: Drawable,
+        (width, height): (u16, u16),
+    ) -> Result<(), ReplyOrIdError> {
+        let x = (width as i16).saturating_sub(self.width) / 2;
+        let y = (height as i16).saturating_sub(CAPTION_PADDING as i16 + self.descent);
+        conn.image_text8(drawable, self.gc, x, y, &self.text)?;
+        Ok(())
+    }
+}
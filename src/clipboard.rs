@@ -0,0 +1,85 @@
+use crate::ascii::{self, AsciiOptions};
+use crate::{svg, Atoms, DrawOptions, Fish};
+use x11rb::connection::Connection;
+use x11rb::errors::ConnectionError;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt, EventMask, PropMode, SelectionNotifyEvent, SelectionRequestEvent, Window,
+    SELECTION_NOTIFY_EVENT,
+};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{CURRENT_TIME, NONE};
+
+//The fish, ready to paste, for as long as the window that owns the selections is up
+//Pasting into a drawing program gets the SVG, pasting into a terminal gets the text fish
+//No INCR, a fish is small enough to go in one property
+pub(crate) struct Clipboard {
+    svg: String,
+    text: String,
+}
+
+impl Clipboard {
+    pub fn new(fish: &Fish, options: &DrawOptions) -> Self {
+        Clipboard {
+            svg: svg::render_svg(fish, options),
+            text: ascii::render_ascii(fish, &AsciiOptions::default()),
+        }
+    }
+
+    //Both CLIPBOARD for ctrl+v and PRIMARY for middle click
+    pub fn claim(&self, conn: &impl Connection, atoms: &Atoms, win_id: Window) -> Result<(), ConnectionError> {
+        conn.set_selection_owner(win_id, atoms.CLIPBOARD, CURRENT_TIME)?;
+        conn.set_selection_owner(win_id, AtomEnum::PRIMARY, CURRENT_TIME)?;
+        Ok(())
+    }
+
+    //Put what was asked for on the requestor's property and tell them it's there, or that we can't do that one
+    pub fn answer(
+        &self,
+        conn: &impl Connection,
+        atoms: &Atoms,
+        request: &SelectionRequestEvent,
+    ) -> Result<(), ConnectionError> {
+        //Clients from before ICCCM 2 leave the property out, and mean the target
+        let property = if request.property == NONE {
+            request.target
+        } else {
+            request.property
+        };
+        let target = request.target;
+        let answered = if target == atoms.TARGETS {
+            let targets: [Atom; 5] = [
+                atoms.TARGETS,
+                atoms.IMAGE_SVG,
+                atoms.UTF8_STRING,
+                atoms.TEXT,
+                AtomEnum::STRING.into(),
+            ];
+            conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &targets)?;
+            true
+        } else if target == atoms.IMAGE_SVG {
+            let svg = self.svg.as_bytes();
+            conn.change_property8(PropMode::REPLACE, request.requestor, property, atoms.IMAGE_SVG, svg)?;
+            true
+        } else if target == atoms.UTF8_STRING || target == atoms.TEXT || target == u32::from(AtomEnum::STRING) {
+            //The text fish is all ASCII, so it's already valid as any of them, TEXT just has to say which
+            let type_ = if target == atoms.TEXT { atoms.UTF8_STRING } else { target };
+            let text = self.text.as_bytes();
+            conn.change_property8(PropMode::REPLACE, request.requestor, property, type_, text)?;
+            true
+        } else {
+            false
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target,
+            property: if answered { property } else { NONE },
+        };
+        conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+        conn.flush()
+    }
+}
@@ -0,0 +1,69 @@
+use crate::FishError;
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+//Whether it's 11:11 right now in an IANA time zone like "Europe/London"
+//Morning or evening both count, it's 11:11 either way
+pub fn is_eleven_eleven(tz: &str) -> Result<bool, FishError> {
+    is_eleven_eleven_at(tz, Utc::now())
+}
+
+pub fn is_eleven_eleven_at(tz: &str, now: DateTime<Utc>) -> Result<bool, FishError> {
+    let local = now.with_timezone(&zone(tz)?);
+    Ok(local.hour12().1 == 11 && local.minute() == 11)
+}
+
+//When it's next 11:11 in the time zone, which is now if it's 11:11 already
+pub fn next_eleven_eleven(tz: &str) -> Result<DateTime<Utc>, FishError> {
+    next_eleven_eleven_after(tz, Utc::now())
+}
+
+pub fn next_eleven_eleven_after(tz: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, FishError> {
+    if is_eleven_eleven_at(tz, now)? {
+        return Ok(now);
+    }
+    let zone = zone(tz)?;
+    let today = now.with_timezone(&zone).date_naive();
+    //Today's two, then tomorrow's, and one of those has to be later than now
+    //A clock change can skip an 11:11 entirely, so the next one along gets it
+    [today, today + Days::new(1)]
+        .into_iter()
+        .flat_map(|day| [11, 23].map(|hour| day.and_hms_opt(hour, 11, 0)))
+        .flatten()
+        .filter_map(|time| zone.from_local_datetime(&time).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .find(|&time| time > now)
+        .ok_or_else(|| FishError::BadParams(format!("couldn't find the next 11:11 in {}", tz)))
+}
+
+//A time like 2024-11-11T11:11:00Z, or with an offset instead of the Z
+pub fn parse_time(time: &str) -> Result<DateTime<Utc>, FishError> {
+    DateTime::parse_from_rfc3339(time.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| FishError::BadParams(format!("{:?} isn't a time, try something like 2024-11-11T11:11:00Z", time)))
+}
+
+//What the date is for whoever's getting the fish, in UTC if we don't know where they are
+pub fn today(tz: Option<&str>) -> Result<NaiveDate, FishError> {
+    let now = Utc::now();
+    match tz {
+        Some(tz) => Ok(now.with_timezone(&zone(tz)?).date_naive()),
+        None => Ok(now.date_naive()),
+    }
+}
+
+//The fish of the day's seed, the same for everyone until midnight UTC
+//Mixed up with splitmix64 so one day's fish looks nothing like the day before's
+pub fn daily_seed(now: DateTime<Utc>) -> u64 {
+    let day = now.date_naive().num_days_from_ce() as u64;
+    let mut seed = day.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    seed ^ (seed >> 31)
+}
+
+fn zone(tz: &str) -> Result<Tz, FishError> {
+    tz.trim()
+        .parse()
+        .map_err(|_| FishError::BadParams(format!("{:?} isn't a time zone, try something like Europe/London", tz)))
+}
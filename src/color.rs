@@ -0,0 +1,240 @@
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::xproto::{
+    ChangeGCAux, ConnectionExt, CoordMode, CreateGCAux, Drawable, FillStyle, Gcontext, Pixmap, Point, Rectangle,
+    Screen, VisualClass, VisualType,
+};
+
+//Ordered dither thresholds, a 4x4 Bayer matrix, for greys on screens that only have black and white
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+//How colors turn into pixels on this screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    //TrueColor and DirectColor, every color is there for the asking
+    Exact,
+    //A colormap with only so many entries, shared with every other client on an 8 bit PseudoColor server
+    //Once it's full, whatever's already in it that's closest will have to do
+    Nearest,
+    //1 bit, so black or white, with greys dithered
+    Mono,
+}
+
+pub(crate) fn strategy(screen: &Screen) -> Strategy {
+    if screen.root_depth == 1 {
+        return Strategy::Mono;
+    }
+    match root_visual(screen).map(|visual| visual.class) {
+        Some(VisualClass::TRUE_COLOR) | Some(VisualClass::DIRECT_COLOR) => Strategy::Exact,
+        _ => Strategy::Nearest,
+    }
+}
+
+fn root_visual(screen: &Screen) -> Option<&VisualType> {
+    screen
+        .allowed_depths
+        .iter()
+        .flat_map(|depth| depth.visuals.iter())
+        .find(|visual| visual.visual_id == screen.root_visual)
+}
+
+//How bright a color looks, 0 to 65535
+fn luma((red, green, blue): (u16, u16, u16)) -> f64 {
+    0.299 * red as f64 + 0.587 * green as f64 + 0.114 * blue as f64
+}
+
+//Hex, or the two names everyone knows, for places with no X server to ask about names
+pub(crate) fn parse_basic(color: &str) -> Option<(u16, u16, u16)> {
+    match color.trim().to_ascii_lowercase().as_str() {
+        "black" => Some((0, 0, 0)),
+        "white" => Some((0xffff, 0xffff, 0xffff)),
+        _ => parse_hex(color),
+    }
+}
+
+//Black or white, whichever shows up better on the background
+pub(crate) fn contrasting(rgb: (u16, u16, u16)) -> &'static str {
+    if luma(rgb) > 32767.5 {
+        "black"
+    } else {
+        "white"
+    }
+}
+
+// Turn "#ff8800", "ff8800" or "#f80" into 16 bit per channel RGB, like X wants
+pub(crate) fn parse_hex(color: &str) -> Option<(u16, u16, u16)> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |digits: &str| u16::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        //Each digit is doubled up, so f becomes ff
+        3 => Some((
+            channel(&hex[0..1])? * 0x1111,
+            channel(&hex[1..2])? * 0x1111,
+            channel(&hex[2..3])? * 0x1111,
+        )),
+        //0xff * 0x101 == 0xffff
+        6 => Some((
+            channel(&hex[0..2])? * 0x101,
+            channel(&hex[2..4])? * 0x101,
+            channel(&hex[4..6])? * 0x101,
+        )),
+        _ => None,
+    }
+}
+
+//Get a pixel value for the color from the screen's colormap
+//Anything that goes wrong just means the default, usually black, and a black fish is still a fine fish
+pub(crate) fn alloc_pixel(conn: &impl Connection, screen: &Screen, color: Option<&str>, default: u32) -> u32 {
+    let Some(color) = color else {
+        return default;
+    };
+
+    let pixel = match parse_hex(color) {
+        Some(rgb) => alloc_rgb(conn, screen, rgb),
+        None if strategy(screen) == Strategy::Mono => find_rgb(conn, screen, color).map(|rgb| mono_pixel(screen, rgb)),
+        None => conn
+            .alloc_named_color(screen.default_colormap, color.as_bytes())
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.pixel)
+            .or_else(|| nearest_pixel(conn, screen, find_rgb(conn, screen, color)?)),
+    };
+    pixel.unwrap_or_else(|| {
+        println!("Couldn't allocate color {:?}, using the default", color);
+        default
+    })
+}
+
+//Pixel for an RGB color, exact if the colormap will give us one and the closest it has if not
+pub(crate) fn alloc_rgb(conn: &impl Connection, screen: &Screen, rgb: (u16, u16, u16)) -> Option<u32> {
+    let (red, green, blue) = rgb;
+    let exact = || {
+        let reply = conn
+            .alloc_color(screen.default_colormap, red, green, blue)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(reply.pixel)
+    };
+    match strategy(screen) {
+        Strategy::Exact => exact(),
+        Strategy::Nearest => exact().or_else(|| nearest_pixel(conn, screen, rgb)),
+        Strategy::Mono => Some(mono_pixel(screen, rgb)),
+    }
+}
+
+//The colormap entry that looks most like the color, for when there's no room left to allocate it
+//Read-only entries other clients allocated are fair game, they won't change under us
+fn nearest_pixel(conn: &impl Connection, screen: &Screen, rgb: (u16, u16, u16)) -> Option<u32> {
+    let entries = root_visual(screen)?.colormap_entries as u32;
+    let pixels: Vec<u32> = (0..entries).collect();
+    let reply = conn
+        .query_colors(screen.default_colormap, &pixels)
+        .ok()?
+        .reply()
+        .ok()?;
+    let distance = |(red, green, blue): (u16, u16, u16)| {
+        let channel = |a: u16, b: u16, weight: f64| weight * (a as f64 - b as f64).powi(2);
+        channel(red, rgb.0, 0.299) + channel(green, rgb.1, 0.587) + channel(blue, rgb.2, 0.114)
+    };
+    pixels
+        .iter()
+        .zip(reply.colors)
+        .min_by(|(_, a), (_, b)| {
+            distance((a.red, a.green, a.blue)).total_cmp(&distance((b.red, b.green, b.blue)))
+        })
+        .map(|(&pixel, _)| pixel)
+}
+
+//Black or white, whichever the color is closer to
+fn mono_pixel(screen: &Screen, rgb: (u16, u16, u16)) -> u32 {
+    if luma(rgb) > 32767.5 {
+        screen.white_pixel
+    } else {
+        screen.black_pixel
+    }
+}
+
+//On a 1 bit screen a grey fish comes out as black dots in the right density instead of all black or all white
+//Sets the GC up to stipple and hands back the stipple, which is the caller's to free
+pub(crate) fn dither(
+    conn: &impl Connection,
+    screen: &Screen,
+    gc_id: Gcontext,
+    drawable: Drawable,
+    color: Option<&str>,
+) -> Result<Option<Pixmap>, ReplyOrIdError> {
+    if strategy(screen) != Strategy::Mono {
+        return Ok(None);
+    }
+    let Some(rgb) = color.and_then(|color| find_rgb(conn, screen, color)) else {
+        return Ok(None);
+    };
+    //Out of 16, how many dots stay white
+    let lit = (luma(rgb) / 65535.0 * 16.0).round() as u8;
+    if lit == 0 || lit == 16 {
+        return Ok(None);
+    }
+
+    let stipple = conn.generate_id()?;
+    conn.create_pixmap(1, stipple, drawable, 4, 4)?;
+    let bits_gc = conn.generate_id()?;
+    conn.create_gc(bits_gc, stipple, &CreateGCAux::new().foreground(0))?;
+    conn.poly_fill_rectangle(stipple, bits_gc, &[Rectangle { x: 0, y: 0, width: 4, height: 4 }])?;
+    let dark: Vec<Point> = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .filter(|&(x, y)| BAYER[y][x] >= lit)
+        .map(|(x, y)| Point { x: x as i16, y: y as i16 })
+        .collect();
+    conn.change_gc(bits_gc, &ChangeGCAux::new().foreground(1))?;
+    conn.poly_point(CoordMode::ORIGIN, stipple, bits_gc, &dark)?;
+    conn.free_gc(bits_gc)?;
+
+    conn.change_gc(
+        gc_id,
+        &ChangeGCAux::new()
+            .foreground(screen.black_pixel)
+            .fill_style(FillStyle::STIPPLED)
+            .stipple(stipple),
+    )?;
+    Ok(Some(stipple))
+}
+
+//Hue in degrees, saturation and value from 0 to 1, into 16 bit per channel RGB
+pub(crate) fn hsv(hue: f64, saturation: f64, value: f64) -> (u16, u16, u16) {
+    let chroma = value * saturation;
+    let sector = (hue.rem_euclid(360.0)) / 60.0;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (red, green, blue) = match sector as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let lightness = value - chroma;
+    let channel = |channel: f64| ((channel + lightness) * 65535.0).round() as u16;
+    (channel(red), channel(green), channel(blue))
+}
+
+//The actual RGB of a color, for drawing that doesn't go through a colormap
+//Same fallback as alloc_pixel, black when there's no color or it can't be found
+pub(crate) fn lookup_rgb(conn: &impl Connection, screen: &Screen, color: Option<&str>) -> (u16, u16, u16) {
+    color.and_then(|color| find_rgb(conn, screen, color)).unwrap_or((0, 0, 0))
+}
+
+//Hex straight away, names by asking the server
+pub(crate) fn find_rgb(conn: &impl Connection, screen: &Screen, color: &str) -> Option<(u16, u16, u16)> {
+    parse_hex(color).or_else(|| {
+        let reply = conn
+            .lookup_color(screen.default_colormap, color.as_bytes())
+            .ok()?
+            .reply()
+            .ok()?;
+        Some((reply.exact_red, reply.exact_green, reply.exact_blue))
+    })
+}
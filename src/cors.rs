@@ -0,0 +1,122 @@
+//Which web pages get to call us from JS, for the make-a-fish page and anyone else's front end
+//XFISH_CORS_ORIGINS is comma separated origins like https://miakizz.quest, or * for anywhere, which is the default
+//XFISH_CORS_METHODS and XFISH_CORS_HEADERS replace what preflights get told is allowed
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    //None means anywhere
+    pub origins: Option<Vec<String>>,
+    pub methods: String,
+    pub headers: String,
+}
+
+const DEFAULT_METHODS: &str = "GET, POST, OPTIONS";
+//Everything a request can carry that a browser wouldn't send without asking
+const DEFAULT_HEADERS: &str = "content-type, accept, x-api-key, x-xauth-cookie, idempotency-key";
+//Headers pages get to read off our responses, browsers hide anything else
+pub const EXPOSE_HEADERS: &str = "retry-after, content-location, idempotent-replayed";
+//How long a browser can skip the preflight for, in seconds
+pub const MAX_AGE: u32 = 600;
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        CorsPolicy {
+            origins: None,
+            methods: DEFAULT_METHODS.to_string(),
+            headers: DEFAULT_HEADERS.to_string(),
+        }
+    }
+}
+
+//Origins don't end in a slash, but people copying them out of the address bar might leave one on
+fn parse_origins(origins: &str) -> Option<Vec<String>> {
+    if origins.trim() == "*" {
+        return None;
+    }
+    Some(
+        origins
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect(),
+    )
+}
+
+impl CorsPolicy {
+    pub fn from_env() -> Self {
+        CorsPolicy {
+            origins: std::env::var("XFISH_CORS_ORIGINS").ok().and_then(|origins| parse_origins(&origins)),
+            methods: std::env::var("XFISH_CORS_METHODS").unwrap_or_else(|_| DEFAULT_METHODS.to_string()),
+            headers: std::env::var("XFISH_CORS_HEADERS").unwrap_or_else(|_| DEFAULT_HEADERS.to_string()),
+        }
+    }
+
+    //What goes in Access-Control-Allow-Origin for a request from this origin, none if it's not allowed
+    pub fn allow_origin(&self, origin: Option<&str>) -> Option<String> {
+        match (&self.origins, origin) {
+            (None, _) => Some("*".to_string()),
+            (Some(origins), Some(origin)) if origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin)) => {
+                Some(origin.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    //Responses that depend on the Origin header have to say so, or caches mix them up
+    pub fn varies(&self) -> bool {
+        self.origins.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &str) -> CorsPolicy {
+        CorsPolicy {
+            origins: parse_origins(origins),
+            ..CorsPolicy::default()
+        }
+    }
+
+    #[test]
+    fn anywhere_by_default() {
+        for policy in [CorsPolicy::default(), policy("*"), policy(" * ")] {
+            assert_eq!(policy.allow_origin(Some("https://evil.example")).as_deref(), Some("*"));
+            assert_eq!(policy.allow_origin(None).as_deref(), Some("*"));
+            assert!(!policy.varies());
+        }
+    }
+
+    #[test]
+    fn allows_listed_origins() {
+        let policy = policy("https://miakizz.quest/, http://localhost:8080");
+        assert_eq!(policy.origins.as_deref().unwrap(), ["https://miakizz.quest", "http://localhost:8080"]);
+        let allowed = policy.allow_origin(Some("https://miakizz.quest"));
+        assert_eq!(allowed.as_deref(), Some("https://miakizz.quest"));
+        let allowed = policy.allow_origin(Some("HTTP://LOCALHOST:8080"));
+        assert_eq!(allowed.as_deref(), Some("HTTP://LOCALHOST:8080"));
+        assert!(policy.varies());
+    }
+
+    #[test]
+    fn rejects_other_origins() {
+        //An empty list lets nobody in rather than everybody
+        assert_eq!(policy("").allow_origin(Some("https://miakizz.quest")), None);
+
+        let policy = policy("https://miakizz.quest");
+        let others = [
+            "https://evil.example",
+            "http://miakizz.quest",
+            "https://miakizz.quest.evil.example",
+            "https://evil.miakizz.quest",
+            "https://miakizz.quest:8443",
+            "null",
+            "",
+            "*",
+        ];
+        for origin in others {
+            assert_eq!(policy.allow_origin(Some(origin)), None, "{:?}", origin);
+        }
+        assert_eq!(policy.allow_origin(None), None);
+    }
+}
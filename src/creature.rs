@@ -0,0 +1,236 @@
+use crate::{fish_csv, generator, Error, Fish};
+use std::f64::consts::PI;
+use std::future::{ready, Future};
+use std::pin::Pin;
+
+pub type CsvFuture<'a> = Pin<Box<dyn Future<Output = Result<String, Error>> + Send + 'a>>;
+
+//Anything that can turn a seed into a drawing, in the same CSV the fish generator speaks
+//Same seed has to give the same creature, people come back for their creatures by seed
+pub trait CreatureGenerator: Send + Sync {
+    //What the `creature` param calls it
+    fn name(&self) -> &'static str;
+    fn generate_csv(&self, seed: u64) -> CsvFuture<'_>;
+    //Same as generate_csv, for front ends without an async runtime
+    fn generate_csv_blocking(&self, seed: u64) -> Result<String, Error>;
+}
+
+//Every creature we know how to make, the first one is what you get if you don't ask
+pub static CREATURES: &[&dyn CreatureGenerator] = &[&FishGenerator, &Jellyfish, &Whale, &Crab];
+
+pub fn find(name: &str) -> Option<&'static dyn CreatureGenerator> {
+    CREATURES
+        .iter()
+        .copied()
+        .find(|creature| creature.name().eq_ignore_ascii_case(name.trim()))
+}
+
+pub fn names() -> Vec<&'static str> {
+    CREATURES.iter().map(|creature| creature.name()).collect()
+}
+
+//The original, from the remote fish generator
+pub struct FishGenerator;
+
+impl CreatureGenerator for FishGenerator {
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    fn generate_csv(&self, seed: u64) -> CsvFuture<'_> {
+        Box::pin(generator::generate_csv(seed))
+    }
+
+    fn generate_csv_blocking(&self, seed: u64) -> Result<String, Error> {
+        generator::generate_csv_blocking(seed)
+    }
+}
+
+//The rest are drawn right here, nobody has built a jellyfish generator yet
+macro_rules! drawn_creature {
+    ($creature:ident, $name:literal, $draw:ident) => {
+        pub struct $creature;
+
+        impl CreatureGenerator for $creature {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn generate_csv(&self, seed: u64) -> CsvFuture<'_> {
+                Box::pin(ready(self.generate_csv_blocking(seed)))
+            }
+
+            fn generate_csv_blocking(&self, seed: u64) -> Result<String, Error> {
+                Ok(fish_csv::write(&$draw(&mut Dice::new(seed))))
+            }
+        }
+    };
+}
+
+drawn_creature!(Jellyfish, "jellyfish", draw_jellyfish);
+drawn_creature!(Whale, "whale", draw_whale);
+drawn_creature!(Crab, "crab", draw_crab);
+
+//SplitMix64, small and good enough to pick tentacle counts with
+struct Dice(u64);
+
+impl Dice {
+    fn new(seed: u64) -> Self {
+        Dice(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn range(&mut self, low: f64, high: f64) -> f64 {
+        low + (self.next() >> 11) as f64 / (1u64 << 53) as f64 * (high - low)
+    }
+
+    fn count(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next() % (high - low + 1) as u64) as usize
+    }
+}
+
+//Part of an ellipse, angles in radians going clockwise on screen since y points down
+fn arc(center: (f64, f64), radius: (f64, f64), from: f64, to: f64, steps: usize) -> Vec<(f64, f64)> {
+    (0..=steps)
+        .map(|step| {
+            let angle = from + (to - from) * step as f64 / steps as f64;
+            (center.0 + radius.0 * angle.cos(), center.1 + radius.1 * angle.sin())
+        })
+        .collect()
+}
+
+fn draw_jellyfish(dice: &mut Dice) -> Fish {
+    let mut jellyfish = Fish::new();
+    let bell = (dice.range(80.0, 120.0), dice.range(60.0, 90.0));
+    let center = (260.0, 30.0 + bell.1);
+
+    //Dome over the top, left to right
+    jellyfish.push(arc(center, bell, PI, 2.0 * PI, 32));
+
+    //Frilly edge along the bottom, right to left
+    let scallops = dice.count(5, 8);
+    let scallop_width = bell.0 * 2.0 / scallops as f64;
+    let mut edge = Vec::new();
+    for scallop in 0..scallops {
+        let middle = center.0 + bell.0 - scallop_width * (scallop as f64 + 0.5);
+        edge.extend(arc((middle, center.1), (scallop_width / 2.0, 8.0), 0.0, PI, 8));
+    }
+    jellyfish.push(edge);
+
+    //Wiggly tentacles hanging down
+    let tentacles = dice.count(4, 7);
+    let top = center.1 + 8.0;
+    for tentacle in 0..tentacles {
+        let x = center.0 - bell.0 * 0.7 + bell.0 * 1.4 * tentacle as f64 / (tentacles - 1) as f64;
+        let length = dice.range(0.6, 1.0) * (310.0 - top);
+        let phase = dice.range(0.0, 2.0 * PI);
+        let wiggle = dice.range(6.0, 12.0);
+        let steps = (length / 6.0) as usize;
+        jellyfish.push(
+            (0..=steps)
+                .map(|step| {
+                    let y = length * step as f64 / steps as f64;
+                    (x + wiggle * (phase + y / 20.0).sin(), top + y)
+                })
+                .collect(),
+        );
+    }
+    jellyfish
+}
+
+fn draw_whale(dice: &mut Dice) -> Fish {
+    let mut whale = Fish::new();
+    let body = (dice.range(150.0, 180.0), dice.range(60.0, 80.0));
+    let center = (240.0, 180.0);
+    let tail = center.0 + body.0;
+
+    //Back, then a flatter belly
+    whale.push(arc(center, body, PI, 2.0 * PI, 40));
+    whale.push(arc(center, (body.0, body.1 * 0.8), 0.0, PI, 40));
+    //Flukes
+    let flick = dice.range(-10.0, 10.0);
+    whale.push(vec![
+        (tail - 5.0, center.1 - 10.0),
+        (tail + 45.0, center.1 - 35.0 + flick),
+        (tail + 75.0, center.1 - 60.0 + flick),
+        (tail + 60.0, center.1 - 25.0 + flick),
+        (tail + 80.0, center.1 + 5.0 + flick),
+        (tail + 45.0, center.1 - 15.0 + flick),
+        (tail - 5.0, center.1 + 10.0),
+    ]);
+    //Eye, mouth and flipper
+    whale.push(arc((center.0 - body.0 * 0.6, center.1), (5.0, 5.0), 0.0, 2.0 * PI, 12));
+    whale.push(vec![
+        (center.0 - body.0 + 10.0, center.1 + 15.0),
+        (center.0 - body.0 * 0.7, center.1 + 24.0),
+        (center.0 - body.0 * 0.4, center.1 + 25.0),
+    ]);
+    whale.push(vec![
+        (center.0 - body.0 * 0.2, center.1 + body.1 * 0.5),
+        (center.0 - body.0 * 0.05, center.1 + body.1),
+        (center.0 + body.0 * 0.1, center.1 + body.1 * 0.55),
+    ]);
+
+    //Water spraying up out of the blowhole, fanning out
+    let blowhole = (center.0 - body.0 * 0.3, center.1 - body.1 * 0.954);
+    let jets = dice.count(3, 5);
+    let height = dice.range(50.0, blowhole.1 - 20.0);
+    for jet in 0..jets {
+        let spread = (jet as f64 / (jets - 1) as f64 - 0.5) * dice.range(50.0, 80.0);
+        whale.push(
+            (0..=14)
+                .map(|step| {
+                    let t = step as f64 / 10.0;
+                    (blowhole.0 + spread * t, blowhole.1 - height * (2.0 * t - t * t))
+                })
+                .collect(),
+        );
+    }
+    whale
+}
+
+fn draw_crab(dice: &mut Dice) -> Fish {
+    let mut crab = Fish::new();
+    let shell = (dice.range(80.0, 100.0), dice.range(45.0, 55.0));
+    let center = (260.0, 200.0);
+
+    crab.push(arc(center, shell, 0.0, 2.0 * PI, 48));
+    //Smile
+    crab.push(arc((center.0, center.1 - 5.0), (15.0, 8.0), 0.2, PI - 0.2, 8));
+
+    for side in [-1.0, 1.0] {
+        //Eyes on stalks
+        let eye = (center.0 + side * 25.0, center.1 - shell.1 - 30.0);
+        crab.push(vec![(center.0 + side * 20.0, center.1 - shell.1 + 3.0), (eye.0, eye.1 + 6.0)]);
+        crab.push(arc(eye, (6.0, 6.0), 0.0, 2.0 * PI, 12));
+
+        //Three legs each side, bent at the knee
+        let splay = dice.range(0.0, 15.0);
+        for leg in 0..3 {
+            let leg = leg as f64;
+            crab.push(vec![
+                (center.0 + side * shell.0 * 0.85, center.1 + shell.1 * (0.1 + 0.25 * leg)),
+                (center.0 + side * (shell.0 + 40.0), center.1 - 10.0 + 20.0 * leg - splay),
+                (center.0 + side * (shell.0 + 60.0), center.1 + shell.1 + 10.0 + 10.0 * leg),
+            ]);
+        }
+
+        //Arm up to a claw, which is a circle with a bite taken out
+        let claw = (center.0 + side * (shell.0 + 35.0), center.1 - shell.1 - 60.0);
+        crab.push(vec![
+            (center.0 + side * shell.0 * 0.6, center.1 - shell.1 * 0.6),
+            (center.0 + side * (shell.0 + 20.0), center.1 - shell.1 - 20.0),
+            (claw.0 - side * 8.0, claw.1 + 20.0),
+        ]);
+        let opening = -PI / 2.0 + side * dice.range(0.2, 0.6);
+        crab.push(arc(claw, (22.0, 22.0), opening + 0.4, opening + 2.0 * PI - 0.4, 24));
+    }
+    crab
+}
@@ -0,0 +1,463 @@
+use crate::auth::{explain, MIT_MAGIC_COOKIE};
+use crate::proxy::Proxy;
+#[cfg(feature = "ssh")]
+use crate::ssh::SshLogin;
+use crate::address::LocalSocket;
+use crate::{metrics, DisplayAddress, FishError};
+use std::collections::VecDeque;
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use x11rb::errors::ConnectError;
+use x11rb::rust_connection::{DefaultStream, RustConnection};
+use x11rb_protocol::parse_display::ConnectAddress;
+
+//How long to wait for a display to answer if nobody says otherwise
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+//Connect timeout from XFISH_CONNECT_TIMEOUT_MS, which is also the most a request can ask for
+pub fn connect_timeout_from_env() -> Duration {
+    std::env::var("XFISH_CONNECT_TIMEOUT_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT)
+}
+
+//How long an address gets to connect before the next one joins the race, RFC 8305 says 250ms
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+//How hard to try a display that doesn't answer the first time, which flaky links often don't
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    //Including the first one, so 1 means no retrying
+    pub attempts: u32,
+    //Wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    //How much of each wait is random, from 0 to 1, so a lot of requests failing together don't retry together
+    pub jitter: f64,
+    //When the request needs to be connected by, tries and waits all fit before it or don't happen
+    pub deadline: Option<Instant>,
+}
+
+//A couple of retries is plenty for a link that drops the odd SYN, more just keeps the request waiting
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 3;
+pub const MAX_CONNECT_ATTEMPTS: u32 = 10;
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: DEFAULT_CONNECT_ATTEMPTS,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(2),
+            jitter: 0.5,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    //Straight to the error the first time, for callers that do their own retrying
+    pub const ONCE: RetryPolicy = RetryPolicy {
+        attempts: 1,
+        backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        jitter: 0.0,
+        deadline: None,
+    };
+
+    //XFISH_CONNECT_ATTEMPTS, XFISH_CONNECT_BACKOFF_MS, XFISH_CONNECT_MAX_BACKOFF_MS and XFISH_CONNECT_JITTER,
+    //whichever are set. The attempts are also the most a request can ask for
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        let millis = |name: &str| var(name).and_then(|ms| ms.parse::<u64>().ok()).map(Duration::from_millis);
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            attempts: var("XFISH_CONNECT_ATTEMPTS")
+                .and_then(|attempts| attempts.parse::<u32>().ok())
+                .map_or(default.attempts, |attempts| attempts.clamp(1, MAX_CONNECT_ATTEMPTS)),
+            backoff: millis("XFISH_CONNECT_BACKOFF_MS").unwrap_or(default.backoff),
+            max_backoff: millis("XFISH_CONNECT_MAX_BACKOFF_MS").unwrap_or(default.max_backoff),
+            jitter: var("XFISH_CONNECT_JITTER")
+                .and_then(|jitter| jitter.parse::<f64>().ok())
+                .filter(|jitter| jitter.is_finite())
+                .map_or(default.jitter, |jitter| jitter.clamp(0.0, 1.0)),
+            deadline: None,
+        }
+    }
+
+    //Same waits, but no more tries than asked for
+    pub fn at_most(self, attempts: u32) -> Self {
+        RetryPolicy {
+            attempts: attempts.clamp(1, self.attempts),
+            ..self
+        }
+    }
+
+    //Same tries, but all of them done by the deadline
+    pub fn until(self, deadline: Instant) -> Self {
+        RetryPolicy {
+            deadline: Some(self.deadline.map_or(deadline, |earlier| earlier.min(deadline))),
+            ..self
+        }
+    }
+
+    //How long to wait after the given failed attempt, counting from 1
+    fn delay(&self, attempt: u32) -> Duration {
+        let doubled = self.backoff.saturating_mul(1 << (attempt - 1).min(16));
+        let delay = doubled.min(self.max_backoff);
+        //Anything that changes between calls will do, this isn't cryptography
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.subsec_nanos())
+            .unwrap_or_default();
+        let random = nanos as f64 / 1e9;
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+//Whether trying again could possibly help. A refused or timed out connection might be the link,
+//a name that doesn't resolve or a cookie that's wrong will be just as wrong next time
+pub fn retryable(err: &FishError) -> bool {
+    matches!(err, FishError::ConnectRefused(_) | FishError::Timeout(_))
+}
+
+//How to get to the display
+#[derive(Debug, Clone, Default)]
+pub enum Route {
+    #[default]
+    Direct,
+    //Through a proxy or bastion
+    Proxy(Proxy),
+    //Logged in to the display's host, for X servers that don't listen on TCP
+    #[cfg(feature = "ssh")]
+    Ssh(SshLogin),
+}
+
+impl Route {
+    pub fn proxy(&self) -> Option<&Proxy> {
+        match self {
+            Route::Proxy(proxy) => Some(proxy),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    pub fn of(addr: &SocketAddr) -> Self {
+        if addr.is_ipv6() {
+            Family::Ipv6
+        } else {
+            Family::Ipv4
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Family::Ipv4 => "ipv4",
+            Family::Ipv6 => "ipv6",
+        }
+    }
+}
+
+//A display we got through to, and how
+pub(crate) struct Dialed {
+    pub conn: RustConnection,
+    pub screen: usize,
+    //Which family won for TCP, none for local sockets and proxies
+    pub family: Option<Family>,
+    pub attempts: u32,
+}
+
+impl Dialed {
+    pub fn new(conn: RustConnection, screen: usize, family: Option<Family>) -> Self {
+        Dialed {
+            conn,
+            screen,
+            family,
+            attempts: 1,
+        }
+    }
+}
+
+//dial, again and again if the policy says so, with how many tries it took
+//Every try goes to the same vetted addresses, if there are any, DNS doesn't get another say
+pub(crate) fn dial_with_retries(
+    display: &DisplayAddress,
+    cookie: Option<&[u8]>,
+    timeout: Option<Duration>,
+    policy: &RetryPolicy,
+    route: &Route,
+    vetted: Option<&[SocketAddr]>,
+) -> Result<Dialed, FishError> {
+    let mut attempt = 1;
+    let gave_up = |err: FishError, attempt: u32| {
+        if attempt > 1 {
+            err.during(&format!("gave up after {} tries", attempt))
+        } else {
+            err
+        }
+    };
+    loop {
+        //Each try gets the connect timeout or what's left before the deadline, whichever is less
+        let timeout = match policy.deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(gave_up(timed_out(&display.to_string()), attempt - 1));
+                }
+                Some(timeout.map_or(left, |timeout| timeout.min(left)))
+            }
+            None => timeout,
+        };
+        let dialed = match route {
+            #[cfg(feature = "ssh")]
+            Route::Ssh(login) => crate::ssh::dial(display, login, cookie, timeout, vetted),
+            route => dial(display, cookie, timeout, route.proxy(), vetted),
+        };
+        match dialed {
+            Ok(dialed) => {
+                return Ok(Dialed {
+                    attempts: attempt,
+                    ..dialed
+                })
+            }
+            Err(err) if attempt < policy.attempts && retryable(&err) => {
+                let delay = policy.delay(attempt);
+                //No point waiting if there won't be time to try after
+                if policy.deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                    return Err(gave_up(err, attempt));
+                }
+                let address = metrics::host_hash(&display.to_string());
+                tracing::info!(%address, attempt, ?delay, "connect failed, trying again: {}", err);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(err) => return Err(gave_up(err, attempt)),
+        }
+    }
+}
+
+pub(crate) fn handshake(
+    stream: DefaultStream,
+    screen: usize,
+    cookie: Option<Vec<u8>>,
+) -> Result<RustConnection, ConnectError> {
+    match cookie {
+        Some(cookie) => {
+            RustConnection::connect_to_stream_with_auth_info(stream, screen, MIT_MAGIC_COOKIE.to_vec(), cookie)
+        }
+        None => RustConnection::connect_to_stream(stream, screen),
+    }
+}
+
+fn timed_out(address: &str) -> FishError {
+    FishError::Timeout(format!("{} didn't answer in time", address))
+}
+
+//Open the socket ourselves so a firewalled host can't keep us waiting for minutes,
+//then hand it to x11rb. With a timeout, it covers the whole connect including X setup
+//Through a proxy, the socket is one end of the tunnel instead
+//Vetted addresses are what AddressPolicy already looked up and checked, for the display or the proxy,
+//and they're used instead of looking the name up again, which could answer differently the second time
+pub(crate) fn dial(
+    display: &DisplayAddress,
+    cookie: Option<&[u8]>,
+    timeout: Option<Duration>,
+    proxy: Option<&Proxy>,
+    vetted: Option<&[SocketAddr]>,
+) -> Result<Dialed, FishError> {
+    let address = &display.to_string();
+    if let (true, Some(proxy)) = (display.is_local(), proxy) {
+        return Err(FishError::BadParams(format!("{} can't reach a local display, it needs a host", proxy)));
+    }
+    let screen = display.screen as usize;
+    let _span = tracing::info_span!("connect", address = %metrics::host_hash(address), screen).entered();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let remaining = || -> Result<Option<Duration>, FishError> {
+        match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Ok(Some(left)),
+                _ => Err(timed_out(address)),
+            },
+            None => Ok(None),
+        }
+    };
+
+    //Sockets named outright only go the one place
+    if let Some(socket) = &display.socket {
+        let stream = connect_socket(socket)
+            .map_err(|err| FishError::ConnectRefused(format!("couldn't connect to {}: {}", address, err)))?;
+        let cookie = cookie.map(|cookie| cookie.to_vec());
+        let had_cookie = cookie.is_some();
+        let conn = handshake(stream, screen, cookie).map_err(|err| explain(err, had_cookie))?;
+        return Ok(Dialed::new(conn, screen, None));
+    }
+
+    let mut last_err = None;
+    //Local displays are a socket, or failing that, TCP on the same machine like Xlib does it
+    let connect_addresses = if display.is_local() {
+        vec![
+            ConnectAddress::Socket(display.socket_path()),
+            ConnectAddress::Hostname("localhost", display.tcp_port()),
+        ]
+    } else {
+        vec![ConnectAddress::Hostname(&display.host, display.tcp_port())]
+    };
+    for connect_address in connect_addresses {
+        let (stream, handle, family) = match (&connect_address, proxy) {
+            //The proxy does the connecting, and for socks5h the looking up too
+            (ConnectAddress::Hostname(host, port), Some(proxy)) => {
+                let stream = proxy.tunnel(host, *port, remaining()?, vetted)?;
+                let handle = stream.try_clone()?;
+                let (stream, _) = DefaultStream::from_tcp_stream(stream)?;
+                (stream, Some(handle), None)
+            }
+            (ConnectAddress::Hostname(host, port), None) => {
+                let addrs: Vec<SocketAddr> = match vetted {
+                    Some(vetted) => vetted.iter().map(|addr| SocketAddr::new(addr.ip(), *port)).collect(),
+                    None => (*host, *port)
+                        .to_socket_addrs()
+                        .map_err(|err| FishError::DnsFailure(format!("couldn't look up {}: {}", host, err)))?
+                        .collect(),
+                };
+                remaining()?;
+                let stream = match happy_eyeballs(addrs, deadline) {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        last_err = Some(err);
+                        continue;
+                    }
+                };
+                let family = stream.peer_addr().ok().map(|addr| Family::of(&addr));
+                //Kept so a handshake that takes too long can be cut off
+                let handle = stream.try_clone()?;
+                let (stream, _) = DefaultStream::from_tcp_stream(stream)?;
+                (stream, Some(handle), family)
+            }
+            //Local sockets answer straight away or not at all
+            _ => match DefaultStream::connect(&connect_address) {
+                Ok((stream, _)) => (stream, None, None),
+                Err(err) => {
+                    last_err = Some(err);
+                    continue;
+                }
+            },
+        };
+
+        let cookie = cookie.map(|cookie| cookie.to_vec());
+        let had_cookie = cookie.is_some();
+        let conn = match (remaining()?, handle) {
+            //The X setup handshake gets whatever time is left, shutting the socket down unsticks it if it runs out
+            (Some(left), Some(handle)) => {
+                let (sender, receiver) = mpsc::channel();
+                thread::spawn(move || sender.send(handshake(stream, screen, cookie)));
+                match receiver.recv_timeout(left) {
+                    Ok(setup) => setup,
+                    Err(_) => {
+                        let _ = handle.shutdown(Shutdown::Both);
+                        return Err(timed_out(address));
+                    }
+                }
+            }
+            _ => handshake(stream, screen, cookie),
+        }
+        .map_err(|err| explain(err, had_cookie))?;
+        return Ok(Dialed::new(conn, screen, family));
+    }
+
+    Err(match last_err {
+        Some(err) if err.kind() == std::io::ErrorKind::TimedOut => timed_out(address),
+        Some(err) => err.into(),
+        None => FishError::BadParams(format!("don't know how to connect to {}", address)),
+    })
+}
+
+fn connect_socket(socket: &LocalSocket) -> std::io::Result<DefaultStream> {
+    let stream = match socket {
+        LocalSocket::Path(path) => UnixStream::connect(path)?,
+        #[cfg(target_os = "linux")]
+        LocalSocket::Abstract(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            UnixStream::connect_addr(&std::os::unix::net::SocketAddr::from_abstract_name(name)?)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        LocalSocket::Abstract(_) => {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "abstract sockets are a Linux thing"));
+        }
+    };
+    Ok(DefaultStream::from_unix_stream(stream)?.0)
+}
+
+//Connect to whichever address answers first, one family then the other, each getting a head start on the next
+//so a host with a blackholed IPv6 (or IPv4) still gets its fish without waiting out a timeout first
+pub(crate) fn happy_eyeballs(addrs: Vec<SocketAddr>, deadline: Option<Instant>) -> std::io::Result<TcpStream> {
+    //Whichever family the resolver likes best goes first, RFC 8305 style
+    let first_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) =
+        addrs.into_iter().partition(|addr| addr.is_ipv6() == first_v6);
+    let mut addrs = VecDeque::new();
+    while !first.is_empty() || !second.is_empty() {
+        addrs.extend(first.pop_front());
+        addrs.extend(second.pop_front());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut racing = 0;
+    let mut last_err = None;
+    let mut next_start = Instant::now();
+    loop {
+        let now = Instant::now();
+        let left = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(now) {
+                Some(left) if !left.is_zero() => Some(left),
+                _ => return Err(std::io::ErrorKind::TimedOut.into()),
+            },
+            None => None,
+        };
+        if now >= next_start {
+            if let Some(addr) = addrs.pop_front() {
+                let sender = sender.clone();
+                //Losers that connect after the race is over get dropped when the send fails
+                thread::spawn(move || {
+                    let attempt = match left {
+                        Some(left) => TcpStream::connect_timeout(&addr, left),
+                        None => TcpStream::connect(addr),
+                    };
+                    let _ = sender.send(attempt);
+                });
+                racing += 1;
+                next_start = now + CONNECTION_ATTEMPT_DELAY;
+            }
+        }
+        if racing == 0 {
+            return Err(last_err.unwrap_or_else(|| std::io::ErrorKind::AddrNotAvailable.into()));
+        }
+
+        //Until the next address is due, or for as long as it takes if there are none left
+        let wait = match (addrs.is_empty(), left) {
+            (false, left) => next_start.saturating_duration_since(now).min(left.unwrap_or(Duration::MAX)),
+            (true, Some(left)) => left,
+            (true, None) => Duration::MAX,
+        };
+        match receiver.recv_timeout(wait) {
+            Ok(Ok(stream)) => return Ok(stream),
+            //One down, so the next one doesn't have to wait its turn
+            Ok(Err(err)) => {
+                racing -= 1;
+                last_err = Some(err);
+                next_start = Instant::now();
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{fish_csv, Error, Fish, FishError};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+//Named drawings like the comeback, which can be swapped out without a redeploy
+//XFISH_DRAWINGS says where they live, s3://bucket/prefix or a local directory, each one <name>.csv
+//Without it, or when one can't be had from there, the ones built in still do
+pub struct Drawings {
+    source: Source,
+    //Kept for XFISH_DRAWINGS_TTL_SECS, so an update shows up without every fish fetching it
+    cache: Mutex<HashMap<String, (Instant, Fish)>>,
+    ttl: Duration,
+}
+
+enum Source {
+    BuiltIn,
+    Directory(PathBuf),
+    #[cfg(feature = "s3")]
+    S3 {
+        client: aws_sdk_s3::Client,
+        bucket: String,
+        prefix: String,
+    },
+}
+
+//What people get when it isn't 11:11
+pub const COMEBACK: &str = "comeback";
+
+const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+fn built_in(name: &str) -> Option<&'static str> {
+    match name {
+        COMEBACK => Some(include_str!("../comeback.csv")),
+        _ => None,
+    }
+}
+
+impl Drawings {
+    pub fn built_in() -> Self {
+        Drawings::new(Source::BuiltIn)
+    }
+
+    fn new(source: Source) -> Self {
+        let ttl = std::env::var("XFISH_DRAWINGS_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        Drawings {
+            source,
+            cache: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub async fn from_env() -> Self {
+        let Ok(location) = std::env::var("XFISH_DRAWINGS") else {
+            return Drawings::built_in();
+        };
+        if let Some(bucket_and_prefix) = location.strip_prefix("s3://") {
+            #[cfg(feature = "s3")]
+            {
+                let (bucket, prefix) = bucket_and_prefix.split_once('/').unwrap_or((bucket_and_prefix, ""));
+                let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+                return Drawings::new(Source::S3 {
+                    client: aws_sdk_s3::Client::new(&config),
+                    bucket: bucket.to_string(),
+                    prefix: prefix.trim_end_matches('/').to_string(),
+                });
+            }
+            #[cfg(not(feature = "s3"))]
+            {
+                println!("Drawings in S3 ({}) need the s3 feature, sticking to the built in ones", bucket_and_prefix);
+                return Drawings::built_in();
+            }
+        }
+        Drawings::new(Source::Directory(PathBuf::from(location)))
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Fish, Error> {
+        //Names end up in paths and S3 keys
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(FishError::BadParams(format!("{:?} isn't a drawing name", name)).into());
+        }
+        if let Ok(cache) = self.cache.lock() {
+            if let Some((fetched, fish)) = cache.get(name) {
+                if fetched.elapsed() < self.ttl {
+                    return Ok(fish.clone());
+                }
+            }
+        }
+        let fish = match self.fetch(name).await {
+            Ok(Some(csv)) => fish_csv::parse(&csv)?,
+            //Only the ones we ship with have something to fall back on
+            result => {
+                if let Err(err) = result {
+                    println!("Couldn't get the {} drawing: {}", name, err);
+                }
+                let csv = built_in(name)
+                    .ok_or_else(|| FishError::NotFound(format!("there's no drawing called {}", name)))?;
+                fish_csv::parse(csv)?
+            }
+        };
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(name.to_string(), (Instant::now(), fish.clone()));
+        }
+        Ok(fish)
+    }
+
+    //None if the source doesn't have it
+    async fn fetch(&self, name: &str) -> Result<Option<String>, Error> {
+        let file = format!("{}.csv", name);
+        match &self.source {
+            Source::BuiltIn => Ok(None),
+            Source::Directory(directory) => match std::fs::read_to_string(directory.join(file)) {
+                Ok(csv) => Ok(Some(csv)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+            #[cfg(feature = "s3")]
+            Source::S3 { client, bucket, prefix } => {
+                let key = if prefix.is_empty() {
+                    file
+                } else {
+                    format!("{}/{}", prefix, file)
+                };
+                let result = client.get_object().bucket(bucket).key(key).send().await;
+                let object = match result {
+                    Ok(object) => object,
+                    Err(err) if err.as_service_error().is_some_and(|err| err.is_no_such_key()) => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+                let bytes = object.body.collect().await?.into_bytes();
+                Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::fish_csv::ParseError;
+use crate::Error;
+use std::fmt;
+use std::time::Duration;
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::ErrorKind;
+use x11rb::x11_utils::X11Error;
+
+//The ways making a fish can go wrong that a caller might want to tell apart
+#[derive(Debug)]
+pub enum FishError {
+    //Something the user asked for doesn't make sense
+    BadParams(String),
+    //Asked to connect somewhere the address policy doesn't allow
+    AddressForbidden(String),
+    //Host name didn't resolve
+    DnsFailure(String),
+    //Nothing listening, or the network wouldn't let us through
+    ConnectRefused(String),
+    //X server didn't like our auth (or lack of it)
+    AuthRejected(String),
+    //X server said something we didn't expect, or hung up on us
+    Protocol(String),
+    //Ran out of time before the fish could be made
+    Timeout(String),
+    //Fish generator is having a bad day
+    Generator(String),
+    //Too many fish too fast, with how long until the next one is allowed
+    RateLimited(String, Duration),
+    //Asked for a stored fish that isn't there
+    NotFound(String),
+    //A window or something else we were drawing with went away halfway through
+    Gone(String),
+    //X server won't let us, another client got there first or it's locked down
+    AccessDenied(String),
+    //X server ran out of memory for our windows and pixmaps
+    OutOfResources(String),
+    //Another request with the same idempotency key is still going
+    Conflict(String),
+}
+
+impl FishError {
+    //Stable name for each kind of error, for clients to match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            FishError::BadParams(_) => "bad_params",
+            FishError::AddressForbidden(_) => "address_forbidden",
+            FishError::DnsFailure(_) => "dns_failure",
+            FishError::ConnectRefused(_) => "connect_refused",
+            FishError::AuthRejected(_) => "auth_rejected",
+            FishError::Protocol(_) => "protocol_error",
+            FishError::Timeout(_) => "timeout",
+            FishError::Generator(_) => "generator_failed",
+            FishError::RateLimited(..) => "rate_limited",
+            FishError::NotFound(_) => "not_found",
+            FishError::Gone(_) => "gone",
+            FishError::AccessDenied(_) => "access_denied",
+            FishError::OutOfResources(_) => "out_of_resources",
+            FishError::Conflict(_) => "conflict",
+        }
+    }
+
+    //Errors that came through `?` as a plain boxed error, sorted back into what kind they are
+    //Anything we don't recognize comes back as it was
+    pub fn classify(err: Error) -> Result<FishError, Error> {
+        let err = match err.downcast::<FishError>() {
+            Ok(err) => return Ok(*err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ParseError>() {
+            Ok(err) => return Ok(FishError::BadParams(format!("bad drawing: {}", err))),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ConnectError>() {
+            Ok(err) => return Ok((*err).into()),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ConnectionError>() {
+            Ok(err) => return Ok((*err).into()),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ReplyError>() {
+            Ok(err) => return Ok((*err).into()),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ReplyOrIdError>() {
+            Ok(err) => return Ok((*err).into()),
+            Err(err) => err,
+        };
+        match err.downcast::<std::io::Error>() {
+            Ok(err) => Ok((*err).into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    //Same kind of error, with what we were doing when it happened in front
+    pub fn during(self, phase: &str) -> FishError {
+        let during = |msg: String| format!("{}: {}", phase, msg);
+        match self {
+            FishError::BadParams(msg) => FishError::BadParams(during(msg)),
+            FishError::AddressForbidden(msg) => FishError::AddressForbidden(during(msg)),
+            FishError::DnsFailure(msg) => FishError::DnsFailure(during(msg)),
+            FishError::ConnectRefused(msg) => FishError::ConnectRefused(during(msg)),
+            FishError::AuthRejected(msg) => FishError::AuthRejected(during(msg)),
+            FishError::Protocol(msg) => FishError::Protocol(during(msg)),
+            FishError::Timeout(msg) => FishError::Timeout(during(msg)),
+            FishError::Generator(msg) => FishError::Generator(during(msg)),
+            FishError::RateLimited(msg, retry_after) => FishError::RateLimited(during(msg), retry_after),
+            FishError::NotFound(msg) => FishError::NotFound(during(msg)),
+            FishError::Gone(msg) => FishError::Gone(during(msg)),
+            FishError::AccessDenied(msg) => FishError::AccessDenied(during(msg)),
+            FishError::OutOfResources(msg) => FishError::OutOfResources(during(msg)),
+            FishError::Conflict(msg) => FishError::Conflict(during(msg)),
+        }
+    }
+}
+
+//Errors from deep in the X calls only say what went wrong, this says what we were in the middle of
+//The kind of error stays the same, so the HTTP status doesn't change
+pub trait Context<T> {
+    fn context(self, phase: &str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn context(self, phase: &str) -> Result<T, Error> {
+        self.map_err(|err| match FishError::classify(err.into()) {
+            Ok(err) => err.during(phase).into(),
+            Err(err) => format!("{}: {}", phase, err).into(),
+        })
+    }
+}
+
+impl fmt::Display for FishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FishError::BadParams(msg)
+            | FishError::AddressForbidden(msg)
+            | FishError::DnsFailure(msg)
+            | FishError::ConnectRefused(msg)
+            | FishError::AuthRejected(msg)
+            | FishError::Protocol(msg)
+            | FishError::Timeout(msg)
+            | FishError::Generator(msg)
+            | FishError::NotFound(msg)
+            | FishError::Gone(msg)
+            | FishError::AccessDenied(msg)
+            | FishError::OutOfResources(msg)
+            | FishError::Conflict(msg)
+            | FishError::RateLimited(msg, _) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FishError {}
+
+impl From<std::io::Error> for FishError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::TimedOut => FishError::Timeout(format!("X server took too long: {}", err)),
+            _ => FishError::ConnectRefused(format!("couldn't connect to X server: {}", err)),
+        }
+    }
+}
+
+impl From<ConnectError> for FishError {
+    fn from(err: ConnectError) -> Self {
+        match err {
+            ConnectError::IoError(err) => err.into(),
+            ConnectError::DisplayParsingError(err) => FishError::BadParams(format!("bad address: {}", err)),
+            ConnectError::SetupFailed(failed) => FishError::AuthRejected(format!(
+                "X server wants authentication, pass a cookie: {}",
+                String::from_utf8_lossy(&failed.reason).trim()
+            )),
+            ConnectError::SetupAuthenticate(auth) => FishError::AuthRejected(format!(
+                "X server wants more authentication than a cookie: {}",
+                String::from_utf8_lossy(&auth.reason).trim()
+            )),
+            err => FishError::Protocol(format!("X server setup went wrong: {}", err)),
+        }
+    }
+}
+
+impl From<ConnectionError> for FishError {
+    fn from(err: ConnectionError) -> Self {
+        match err {
+            ConnectionError::IoError(err) => FishError::Protocol(format!("lost the X server: {}", err)),
+            err => FishError::Protocol(format!("X connection went wrong: {}", err)),
+        }
+    }
+}
+
+impl From<ReplyError> for FishError {
+    fn from(err: ReplyError) -> Self {
+        match err {
+            ReplyError::ConnectionError(err) => err.into(),
+            ReplyError::X11Error(err) => err.into(),
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for FishError {
+    fn from(err: ReplyOrIdError) -> Self {
+        match err {
+            ReplyOrIdError::ConnectionError(err) => err.into(),
+            ReplyOrIdError::X11Error(err) => err.into(),
+            ReplyOrIdError::IdsExhausted => FishError::Protocol("ran out of X resource ids".to_string()),
+        }
+    }
+}
+
+//Turns "X11Error { error_kind: Match, major_opcode: 1, .. }" into something a person can act on
+//like "BadMatch in CreateWindow: the screen's default visual doesn't support the requested background"
+impl From<X11Error> for FishError {
+    fn from(err: X11Error) -> Self {
+        let request = err.request_name.unwrap_or("an unknown request");
+        let id = err.bad_value;
+        let missing = |thing: &str| format!("{} {:#x} doesn't exist, probably closed while we were drawing", thing, id);
+        let explanation = match (err.error_kind, request) {
+            (ErrorKind::Match, "CreateWindow") => {
+                "the screen's default visual doesn't support the requested background or border".to_string()
+            }
+            (ErrorKind::Match, "CopyArea") => "the window and the fish's pixmap don't have the same depth".to_string(),
+            (ErrorKind::Match, _) => "the server's visual or depth doesn't fit what we asked for".to_string(),
+            (ErrorKind::Value, _) => format!("the server doesn't take {} there", id),
+            (ErrorKind::Window, _) => missing("window"),
+            (ErrorKind::Drawable, _) => missing("window or pixmap"),
+            (ErrorKind::Pixmap, _) => missing("pixmap"),
+            (ErrorKind::GContext, _) => missing("graphics context"),
+            (ErrorKind::Colormap, _) => missing("colormap"),
+            (ErrorKind::Alloc, _) => "the X server ran out of memory".to_string(),
+            (ErrorKind::Access, _) => "the server won't let us, another client has it or it's locked down".to_string(),
+            (ErrorKind::Name, _) => "the server doesn't have a font or color by that name".to_string(),
+            (ErrorKind::Length, _) => "that was too much for the server in one request".to_string(),
+            (ErrorKind::Implementation, _) => "the server doesn't implement that".to_string(),
+            (ErrorKind::Request, _) => "the server doesn't know that request, is an extension missing?".to_string(),
+            (ErrorKind::IDChoice, _) => format!("the server didn't like resource id {:#x}", id),
+            (kind, _) => format!("{:?} (error code {})", kind, err.error_code),
+        };
+        let msg = format!("Bad{:?} in {}: {}", err.error_kind, request, explanation);
+        match err.error_kind {
+            ErrorKind::Window | ErrorKind::Drawable | ErrorKind::Pixmap | ErrorKind::GContext | ErrorKind::Colormap => {
+                FishError::Gone(msg)
+            }
+            ErrorKind::Access => FishError::AccessDenied(msg),
+            ErrorKind::Alloc => FishError::OutOfResources(msg),
+            _ => FishError::Protocol(msg),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::guard::{GcGuard, PictureGuard};
+use crate::state::exposed;
+use crate::surface::Surface;
+use crate::{
+    color, create_line_gc, fit_fish, DrawOptions, DrawReport, Error, Fish, FishError, Pen, XFishSession,
+    MAX_LINE_DELAY, MIN_WINDOW_SIZE, POLL_INTERVAL,
+};
+use std::thread;
+use std::time::Instant;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ChangeGCAux, ChangeWindowAttributesAux, ClipOrdering, ConnectionExt, EventMask, MapState, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::NONE;
+
+//"0x1e00003" like xwininfo prints them, or plain decimal like xdotool does
+pub fn parse_window_id(window: &str) -> Result<Window, FishError> {
+    let window = window.trim();
+    let parsed = match window.strip_prefix("0x").or_else(|| window.strip_prefix("0X")) {
+        Some(hex) => Window::from_str_radix(hex, 16),
+        None => window.parse(),
+    };
+    parsed
+        .ok()
+        .filter(|&id| id != 0)
+        .ok_or_else(|| FishError::BadParams(format!("{:?} isn't a window id, try something like 0x1e00003", window)))
+}
+
+impl XFishSession {
+    //Draw straight onto somebody else's window, like an xterm, instead of making our own
+    //It isn't ours to fill with a background, so only the lines go on, and they go back on whenever it repaints
+    //Taking the fish back is asking the window to repaint itself without it
+    pub(crate) fn draw_existing(
+        &self,
+        fish: &Fish,
+        options: &DrawOptions,
+        deadline: Instant,
+        win_id: Window,
+    ) -> Result<DrawReport, Error> {
+        let conn = &*self.conn;
+        let screen = self.screen();
+        let missing = |_| FishError::BadParams(format!("there's no window {:#x} on the display", win_id));
+
+        let attributes = conn.get_window_attributes(win_id)?.reply().map_err(missing)?;
+        if attributes.class == WindowClass::INPUT_ONLY {
+            let msg = format!("window {:#x} is input only, there's nothing to draw on", win_id);
+            return Err(FishError::BadParams(msg).into());
+        }
+        if attributes.map_state != MapState::VIEWABLE {
+            let msg = format!("window {:#x} isn't on screen, nobody would see the fish", win_id);
+            return Err(FishError::BadParams(msg).into());
+        }
+        let geometry = conn.get_geometry(win_id)?.reply().map_err(missing)?;
+        let mut size = (geometry.width, geometry.height);
+        if size.0 < MIN_WINDOW_SIZE.0 || size.1 < MIN_WINDOW_SIZE.1 {
+            let msg = format!("window {:#x} is only {}x{}, too small for a fish", win_id, size.0, size.1);
+            return Err(FishError::BadParams(msg).into());
+        }
+
+        let background = color::alloc_pixel(conn, screen, options.background.as_deref(), screen.white_pixel);
+        let surface = Surface::of_window(geometry.depth, attributes.visual, background);
+        let gc = GcGuard::new(conn, conn.generate_id()?);
+        let foreground = color::alloc_pixel(conn, screen, options.color.as_deref(), screen.black_pixel);
+        create_line_gc(conn, gc.id, win_id, foreground, options)?;
+        let eraser = self.eraser(options, &surface, win_id)?;
+        let brush = self.brush(options, &surface)?;
+        let _fill = brush.map(|brush| PictureGuard::new(conn, brush.fill));
+        let pen = Pen {
+            gc: gc.id,
+            brush,
+            eraser: eraser.as_ref().map(|eraser| eraser.id),
+            palette: self.palette(options, &surface),
+            //Captions come with a background, which isn't ours to paint
+            caption: None,
+        };
+
+        //Only our own event mask changes, the window's owner keeps theirs
+        let event_mask = EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY;
+        conn.change_window_attributes(win_id, &ChangeWindowAttributesAux::new().event_mask(event_mask))?;
+        let mut lines = fit_fish(fish, size);
+        {
+            let _span = tracing::info_span!("animate", instant = options.instant).entered();
+            for stroke in pen.plan(&lines) {
+                if self.cancelled() {
+                    break;
+                }
+                pen.stroke(conn, win_id, &stroke)?;
+                if !options.instant {
+                    thread::sleep(options.line_delay.min(MAX_LINE_DELAY));
+                    conn.flush()?;
+                }
+            }
+            conn.flush()?;
+        }
+        let report = self.report(fish, Some(win_id), self.take_proof(options, win_id, None, size));
+        self.drawn(&report);
+
+        let _span = tracing::info_span!("event_loop", mode = "existing").entered();
+        let mut damage = Vec::new();
+        loop {
+            let Some(event) = conn.poll_for_event()? else {
+                if Instant::now() >= deadline {
+                    println!("Ran out of time, taking the fish back");
+                    break;
+                }
+                if self.cancelled() {
+                    println!("Drawing was cancelled, taking the fish back");
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            };
+            match event {
+                //The owner repaints first, then the fish goes back on top once the last expose is in
+                //Only where it got repainted though, the rest of the fish is still there
+                //Fills go through RENDER and don't care about the clip, but they only cover fish anyway
+                Event::Expose(event) => {
+                    damage.push(exposed(&event));
+                    if event.count > 0 {
+                        continue;
+                    }
+                    let gcs = [Some(pen.gc), pen.eraser];
+                    for gc in gcs.into_iter().flatten() {
+                        conn.set_clip_rectangles(ClipOrdering::UNSORTED, gc, 0, 0, &damage)?;
+                    }
+                    for stroke in pen.plan(&lines) {
+                        pen.stroke(conn, win_id, &stroke)?;
+                    }
+                    for gc in gcs.into_iter().flatten() {
+                        conn.change_gc(gc, &ChangeGCAux::new().clip_mask(NONE))?;
+                    }
+                    damage.clear();
+                    conn.flush()?;
+                }
+                Event::ConfigureNotify(event) if (event.width, event.height) != size => {
+                    size = (event.width, event.height);
+                    lines = fit_fish(fish, size);
+                }
+                Event::ConfigureNotify(_) | Event::MapNotify(_) | Event::UnmapNotify(_) | Event::ReparentNotify(_) => {}
+                //Gone, and the fish with it
+                Event::DestroyNotify(event) if event.window == win_id => {
+                    println!("Window was closed by its owner");
+                    return Ok(report);
+                }
+                Event::Error(err) => return Err(FishError::from(err).into()),
+                ev => println!("Got an unknown event: {:?}", ev),
+            }
+        }
+
+        //Stop listening and have the owner paint over the fish
+        conn.change_window_attributes(win_id, &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT))?;
+        conn.clear_area(true, win_id, 0, 0, 0, 0)?;
+        conn.flush()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,101 @@
+use x11rb::protocol::xproto::Point;
+
+//Lines thinner than this on average are details like gills and smiles, not shapes worth filling
+const DETAIL_THICKNESS: f64 = 2.0;
+
+//One line of the fish, and how it should be drawn
+pub(crate) struct Stroke<'l> {
+    pub points: &'l [Point],
+    //Where it comes in the drawing order, for palettes that change color as they go
+    pub index: usize,
+    //Filled in as a closed shape, instead of just drawn along
+    pub closed: bool,
+    //In the background color, for things like an eye that sits inside a filled body
+    pub erase: bool,
+}
+
+//Twice the area the line would enclose if its ends were joined, which is plenty to compare sizes with
+fn doubled_area(points: &[Point]) -> f64 {
+    let wrapped = points.iter().zip(points.iter().cycle().skip(1));
+    wrapped
+        .map(|(a, b)| a.x as f64 * b.y as f64 - b.x as f64 * a.y as f64)
+        .sum::<f64>()
+        .abs()
+}
+
+fn length(points: &[Point]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| (pair[1].x as f64 - pair[0].x as f64).hypot(pair[1].y as f64 - pair[0].y as f64))
+        .sum()
+}
+
+//Even-odd ray casting, treating the line as closed
+fn contains(polygon: &[Point], point: Point) -> bool {
+    let (x, y) = (point.x as f64, point.y as f64);
+    let mut inside = false;
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        let (ax, ay, bx, by) = (a.x as f64, a.y as f64, b.x as f64, b.y as f64);
+        if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+//What order to draw the lines in, and how
+//Outlines are just every line in the order it came. Filled fish go biggest shape first, so the body
+//doesn't cover up the fins and eye, then the thin detail lines on top. Anything sitting inside an odd
+//number of filled shapes is drawn in the background color, so an eye in a body still shows up
+pub(crate) fn plan(lines: &[Vec<Point>], filled: bool) -> Vec<Stroke<'_>> {
+    if !filled {
+        return lines
+            .iter()
+            .enumerate()
+            .map(|(index, points)| Stroke {
+                points,
+                index,
+                closed: false,
+                erase: false,
+            })
+            .collect();
+    }
+
+    let mut shapes = Vec::new();
+    let mut details = Vec::new();
+    for points in lines.iter().filter(|points| points.len() > 1) {
+        let area = doubled_area(points) / 2.0;
+        if points.len() > 2 && area > length(points) * DETAIL_THICKNESS {
+            shapes.push((area, points.as_slice()));
+        } else {
+            details.push(points.as_slice());
+        }
+    }
+    shapes.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let depth = |point: Point, smaller_than: f64| {
+        shapes
+            .iter()
+            .filter(|(area, shape)| *area > smaller_than && contains(shape, point))
+            .count()
+    };
+    let mut strokes: Vec<Stroke> = shapes
+        .iter()
+        .map(|&(area, points)| Stroke {
+            points,
+            index: 0,
+            closed: true,
+            erase: depth(points[0], area) % 2 == 1,
+        })
+        .collect();
+    strokes.extend(details.into_iter().map(|points| Stroke {
+        points,
+        index: 0,
+        closed: false,
+        erase: depth(points[points.len() / 2], 0.0) % 2 == 1,
+    }));
+    for (index, stroke) in strokes.iter_mut().enumerate() {
+        stroke.index = index;
+    }
+    strokes
+}
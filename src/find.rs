@@ -0,0 +1,158 @@
+use crate::{Atoms, FishError};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Screen, Window};
+
+//How many near misses the error lists
+const CLOSE_MATCHES: usize = 3;
+//Deep enough to get past any window manager's frames, not so deep that a weird tree keeps us busy
+const MAX_TREE_DEPTH: usize = 4;
+//Nobody's title is longer than this, in 32 bit units like get_property counts
+const MAX_PROPERTY_LENGTH: u32 = 1024;
+
+//How to pick out a window that's already on the screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowQuery {
+    //Anywhere in the title, ignoring case
+    Title(String),
+    //Either half of WM_CLASS, ignoring case
+    Class(String),
+}
+
+impl WindowQuery {
+    fn matches(&self, candidate: &Candidate) -> bool {
+        match self {
+            WindowQuery::Title(title) => candidate.title.to_lowercase().contains(&title.to_lowercase()),
+            WindowQuery::Class(class) => {
+                candidate.class.0.eq_ignore_ascii_case(class) || candidate.class.1.eq_ignore_ascii_case(class)
+            }
+        }
+    }
+
+    //What to compare a near miss by
+    fn labels<'c>(&self, candidate: &'c Candidate) -> Vec<&'c str> {
+        match self {
+            WindowQuery::Title(_) => vec![&candidate.title],
+            WindowQuery::Class(_) => vec![&candidate.class.0, &candidate.class.1],
+        }
+    }
+
+    fn wanted(&self) -> &str {
+        match self {
+            WindowQuery::Title(wanted) | WindowQuery::Class(wanted) => wanted,
+        }
+    }
+}
+
+//A window with a name, and so something people could have meant
+struct Candidate {
+    id: Window,
+    title: String,
+    class: (String, String),
+}
+
+fn text_property(conn: &impl Connection, window: Window, property: Atom, type_: impl Into<Atom>) -> Option<String> {
+    let reply = conn
+        .get_property(false, window, property, type_, 0, MAX_PROPERTY_LENGTH)
+        .ok()?
+        .reply()
+        .ok()?;
+    (!reply.value.is_empty()).then(|| String::from_utf8_lossy(&reply.value).into_owned())
+}
+
+fn candidate(conn: &impl Connection, atoms: &Atoms, id: Window) -> Option<Candidate> {
+    //_NET_WM_NAME is UTF-8, WM_NAME is whatever the app felt like, usually Latin-1 that's also ASCII
+    let title = text_property(conn, id, atoms._NET_WM_NAME, atoms.UTF8_STRING)
+        .or_else(|| text_property(conn, id, AtomEnum::WM_NAME.into(), AtomEnum::ANY))
+        .unwrap_or_default();
+    //Two NUL terminated strings, instance then class
+    let class = text_property(conn, id, AtomEnum::WM_CLASS.into(), AtomEnum::STRING).unwrap_or_default();
+    let mut class = class.split('\0');
+    let class = (
+        class.next().unwrap_or_default().to_string(),
+        class.next().unwrap_or_default().to_string(),
+    );
+    if title.is_empty() && class.0.is_empty() && class.1.is_empty() {
+        return None;
+    }
+    Some(Candidate { id, title, class })
+}
+
+//Top level windows as the window manager sees them, or a walk of the tree if there's no EWMH window manager
+fn windows(conn: &impl Connection, screen: &Screen, atoms: &Atoms) -> Vec<Window> {
+    let clients = conn
+        .get_property(false, screen.root, atoms._NET_CLIENT_LIST, AtomEnum::WINDOW, 0, u32::MAX / 4)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| Some(reply.value32()?.collect::<Vec<_>>()))
+        .unwrap_or_default();
+    if !clients.is_empty() {
+        return clients;
+    }
+
+    let mut found = Vec::new();
+    let mut level = vec![screen.root];
+    for _ in 0..MAX_TREE_DEPTH {
+        let mut next = Vec::new();
+        for window in level {
+            if let Some(tree) = conn.query_tree(window).ok().and_then(|cookie| cookie.reply().ok()) {
+                next.extend(tree.children);
+            }
+        }
+        found.extend(&next);
+        level = next;
+    }
+    found
+}
+
+//Edit distance, for guessing which window someone meant when they typo'd it
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = diagonal + (a != b) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+//The first window that matches, or an error with the ones that nearly did
+pub(crate) fn find_window(
+    conn: &impl Connection,
+    screen: &Screen,
+    atoms: &Atoms,
+    query: &WindowQuery,
+) -> Result<Window, FishError> {
+    let candidates: Vec<Candidate> = windows(conn, screen, atoms)
+        .into_iter()
+        .filter_map(|id| candidate(conn, atoms, id))
+        .collect();
+    if let Some(found) = candidates.iter().find(|candidate| query.matches(candidate)) {
+        return Ok(found.id);
+    }
+
+    let wanted = query.wanted().to_lowercase();
+    let mut near: Vec<(usize, &str)> = candidates
+        .iter()
+        .flat_map(|candidate| query.labels(candidate))
+        .filter(|label| !label.is_empty())
+        .map(|label| (distance(&wanted, &label.to_lowercase()), label))
+        .collect();
+    near.sort();
+    near.dedup_by(|a, b| a.1 == b.1);
+    let what = match query {
+        WindowQuery::Title(_) => "in its title",
+        WindowQuery::Class(_) => "as its class",
+    };
+    let msg = if near.is_empty() {
+        format!("no window has {:?} {}, there aren't any windows with names at all", query.wanted(), what)
+    } else {
+        let closest: Vec<String> = near.iter().take(CLOSE_MATCHES).map(|(_, label)| format!("{:?}", label)).collect();
+        format!("no window has {:?} {}, closest are {}", query.wanted(), what, closest.join(", "))
+    };
+    Err(FishError::NotFound(msg))
+}
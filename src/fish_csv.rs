@@ -0,0 +1,93 @@
+use crate::Fish;
+use std::fmt;
+
+// Fish CSV is so simple, it can be parsed manually
+// Each row is one line of the drawing, as x,y,x,y,...
+// Rows are not connected to each other
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    //Something in the row that isn't a number, columns count from 1 like a spreadsheet
+    NotANumber { line: usize, column: usize, item: String },
+    //An x without a y
+    OddRow { line: usize, items: usize },
+    //Nothing to draw at all
+    Empty,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NotANumber { line, column, item } => {
+                write!(f, "line {}, column {}: {:?} is not a number", line, column, item)
+            }
+            ParseError::OddRow { line, items } => {
+                write!(f, "line {}: {} numbers can't be made into x,y pairs", line, items)
+            }
+            ParseError::Empty => write!(f, "there are no lines to draw"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(fish_str: &str) -> Result<Fish, ParseError> {
+    let mut fish = Fish::new();
+    for (index, line) in fish_str.lines().enumerate() {
+        //Some spreadsheets like to leave a comma at the end, it doesn't mean anything
+        let line = line.trim().trim_end_matches(',');
+        if line.is_empty() {
+            continue;
+        }
+
+        let numbers = line
+            .split(',')
+            .enumerate()
+            .map(|(column, item)| {
+                item.trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .ok_or_else(|| ParseError::NotANumber {
+                        line: index + 1,
+                        column: column + 1,
+                        item: item.trim().to_string(),
+                    })
+            })
+            .collect::<Result<Vec<f64>, ParseError>>()?;
+        if numbers.len() % 2 != 0 {
+            return Err(ParseError::OddRow {
+                line: index + 1,
+                items: numbers.len(),
+            });
+        }
+
+        fish.push(numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+    }
+
+    if fish.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(fish)
+}
+
+//The other way around, for fish that didn't come from a CSV to begin with
+pub fn write(fish: &Fish) -> String {
+    let mut fish_str = String::new();
+    for line in fish {
+        let numbers: Vec<String> = line
+            .iter()
+            .flat_map(|&(x, y)| [x, y])
+            //Hundredths of a pixel is already more than anyone can see
+            //Numbers too big to multiply by 100 stay as they are, rounding them would make them infinite
+            .map(|number| {
+                let rounded = (number * 100.0).round() / 100.0;
+                let number = if rounded.is_finite() { rounded } else { number };
+                number.to_string()
+            })
+            .collect();
+        fish_str.push_str(&numbers.join(","));
+        fish_str.push('\n');
+    }
+    fish_str
+}
@@ -0,0 +1,76 @@
+use crate::{Error, FishError, FISH_URL};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Fish we've already made, so the same seed is the same fish even if the generator has a bad day
+//Only lives as long as the process, which on Lambda is as long as the instance stays warm
+//Every random fish has a new seed, so the oldest get forgotten to make room rather than piling up forever
+static CACHE: LazyLock<Mutex<FishCache>> = LazyLock::new(|| Mutex::new(FishCache::default()));
+//A few KB of CSV each, so this is a couple of MB at most
+const CACHE_SIZE: usize = 256;
+
+#[derive(Default)]
+struct FishCache {
+    fish: HashMap<u64, String>,
+    //Seeds from oldest to newest
+    order: VecDeque<u64>,
+}
+
+//Something different every time, for when the user doesn't care which fish they get
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn url_for(seed: u64) -> String {
+    format!("{}?seed={}", FISH_URL, seed)
+}
+
+fn cached(seed: u64) -> Option<String> {
+    CACHE.lock().ok()?.fish.get(&seed).cloned()
+}
+
+fn remember(seed: u64, fish_str: &str) {
+    if let Ok(mut cache) = CACHE.lock() {
+        if cache.fish.insert(seed, fish_str.to_string()).is_none() {
+            cache.order.push_back(seed);
+        }
+        while cache.order.len() > CACHE_SIZE {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.fish.remove(&oldest);
+            }
+        }
+    }
+}
+
+async fn fetch(url: String) -> Result<String, reqwest::Error> {
+    reqwest::get(url).await?.error_for_status()?.text().await
+}
+
+//Get the fish CSV for a seed, asking the generator only if we haven't seen it before
+pub async fn generate_csv(seed: u64) -> Result<String, Error> {
+    if let Some(fish_str) = cached(seed) {
+        return Ok(fish_str);
+    }
+    let fish_str = fetch(url_for(seed))
+        .await
+        .map_err(|err| FishError::Generator(format!("fish generator failed: {}", err)))?;
+    remember(seed, &fish_str);
+    Ok(fish_str)
+}
+
+//Same as generate_csv, for front ends without an async runtime
+pub fn generate_csv_blocking(seed: u64) -> Result<String, Error> {
+    if let Some(fish_str) = cached(seed) {
+        return Ok(fish_str);
+    }
+    let fish_str = reqwest::blocking::get(url_for(seed))
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|err| FishError::Generator(format!("fish generator failed: {}", err)))?;
+    remember(seed, &fish_str);
+    Ok(fish_str)
+}
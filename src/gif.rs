@@ -0,0 +1,61 @@
+use crate::png::Canvas;
+use crate::{DrawOptions, Error, Fish, MAX_LINE_DELAY};
+use ::gif::{Encoder, Frame, Repeat};
+
+//Past this many frames the GIF is mostly bytes, so lines get drawn a few at a time instead
+pub const MAX_GIF_FRAMES: usize = 60;
+//GIF delays are in hundredths of a second, and most viewers treat anything under 2 as "as fast as you like"
+const MIN_FRAME_DELAY: u16 = 2;
+//How long the finished fish stays up before it starts over
+const FINAL_FRAME_DELAY: u16 = 300;
+//NeuQuant effort, 1 is best and slowest, 10 is plenty for black lines on white
+const QUANTIZE_SPEED: i32 = 10;
+//Every frame gets quantized whole, so a 4096 pixel GIF would be 60 rounds of NeuQuant on 16 million pixels
+//Bigger asks get the same picture shrunk to fit, which is plenty for sharing
+pub const MAX_GIF_SIDE: u16 = 640;
+
+//The slow draw as an animated GIF, one frame per line like the window would get them
+//Lines come in at the requested speed, instant fish just get the one frame
+pub fn render_gif(fish: &Fish, options: &DrawOptions) -> Result<Vec<u8>, Error> {
+    let (width, height) = options.size;
+    let shrink = (MAX_GIF_SIDE as f64 / width.max(height) as f64).min(1.0);
+    let size = ((width as f64 * shrink).max(1.0) as u16, (height as f64 * shrink).max(1.0) as u16);
+    let options = &DrawOptions { size, ..options.clone() };
+    let (width, height) = size;
+    let mut canvas = Canvas::new(options)?;
+    let lines_per_frame = if options.instant {
+        fish.len().max(1)
+    } else {
+        fish.len().div_ceil(MAX_GIF_FRAMES).max(1)
+    };
+    let delay = (options.line_delay.min(MAX_LINE_DELAY).as_millis() as usize * lines_per_frame / 10)
+        .clamp(MIN_FRAME_DELAY as usize, u16::MAX as usize) as u16;
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut bytes, width, height, &[])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+        let chunks: Vec<_> = fish.chunks(lines_per_frame).collect();
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            for (index, line) in chunk.iter().enumerate() {
+                canvas.line(chunk_index * lines_per_frame + index, line);
+            }
+            //Everything's opaque, so the premultiplied pixels are already plain RGBA
+            let mut rgba = canvas.pixmap.data().to_vec();
+            let mut frame = Frame::from_rgba_speed(width, height, &mut rgba, QUANTIZE_SPEED);
+            frame.delay = if chunk_index + 1 == chunks.len() {
+                FINAL_FRAME_DELAY
+            } else {
+                delay
+            };
+            encoder.write_frame(&frame)?;
+        }
+        //No lines at all still makes a GIF, just a very calm one
+        if chunks.is_empty() {
+            let mut rgba = canvas.pixmap.data().to_vec();
+            encoder.write_frame(&Frame::from_rgba_speed(width, height, &mut rgba, QUANTIZE_SPEED))?;
+        }
+        //The trailer gets written when the encoder goes away
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,102 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::render::{ConnectionExt as _, Picture};
+use x11rb::protocol::xproto::{Colormap, ConnectionExt, Gcontext, Pixmap, Window};
+
+//Server side resources that clean themselves up, so an error halfway through a fish
+//doesn't leave windows lying around on someone's screen until we disconnect
+//Errors while cleaning up are ignored, there's nothing left to do about them by then
+
+pub(crate) struct WindowGuard<'c, C: Connection> {
+    conn: &'c C,
+    pub id: Window,
+}
+
+impl<'c, C: Connection> WindowGuard<'c, C> {
+    pub fn new(conn: &'c C, id: Window) -> Self {
+        WindowGuard { conn, id }
+    }
+
+    //Somebody else already destroyed it, destroying it again would only get us an error later
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl<C: Connection> Drop for WindowGuard<'_, C> {
+    fn drop(&mut self) {
+        let _ = self.conn.destroy_window(self.id);
+        let _ = self.conn.flush();
+    }
+}
+
+pub(crate) struct GcGuard<'c, C: Connection> {
+    conn: &'c C,
+    pub id: Gcontext,
+}
+
+impl<'c, C: Connection> GcGuard<'c, C> {
+    pub fn new(conn: &'c C, id: Gcontext) -> Self {
+        GcGuard { conn, id }
+    }
+}
+
+impl<C: Connection> Drop for GcGuard<'_, C> {
+    fn drop(&mut self) {
+        let _ = self.conn.free_gc(self.id);
+        let _ = self.conn.flush();
+    }
+}
+
+pub(crate) struct PixmapGuard<'c, C: Connection> {
+    conn: &'c C,
+    pub id: Pixmap,
+}
+
+impl<'c, C: Connection> PixmapGuard<'c, C> {
+    pub fn new(conn: &'c C, id: Pixmap) -> Self {
+        PixmapGuard { conn, id }
+    }
+}
+
+impl<C: Connection> Drop for PixmapGuard<'_, C> {
+    fn drop(&mut self) {
+        let _ = self.conn.free_pixmap(self.id);
+        let _ = self.conn.flush();
+    }
+}
+
+pub(crate) struct ColormapGuard<'c, C: Connection> {
+    conn: &'c C,
+    pub id: Colormap,
+}
+
+impl<'c, C: Connection> ColormapGuard<'c, C> {
+    pub fn new(conn: &'c C, id: Colormap) -> Self {
+        ColormapGuard { conn, id }
+    }
+}
+
+impl<C: Connection> Drop for ColormapGuard<'_, C> {
+    fn drop(&mut self) {
+        let _ = self.conn.free_colormap(self.id);
+        let _ = self.conn.flush();
+    }
+}
+
+pub(crate) struct PictureGuard<'c, C: Connection> {
+    conn: &'c C,
+    pub id: Picture,
+}
+
+impl<'c, C: Connection> PictureGuard<'c, C> {
+    pub fn new(conn: &'c C, id: Picture) -> Self {
+        PictureGuard { conn, id }
+    }
+}
+
+impl<C: Connection> Drop for PictureGuard<'_, C> {
+    fn drop(&mut self) {
+        let _ = self.conn.render_free_picture(self.id);
+        let _ = self.conn.flush();
+    }
+}
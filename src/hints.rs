@@ -0,0 +1,215 @@
+use crate::{Atoms, FishError};
+use std::str::FromStr;
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::properties::WmHints;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, PropMode, Window};
+use x11rb::wrapper::ConnectionExt as _;
+
+//_NET_WM_STATE client messages say whether to add, remove or toggle
+const NET_WM_STATE_ADD: u32 = 1;
+//Tells the window manager the request comes from a normal application, not a pager
+const SOURCE_APPLICATION: u32 = 1;
+
+//What kind of window the fish is, window managers decide borders and stacking from this
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowType {
+    #[default]
+    Normal,
+    //Usually no border, in the middle, gone when the app is done loading, which we never are
+    Splash,
+    Dialog,
+    //Small toolbox-ish window, often kept above the rest
+    Utility,
+}
+
+impl WindowType {
+    fn atom(self, atoms: &Atoms) -> Atom {
+        match self {
+            WindowType::Normal => atoms._NET_WM_WINDOW_TYPE_NORMAL,
+            WindowType::Splash => atoms._NET_WM_WINDOW_TYPE_SPLASH,
+            WindowType::Dialog => atoms._NET_WM_WINDOW_TYPE_DIALOG,
+            WindowType::Utility => atoms._NET_WM_WINDOW_TYPE_UTILITY,
+        }
+    }
+}
+
+impl FromStr for WindowType {
+    type Err = FishError;
+
+    fn from_str(window_type: &str) -> Result<Self, Self::Err> {
+        match window_type.trim().to_ascii_lowercase().as_str() {
+            "normal" => Ok(WindowType::Normal),
+            "splash" => Ok(WindowType::Splash),
+            "dialog" => Ok(WindowType::Dialog),
+            "utility" => Ok(WindowType::Utility),
+            _ => Err(FishError::BadParams(format!(
+                "don't know the {:?} window type, try normal, splash, dialog or utility",
+                window_type
+            ))),
+        }
+    }
+}
+
+//Things the window manager can do to the window on top of its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowState {
+    //Always on top
+    Above,
+    //On every desktop
+    Sticky,
+    SkipTaskbar,
+    Fullscreen,
+    //Flashing in the taskbar until somebody looks
+    DemandsAttention,
+}
+
+impl WindowState {
+    fn atom(self, atoms: &Atoms) -> Atom {
+        match self {
+            WindowState::Above => atoms._NET_WM_STATE_ABOVE,
+            WindowState::Sticky => atoms._NET_WM_STATE_STICKY,
+            WindowState::SkipTaskbar => atoms._NET_WM_STATE_SKIP_TASKBAR,
+            WindowState::Fullscreen => atoms._NET_WM_STATE_FULLSCREEN,
+            WindowState::DemandsAttention => atoms._NET_WM_STATE_DEMANDS_ATTENTION,
+        }
+    }
+}
+
+impl FromStr for WindowState {
+    type Err = FishError;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state.trim().to_ascii_lowercase().as_str() {
+            "above" => Ok(WindowState::Above),
+            "sticky" => Ok(WindowState::Sticky),
+            "skip_taskbar" => Ok(WindowState::SkipTaskbar),
+            "fullscreen" => Ok(WindowState::Fullscreen),
+            "demands_attention" => Ok(WindowState::DemandsAttention),
+            _ => Err(FishError::BadParams(format!(
+                "don't know the {:?} window state, try above, sticky, skip_taskbar, fullscreen or demands_attention",
+                state
+            ))),
+        }
+    }
+}
+
+//Comma separated, like "above,sticky"
+pub fn parse_states(states: &str) -> Result<Vec<WindowState>, FishError> {
+    states
+        .split(',')
+        .filter(|state| !state.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+//Before mapping, window managers read the type and starting state straight off the window
+pub(crate) fn set_hints(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    win_id: Window,
+    window_type: WindowType,
+    states: &[WindowState],
+) -> Result<(), ConnectionError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        win_id,
+        atoms._NET_WM_WINDOW_TYPE,
+        AtomEnum::ATOM,
+        &[window_type.atom(atoms)],
+    )?;
+    if !states.is_empty() {
+        let states: Vec<Atom> = states.iter().map(|state| state.atom(atoms)).collect();
+        conn.change_property32(PropMode::REPLACE, win_id, atoms._NET_WM_STATE, AtomEnum::ATOM, &states)?;
+    }
+    Ok(())
+}
+
+//Once it's mapped, the window manager owns _NET_WM_STATE and has to be asked nicely through the root window
+//Some only listen this way, so ask again even though the property already says so
+pub(crate) fn request_states(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: Window,
+    win_id: Window,
+    states: &[WindowState],
+) -> Result<(), ConnectionError> {
+    for state in states {
+        let event = ClientMessageEvent::new(
+            32,
+            win_id,
+            atoms._NET_WM_STATE,
+            [NET_WM_STATE_ADD, state.atom(atoms), 0, SOURCE_APPLICATION, 0],
+        );
+        conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+    }
+    Ok(())
+}
+
+//EWMH window managers list what they support on the root window, the ones from before it don't know _NET anything
+//Without one there's no point setting _NET hints, and some of the old ones get confused by them
+//The atoms still get interned along with the rest, that's harmless, it's setting them that isn't
+pub(crate) fn has_ewmh(conn: &impl Connection, atoms: &Atoms, root: Window) -> bool {
+    conn.get_property(false, root, atoms._NET_SUPPORTED, AtomEnum::ATOM, 0, 1)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .is_some_and(|reply| reply.value_len > 0)
+}
+
+//Once the fish is done, make sure somebody notices it
+//Old window managers only know the ICCCM urgency hint, newer ones want the EWMH state, so both when there's EWMH
+pub(crate) fn demand_attention(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: Window,
+    win_id: Window,
+    ewmh: bool,
+) -> Result<(), ReplyError> {
+    let mut hints = WmHints::get(conn, win_id)?.reply()?.unwrap_or_default();
+    hints.urgent = true;
+    hints.set(conn, win_id)?;
+    if ewmh {
+        request_states(conn, atoms, root, win_id, &[WindowState::DemandsAttention])?;
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+//WM_STATE values from ICCCM, only iconic matters to us
+const ICONIC_STATE: u32 = 3;
+
+//Window managers that iconify a window unmap it and mark it iconic, anything else that unmaps it is closing it
+//No WM_STATE at all means no window manager looking after it, so that's a close too
+pub(crate) fn is_iconic(conn: &impl Connection, atoms: &Atoms, win_id: Window) -> bool {
+    conn.get_property(false, win_id, atoms.WM_STATE, atoms.WM_STATE, 0, 1)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| reply.value32()?.next())
+        == Some(ICONIC_STATE)
+}
+
+//Window managers ping to see if we're still alive, and grey the window out if we don't answer
+pub(crate) fn is_ping(atoms: &Atoms, event: &ClientMessageEvent) -> bool {
+    event.format == 32 && event.type_ == atoms.WM_PROTOCOLS && event.data.as_data32()[0] == atoms._NET_WM_PING
+}
+
+//The answer is the same message straight back, addressed to the root window this time
+pub(crate) fn answer_ping(
+    conn: &impl Connection,
+    root: Window,
+    mut ping: ClientMessageEvent,
+) -> Result<(), ConnectionError> {
+    ping.window = root;
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        ping,
+    )?;
+    conn.flush()
+}
@@ -0,0 +1,74 @@
+use crate::{fit_fish, Atoms, Fish};
+use x11rb::connection::Connection;
+use x11rb::errors::ConnectionError;
+use x11rb::protocol::xproto::{AtomEnum, PropMode, Window};
+use x11rb::wrapper::ConnectionExt as _;
+
+//Taskbars pick whichever is closest to what they want, so give them a few to choose from
+const ICON_SIZES: [u16; 4] = [16, 32, 48, 64];
+//Below this a second pixel of line just turns the fish into a blob
+const THICK_ICON_SIZE: u16 = 48;
+
+//16 bit per channel RGB into the opaque ARGB that _NET_WM_ICON wants
+fn argb((red, green, blue): (u16, u16, u16)) -> u32 {
+    0xff000000 | (red as u32 >> 8) << 16 | (green as u32 >> 8) << 8 | blue as u32 >> 8
+}
+
+//Plain old Bresenham, there's no X server to draw on for these
+fn rasterize(fish: &Fish, size: u16, color: u32, background: u32) -> Vec<u32> {
+    let side = size as i32;
+    let mut pixels = vec![background; (side * side) as usize];
+    let thickness = if size >= THICK_ICON_SIZE { 2 } else { 1 };
+    let mut plot = |x: i32, y: i32| {
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().take(thickness * thickness) {
+            let (x, y) = (x + dx, y + dy);
+            if (0..side).contains(&x) && (0..side).contains(&y) {
+                pixels[(y * side + x) as usize] = color;
+            }
+        }
+    };
+    for line in fit_fish(fish, (size, size)) {
+        for pair in line.windows(2) {
+            let (mut x, mut y) = (pair[0].x as i32, pair[0].y as i32);
+            let (x1, y1) = (pair[1].x as i32, pair[1].y as i32);
+            let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+            let (step_x, step_y) = ((x1 - x).signum(), (y1 - y).signum());
+            let mut error = dx + dy;
+            loop {
+                plot(x, y);
+                if x == x1 && y == y1 {
+                    break;
+                }
+                if error * 2 >= dy {
+                    error += dy;
+                    x += step_x;
+                }
+                if error * 2 <= dx {
+                    error += dx;
+                    y += step_y;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+//The fish as its own icon, so alt-tab shows a fish instead of whatever the window manager's generic one is
+//Each size goes in as width, height, then the pixels row by row
+pub(crate) fn set_icon(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    win_id: Window,
+    fish: &Fish,
+    color: (u16, u16, u16),
+    background: (u16, u16, u16),
+) -> Result<(), ConnectionError> {
+    let mut icon = Vec::new();
+    for size in ICON_SIZES {
+        icon.push(size as u32);
+        icon.push(size as u32);
+        icon.extend(rasterize(fish, size, argb(color), argb(background)));
+    }
+    conn.change_property32(PropMode::REPLACE, win_id, atoms._NET_WM_ICON, AtomEnum::CARDINAL, &icon)?;
+    Ok(())
+}
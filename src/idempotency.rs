@@ -0,0 +1,190 @@
+use crate::{stable_hash, Error, FishError};
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Remembers which Idempotency-Keys we've seen, for deployments that set XFISH_IDEMPOTENCY_TABLE
+//A retried click gets the first answer back instead of a second fish
+//Same shape as the rate limit table, a "key" string and an "expires" number for DynamoDB's TTL to clean up
+pub struct IdempotencyStore {
+    client: Client,
+    table: String,
+}
+
+//How long a finished request's answer stays around for retries
+const DONE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+//Keys are for telling retries apart, not for storing things in
+const MAX_KEY_LENGTH: usize = 255;
+//DynamoDB items top out at 400KB, anything bigger is remembered as done without the answer
+const MAX_STORED_BODY: usize = 350 * 1024;
+
+//The answer a request got, to give to anyone asking again
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+//An Idempotency-Key as stored, scoped to whoever sent it so one caller can't be handed another's fish
+//The request it came with is kept too, reusing a key for something different is a mistake, not a retry
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey {
+    item: String,
+    request: String,
+}
+
+impl IdempotencyKey {
+    //The caller is whoever sent it, as something fine to keep in the table, like a hash of their API key
+    pub fn new(key: &str, caller: &str, request: &[&[u8]]) -> Result<Self, FishError> {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH || !key.bytes().all(|byte| byte.is_ascii_graphic()) {
+            let msg = format!("Idempotency-Key should be 1 to {} printable characters", MAX_KEY_LENGTH);
+            return Err(FishError::BadParams(msg));
+        }
+        //Each part goes in with its length, so where one ends and the next starts can't be moved around
+        let request: Vec<u8> = request
+            .iter()
+            .flat_map(|part| (part.len() as u64).to_le_bytes().into_iter().chain(part.iter().copied()))
+            .collect();
+        Ok(IdempotencyKey {
+            item: format!("{}:{}", caller, key),
+            request: stable_hash(&request),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Claim {
+    //Nobody's used this key, it's ours now
+    New,
+    //Someone has and it's still going
+    InFlight,
+    //Someone has and this is what they got, none if it was too big to keep
+    Done(Option<StoredResponse>),
+    //Someone has, for a different request
+    Mismatch,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+impl IdempotencyStore {
+    pub async fn from_env() -> Option<Self> {
+        let table = std::env::var("XFISH_IDEMPOTENCY_TABLE").ok()?;
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Some(IdempotencyStore {
+            client: Client::new(&config),
+            table,
+        })
+    }
+
+    //Claim the key for as long as the request could possibly take, or find out what happened to it
+    //If it was claimed and never finished, it frees itself up when the claim runs out
+    pub async fn begin(&self, key: &IdempotencyKey, in_flight_for: Duration) -> Claim {
+        let now = now();
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("key", AttributeValue::S(key.item.clone()))
+            .item("request", AttributeValue::S(key.request.clone()))
+            .item("state", AttributeValue::S("in_flight".to_string()))
+            .item("expires", AttributeValue::N((now + in_flight_for.as_secs()).to_string()))
+            .condition_expression("attribute_not_exists(#key) OR #expires < :now")
+            .expression_attribute_names("#key", "key")
+            .expression_attribute_names("#expires", "expires")
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => return Claim::New,
+            Err(err) if err.as_service_error().is_some_and(|err| err.is_conditional_check_failed_exception()) => {}
+            //Like rate limiting, a broken table shouldn't stop the fish
+            Err(err) => {
+                println!("Couldn't check the idempotency table: {}", err);
+                return Claim::New;
+            }
+        }
+
+        let result = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("key", AttributeValue::S(key.item.clone()))
+            .consistent_read(true)
+            .send()
+            .await;
+        //Somebody has the key, and without knowing more, a second fish is what we're here to avoid
+        let item = match result {
+            Ok(output) => output.item.unwrap_or_default(),
+            Err(err) => {
+                println!("Couldn't look up an idempotency key: {}", err);
+                return Claim::InFlight;
+            }
+        };
+        if item.get("request").and_then(|request| request.as_s().ok()).is_some_and(|request| *request != key.request) {
+            return Claim::Mismatch;
+        }
+        if item.get("state").and_then(|state| state.as_s().ok()).map(String::as_str) != Some("done") {
+            return Claim::InFlight;
+        }
+        Claim::Done(stored_response(&item))
+    }
+
+    //Keep the answer for anyone who asks again with the same key
+    pub async fn finish(&self, key: &IdempotencyKey, response: &StoredResponse) -> Result<(), Error> {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("key", AttributeValue::S(key.item.clone()))
+            .item("request", AttributeValue::S(key.request.clone()))
+            .item("state", AttributeValue::S("done".to_string()))
+            .item("expires", AttributeValue::N((now() + DONE_TTL.as_secs()).to_string()));
+        if response.body.len() <= MAX_STORED_BODY {
+            request = request
+                .item("status", AttributeValue::N(response.status.to_string()))
+                .item("body", AttributeValue::B(Blob::new(response.body.clone())));
+            if let Some(content_type) = &response.content_type {
+                request = request.item("content_type", AttributeValue::S(content_type.clone()));
+            }
+        }
+        request.send().await?;
+        Ok(())
+    }
+
+    //Let the key go without an answer, for when trying again might go better
+    //Only our own claim though, a finished answer stays
+    pub async fn release(&self, key: &IdempotencyKey) -> Result<(), Error> {
+        let result = self
+            .client
+            .delete_item()
+            .table_name(&self.table)
+            .key("key", AttributeValue::S(key.item.clone()))
+            .condition_expression("#state = :in_flight")
+            .expression_attribute_names("#state", "state")
+            .expression_attribute_values(":in_flight", AttributeValue::S("in_flight".to_string()))
+            .send()
+            .await;
+        match result {
+            Err(err) if !err.as_service_error().is_some_and(|err| err.is_conditional_check_failed_exception()) => {
+                Err(err.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn stored_response(item: &HashMap<String, AttributeValue>) -> Option<StoredResponse> {
+    Some(StoredResponse {
+        status: item.get("status")?.as_n().ok()?.parse().ok()?,
+        content_type: item
+            .get("content_type")
+            .and_then(|content_type| content_type.as_s().ok())
+            .cloned(),
+        body: item.get("body")?.as_b().ok()?.clone().into_inner(),
+    })
+}
@@ -0,0 +1,36 @@
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ConnectionExt, Keycode, Keysym};
+
+//Keysyms for plain letters are just their ASCII
+pub(crate) const KEY_N: Keysym = 0x6e;
+pub(crate) const KEY_Q: Keysym = 0x71;
+
+//Which keysym each keycode makes, KeyPress only says which key it was
+pub(crate) struct Keymap {
+    min_keycode: Keycode,
+    keysyms_per_keycode: u8,
+    keysyms: Vec<Keysym>,
+}
+
+impl Keymap {
+    pub fn new(conn: &impl Connection) -> Result<Self, ReplyError> {
+        let setup = conn.setup();
+        let count = setup.max_keycode - setup.min_keycode + 1;
+        let reply = conn.get_keyboard_mapping(setup.min_keycode, count)?.reply()?;
+        Ok(Keymap {
+            min_keycode: setup.min_keycode,
+            keysyms_per_keycode: reply.keysyms_per_keycode,
+            keysyms: reply.keysyms,
+        })
+    }
+
+    //Without shift, which is all q and n need
+    pub fn keysym(&self, keycode: Keycode) -> Keysym {
+        let Some(offset) = keycode.checked_sub(self.min_keycode) else {
+            return 0;
+        };
+        let index = offset as usize * self.keysyms_per_keycode as usize;
+        self.keysyms.get(index).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,1537 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyOrIdError};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, CapStyle, ChangeGCAux, ChangeWindowAttributesAux, ClipOrdering, CloseDown, ConfigureWindowAux,
+    ConnectionExt, CoordMode, CreateGCAux, CreateWindowAux, Drawable, Gcontext, JoinStyle, LineStyle, Pixmap, Point,
+    PolyShape, PropMode, Rectangle, Screen, StackMode, Visibility, Window, WindowClass,
+};
+use x11rb::properties::{AspectRatio, WmSizeHints, WmSizeHintsSpecification};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{atom_manager, connect, NONE};
+
+use find::WindowQuery;
+use dial::Dialed;
+use error::Context;
+use hints::{WindowState, WindowType};
+use input::Keymap;
+use guard::{ColormapGuard, GcGuard, PictureGuard, PixmapGuard, WindowGuard};
+use caption::Caption;
+use clipboard::Clipboard;
+use fill::Stroke;
+use render::Brush;
+use state::{Action, SessionState};
+use surface::Surface;
+
+use x11rb::protocol::xproto::EventMask;
+
+pub mod address;
+#[cfg(feature = "signing")]
+pub mod apikeys;
+pub mod ascii;
+mod aquarium;
+#[cfg(feature = "dynamodb")]
+pub mod audit;
+pub mod auth;
+pub mod caption;
+mod clipboard;
+#[cfg(feature = "clock")]
+pub mod clock;
+mod color;
+pub mod cors;
+#[cfg(feature = "generator")]
+pub mod creature;
+pub mod dial;
+pub mod drawings;
+pub mod error;
+mod existing;
+mod fill;
+pub mod find;
+pub mod fish_csv;
+#[cfg(feature = "generator")]
+pub mod generator;
+#[cfg(feature = "gif")]
+pub mod gif;
+mod guard;
+pub mod hints;
+mod icon;
+#[cfg(feature = "dynamodb")]
+pub mod idempotency;
+mod input;
+pub mod metrics;
+mod monitor;
+pub mod overlay;
+#[cfg(feature = "png")]
+pub mod png;
+pub mod policy;
+#[cfg(feature = "png")]
+mod proof;
+mod pool;
+pub mod props;
+pub mod proxy;
+pub mod ratelimit;
+mod render;
+pub mod request;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+pub mod school;
+#[cfg(feature = "secrets")]
+pub mod secrets;
+mod shape;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "ssh")]
+pub mod ssh;
+mod state;
+#[cfg(feature = "dynamodb")]
+pub mod stats;
+#[cfg(feature = "s3")]
+pub mod store;
+pub mod style;
+mod surface;
+pub mod svg;
+pub mod theme;
+pub mod transform;
+mod tray;
+pub mod upload;
+mod xtest;
+
+pub use address::DisplayAddress;
+pub use dial::{Family, RetryPolicy, Route};
+pub use error::FishError;
+pub use existing::parse_window_id;
+pub use policy::AddressPolicy;
+pub use proxy::Proxy;
+pub use style::{Cap, Palette};
+
+//Same shape as lambda_http::Error, so `?` works on both sides
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+// Each inner Vec is a list of points that make up a connected line
+// The lines are not connected to each other
+pub type Fish = Vec<Vec<(f64, f64)>>;
+
+//Size of the canvas the fish generator draws on, the fish gets scaled from this to the window
+pub const FISH_CANVAS: (f64, f64) = (520.0, 320.0);
+
+//The generator that makes a brand new fish on every request
+pub const FISH_URL: &str = "https://j7qpm35ughmqz53afoye64up7a0wpawg.lambda-url.us-east-1.on.aws/";
+
+//How much of the canvas to leave empty on each side when fitting a fish to it, in percent
+pub const DEFAULT_MARGIN: f64 = 5.0;
+pub const MAX_MARGIN: f64 = 45.0;
+
+//Smallest and biggest windows we'll make, a tenth of the canvas is about when the fish stops being a fish
+pub const MIN_WINDOW_SIZE: (u16, u16) = (52, 32);
+pub const MAX_WINDOW_SIZE: (u16, u16) = (4096, 4096);
+
+//Pause between lines, creates a slow drawing effect
+pub const LINE_DELAY: Duration = Duration::from_millis(7);
+//Any slower and the fish would outlive the Lambda
+pub const MAX_LINE_DELAY: Duration = Duration::from_millis(100);
+
+//What the window is called if nobody says otherwise
+pub const DEFAULT_TITLE: &str = "X11:11 makeafish";
+pub const DEFAULT_CLASS: (&str, &str) = ("makeafish", "XFish");
+
+//However long anyone asks for, nothing stays up longer than this
+//Window managers that never send WM_DELETE_WINDOW and never unmap us would otherwise keep us around forever
+pub const MAX_WINDOW_LIFETIME: Duration = Duration::from_secs(60 * 60 * 24);
+
+//How often to check for new events while waiting for the window to be closed
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+atom_manager! {
+    pub Atoms: AtomsCookie {
+        UTF8_STRING,
+        CLIPBOARD,
+        TARGETS,
+        TEXT,
+        IMAGE_SVG: b"image/svg+xml",
+        WM_DELETE_WINDOW,
+        WM_PROTOCOLS,
+        WM_STATE,
+        _NET_SUPPORTED,
+        _NET_WM_PING,
+        _NET_WM_NAME,
+        _NET_WM_ICON,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_NORMAL,
+        _NET_WM_WINDOW_TYPE_SPLASH,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
+        _NET_WORKAREA,
+        _NET_CURRENT_DESKTOP,
+        _NET_CLIENT_LIST,
+        _NET_WM_STRUT,
+        _NET_SYSTEM_TRAY_OPCODE,
+        _XEMBED_INFO,
+        _XROOTPMAP_ID,
+        ESETROOT_PMAP_ID,
+    }
+}
+
+//The pixmap a wallpaper property on the root window points at, if there is one
+fn root_pixmap(conn: &impl Connection, root: Window, property: Atom) -> Option<Pixmap> {
+    conn.get_property(false, root, property, AtomEnum::PIXMAP, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()
+        .filter(|&pixmap| pixmap != NONE)
+}
+
+//Scale and offset that fit the fish canvas in the window, keeping its shape and centering it
+pub(crate) fn fit_transform((width, height): (u16, u16)) -> (f64, f64, f64) {
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let scale = (width as f64 / canvas_width).min(height as f64 / canvas_height);
+    let offset_x = (width as f64 - canvas_width * scale) / 2.0;
+    let offset_y = (height as f64 - canvas_height * scale) / 2.0;
+    (scale, offset_x, offset_y)
+}
+
+//Scale the fish from its canvas to fit the window
+pub fn fit_fish(fish: &Fish, size: (u16, u16)) -> Vec<Vec<Point>> {
+    let (scale, offset_x, offset_y) = fit_transform(size);
+    fish.iter()
+        .map(|line| {
+            line.iter()
+                .map(|&(x, y)| Point {
+                    x: (x * scale + offset_x) as i16,
+                    y: (y * scale + offset_y) as i16,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+//Top left and bottom right corners of everything in the fish, none if there's nothing in it
+pub fn fish_bounds(fish: &Fish) -> Option<((f64, f64), (f64, f64))> {
+    let mut points = fish.iter().flatten();
+    let &(x, y) = points.next()?;
+    Some(points.fold(((x, y), (x, y)), |((left, top), (right, bottom)), &(x, y)| {
+        ((left.min(x), top.min(y)), (right.max(x), bottom.max(y)))
+    }))
+}
+
+//Scale and move the fish so it fills the canvas, less the margin on every side, keeping its shape
+//Drawings can come from anywhere at any size, this puts them where the windows expect them
+pub fn fit_to_canvas(fish: &mut Fish, margin: f64) {
+    let Some(((left, top), (right, bottom))) = fish_bounds(fish) else {
+        return;
+    };
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let room = 1.0 - 2.0 * margin / 100.0;
+    //A flat line has no height to go by, only its width counts, and a lone dot stays the size it is
+    let scale = [(canvas_width * room, right - left), (canvas_height * room, bottom - top)]
+        .into_iter()
+        .filter(|&(_, size)| size > 0.0)
+        .map(|(room, size)| room / size)
+        .fold(f64::INFINITY, f64::min);
+    let scale = if scale.is_finite() { scale } else { 1.0 };
+    let (center_x, center_y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+    for (x, y) in fish.iter_mut().flatten() {
+        *x = (*x - center_x) * scale + canvas_width / 2.0;
+        *y = (*y - center_y) * scale + canvas_height / 2.0;
+    }
+}
+
+//"instance,Class" like xprop shows it, or just one name to use for both
+//WM_CLASS is NUL separated, so NULs can't be let through
+pub fn parse_class(class: &str) -> (String, String) {
+    let clean = |name: &str| name.trim().replace('\0', "");
+    match class.split_once(',') {
+        Some((instance, class)) => (clean(instance), clean(class)),
+        None => (clean(class), clean(class)),
+    }
+}
+
+//Add a default display/screen (?) number if user did not supply it, and tidy up the rest
+pub fn normalize_address(address: &str) -> Result<String, FishError> {
+    Ok(DisplayAddress::parse(address)?.to_string())
+}
+
+//FNV-1a as hex, for IDs and hashes that have to come out the same no matter which Rust built us
+pub(crate) fn stable_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+//Where the fish ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    //A window of its own
+    #[default]
+    Window,
+    //The desktop background, like feh --bg does it
+    Root,
+}
+
+//What the fish does once it's drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    //Sits there being a fish
+    #[default]
+    Still,
+    //Swims back and forth until the window is closed
+    Aquarium,
+    //No window at all, the recipient's own pointer draws it in whatever app they have focused
+    XTest,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrawOptions {
+    pub target: Target,
+    pub mode: Mode,
+    pub size: (u16, u16),
+    //Where the window goes on the screen, if the window manager listens
+    //Nothing means the middle of the monitor
+    pub position: Option<(i16, i16)>,
+    //Which monitor to center on or fill, by RandR's numbering, the primary one if missing
+    pub monitor: Option<usize>,
+    pub line_delay: Duration,
+    //Draw everything at once with one flush, for people far away from us on the network
+    pub instant: bool,
+    //Named color like "salmon" or hex like "#fa8072", black if missing
+    //With a background and no color, whichever of black or white stands out more
+    pub color: Option<String>,
+    //Same kinds of color as the fish, white if missing
+    pub background: Option<String>,
+    //Read the fish back off the screen after drawing it, needs the png feature
+    pub proof: bool,
+    //See-through background so the fish floats over the desktop, needs a compositor to look right
+    pub transparent: bool,
+    //Cut the window to the shape of the fish, for when there's no compositor
+    pub shaped: bool,
+    //Smooth lines through RENDER when the server has it, off means core lines like it's 1987
+    pub anti_alias: bool,
+    //0 is the server's fastest hairline, anything more is that many pixels
+    pub line_width: u16,
+    //On, off, on, off lengths in pixels, empty for solid lines
+    pub dashes: Vec<u8>,
+    pub cap: Cap,
+    //Solid shapes instead of outlines
+    pub filled: bool,
+    pub palette: Palette,
+    //Text under the fish, in a core font, with the font falling back to "fixed"
+    pub caption: Option<String>,
+    pub font: Option<String>,
+    //What the window manager shows in the title bar
+    pub title: String,
+    //Instance and class for WM_CLASS, which is what window manager rules match on
+    pub class: (String, String),
+    //EWMH type and states, for splash screens and always on top fish
+    pub window_type: WindowType,
+    pub states: Vec<WindowState>,
+    //Skip the window manager altogether, no borders, no placement, just a fish where we said
+    pub popup: bool,
+    //Cover the whole primary monitor, size and position get worked out from it
+    pub fullscreen: bool,
+    //Click for another fish, n for the next theme, q to close
+    pub interactive: bool,
+    //Which theme the colors and lines came from, so n knows what comes after it
+    pub theme: &'static str,
+    //Somebody else's window to draw on instead of opening one, like an xterm
+    pub window: Option<Window>,
+    //Same, but found by its title or class, for when nobody knows the ID
+    pub find: Option<WindowQuery>,
+    //A tiny fish swimming in the system tray instead of a window
+    pub tray: bool,
+    //Paste-able fish, as SVG and text, for as long as the window is up
+    pub clipboard: bool,
+    //Flash the taskbar once the fish is done, so it doesn't go unseen
+    pub attention: bool,
+}
+
+//What happened while drawing
+#[derive(Debug, Default, Clone)]
+pub struct DrawReport {
+    //PNG of the fish as it appeared on screen, if proof was asked for and could be had
+    pub proof_png: Option<Vec<u8>>,
+    //How many tries it took to get through to the display, 0 for a connection from the pool
+    pub connect_attempts: u32,
+    //Which family won the race to a dual stack host, none for sockets, proxies and pooled connections
+    pub address_family: Option<Family>,
+    pub lines: usize,
+    pub points: usize,
+    //From draw() starting to the whole fish being up, none if it never got that far
+    pub draw_time: Option<Duration>,
+    //Connecting and X setup, none for a connection from the pool
+    pub connect_time: Option<Duration>,
+    //Who made the X server, and which version, as it told us in the setup
+    pub vendor: String,
+    pub release: u32,
+    //The fish's own window, or whichever one it got drawn on
+    pub window: Option<Window>,
+    //How long the window was up before the recipient closed it, none if they never did
+    pub closed_after: Option<Duration>,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        DrawOptions {
+            target: Target::Window,
+            mode: Mode::Still,
+            size: (520, 320),
+            position: None,
+            monitor: None,
+            line_delay: LINE_DELAY,
+            instant: false,
+            color: None,
+            background: None,
+            proof: false,
+            transparent: false,
+            shaped: false,
+            anti_alias: true,
+            line_width: 0,
+            dashes: Vec::new(),
+            cap: Cap::default(),
+            filled: false,
+            palette: Palette::default(),
+            caption: None,
+            font: None,
+            title: DEFAULT_TITLE.to_string(),
+            class: (DEFAULT_CLASS.0.to_string(), DEFAULT_CLASS.1.to_string()),
+            window_type: WindowType::default(),
+            states: Vec::new(),
+            popup: false,
+            fullscreen: false,
+            interactive: false,
+            theme: theme::THEMES[0].name,
+            window: None,
+            find: None,
+            tray: false,
+            clipboard: false,
+            attention: false,
+        }
+    }
+}
+
+pub struct XFishSession {
+    conn: Arc<RustConnection>,
+    screen_num: usize,
+    //Key the connection goes back to the pool under when we're done, none if it isn't one to share
+    pool_key: Option<String>,
+    //Tries it took to connect, for the report
+    connect_attempts: u32,
+    //Which of IPv4 and IPv6 got there first, for the report
+    address_family: Option<Family>,
+    //How long connecting took, none for a connection from the pool
+    connect_time: Option<Duration>,
+    //When the current draw() started, so the report can say how long the fish took
+    draw_started: Mutex<Option<Instant>>,
+    //How the connection got there, so connections to the other screens can go the same way
+    route: Route,
+    //Same for where AddressPolicy said the first hop could go
+    vetted: Option<Vec<SocketAddr>>,
+    atoms: Atoms,
+    //Set from another thread to take the fish back early
+    cancel: Arc<AtomicBool>,
+    //Called once the fish is up, while the window is still waiting to be closed
+    on_drawn: Option<OnDrawn>,
+    //Where interactive windows get another fish from when they're clicked
+    new_fish: Option<NewFish>,
+}
+
+pub type OnDrawn = Arc<dyn Fn(&DrawReport) + Send + Sync>;
+pub type NewFish = Arc<dyn Fn() -> Result<Fish, Error> + Send + Sync>;
+
+//What's in one window, kept while the theme changes around it
+struct Scene<'f> {
+    fish: Cow<'f, Fish>,
+    //Only the first fish to finish gets reported, later ones are just for fun
+    report: Option<DrawReport>,
+    //What gets pasted, until somebody else copies something
+    clipboard: Option<Clipboard>,
+    //Whether the window manager speaks EWMH, or only ICCCM
+    ewmh: bool,
+    //When the window went up, and how long it lasted if the recipient was the one to close it
+    opened: Instant,
+    closed_after: Option<Duration>,
+}
+
+impl Scene<'_> {
+    fn closed_by_recipient(&mut self) {
+        println!("Window was asked to close");
+        self.closed_after = Some(self.opened.elapsed());
+    }
+
+    //A fish closed before it finished still gets a report, just without the proof
+    fn into_report(self) -> DrawReport {
+        DrawReport {
+            closed_after: self.closed_after,
+            ..self.report.unwrap_or_default()
+        }
+    }
+}
+
+//Why an event loop stopped
+enum Ending {
+    Closed,
+    //The window's already gone, so there's nothing to take down
+    Destroyed,
+    //n was pressed, so everything gets made again in the next theme
+    Restyle,
+}
+
+impl XFishSession {
+    pub fn connect(address: &str) -> Result<Self, Error> {
+        let started = Instant::now();
+        let display = DisplayAddress::parse(address)?;
+        //x11rb only knows the usual places to look, sockets named outright are ours to open
+        if display.socket.is_some() {
+            let cookie = auth::local_cookie(display.display);
+            return Self::dialed(dial::dial(&display, cookie.as_deref(), None, None, None)?, started);
+        }
+        let address = display.to_string();
+        let (conn, screen_num) = {
+            let _span = tracing::info_span!("connect", address = %metrics::host_hash(&address)).entered();
+            connect(Some(&address)).map_err(|err| auth::explain(err, false))?
+        };
+        Self::dialed(Dialed::new(conn, screen_num, None), started)
+    }
+
+    //For X servers that want a MIT-MAGIC-COOKIE-1, which is most of them over TCP
+    pub fn connect_with_cookie(address: &str, cookie: &[u8]) -> Result<Self, Error> {
+        let started = Instant::now();
+        Self::dialed(dial::dial(&DisplayAddress::parse(address)?, Some(cookie), None, None, None)?, started)
+    }
+
+    //Give up if the display hasn't answered and finished setup within the timeout
+    pub fn connect_with_timeout(address: &str, cookie: Option<&[u8]>, timeout: Duration) -> Result<Self, Error> {
+        let started = Instant::now();
+        Self::dialed(dial::dial(&DisplayAddress::parse(address)?, cookie, Some(timeout), None, None)?, started)
+    }
+
+    //Keep trying a display that refuses or doesn't answer, as often as the policy allows
+    //By whichever route, for displays we can't reach directly
+    //With vetted addresses from AddressPolicy, that's where the first hop goes, without looking the name up again
+    pub fn connect_with_retries(
+        address: &str,
+        cookie: Option<&[u8]>,
+        timeout: Option<Duration>,
+        retry: &RetryPolicy,
+        route: &Route,
+        vetted: Option<&[SocketAddr]>,
+    ) -> Result<Self, Error> {
+        let started = Instant::now();
+        let display = DisplayAddress::parse(address)?;
+        let dialed = dial::dial_with_retries(&display, cookie, timeout, retry, route, vetted)?;
+        let mut session = Self::dialed(dialed, started)?;
+        session.route = route.clone();
+        session.vetted = vetted.map(<[SocketAddr]>::to_vec);
+        Ok(session)
+    }
+
+    //Retries, backoff and X setup all count towards how long connecting took
+    fn dialed(dialed: Dialed, started: Instant) -> Result<Self, Error> {
+        let mut session = Self::setup(dialed.conn, dialed.screen)?;
+        session.connect_attempts = dialed.attempts;
+        session.address_family = dialed.family;
+        session.connect_time = Some(started.elapsed());
+        Ok(session)
+    }
+
+    //Reuse a connection to the same display from an earlier request with the same cookie if there is one
+    //Whatever connection we end up with goes in the pool after a successful draw
+    //Proxied and SSH connections were made with somebody's credentials, so only direct ones get shared
+    pub fn connect_pooled(
+        address: &str,
+        cookie: Option<&[u8]>,
+        timeout: Duration,
+        retry: &RetryPolicy,
+        route: &Route,
+        vetted: Option<&[SocketAddr]>,
+    ) -> Result<Self, Error> {
+        let address = normalize_address(address)?;
+        let key = matches!(route, Route::Direct).then(|| pool::key(&address, cookie));
+        let mut session = match key.as_deref().and_then(|key| pool::take(key, timeout)) {
+            Some((conn, screen_num)) => {
+                let address_hash = metrics::host_hash(&address);
+                tracing::info!(address = %address_hash, screen = screen_num, "reusing a pooled connection");
+                let mut session = Self::setup(conn, screen_num)?;
+                session.connect_attempts = 0;
+                session
+            }
+            None => Self::connect_with_retries(&address, cookie, Some(timeout), retry, route, vetted)?,
+        };
+        session.pool_key = key;
+        Ok(session)
+    }
+
+    fn setup(conn: impl Into<Arc<RustConnection>>, screen_num: usize) -> Result<Self, Error> {
+        let conn = conn.into();
+        let screens = conn.setup().roots.len();
+        if screen_num >= screens {
+            let msg = format!("display only has {} screen(s), there's no screen {}", screens, screen_num);
+            return Err(FishError::BadParams(msg).into());
+        }
+        let atoms = {
+            let _span = tracing::info_span!("intern_atoms").entered();
+            Atoms::new(&*conn)?.reply()?
+        };
+        Ok(XFishSession {
+            conn,
+            screen_num,
+            pool_key: None,
+            connect_attempts: 1,
+            address_family: None,
+            connect_time: None,
+            draw_started: Mutex::new(None),
+            route: Route::Direct,
+            vetted: None,
+            atoms,
+            cancel: Arc::new(AtomicBool::new(false)),
+            on_drawn: None,
+            new_fish: None,
+        })
+    }
+
+    //Share a cancel flag with whoever started the session, setting it ends draw() at the next chance
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    //One fish on every screen of the display at the same time, for setups like :0.0 and :0.1
+    //The other screens get connections of their own, windows sharing one would fight over its events
+    //Results come back in screen order, one screen failing doesn't take the fish off the others
+    pub fn draw_every_screen(
+        self,
+        address: &str,
+        cookie: Option<&[u8]>,
+        timeout: Option<Duration>,
+        fish: &Fish,
+        options: &DrawOptions,
+        deadline: Instant,
+    ) -> Result<Vec<Result<DrawReport, Error>>, Error> {
+        let home = self.screen_num;
+        let mut sessions = Vec::new();
+        for screen_num in (0..self.conn.setup().roots.len()).filter(|&screen_num| screen_num != home) {
+            //Same way in as the first connection, whichever screen the address says is fine, we pick it after
+            //Vetted addresses included, so the other screens can't be sent anywhere the first one couldn't
+            let mut session = match (cookie, timeout, &self.route, &self.vetted) {
+                (cookie, Some(timeout), Route::Direct, None) => Self::connect_with_timeout(address, cookie, timeout)?,
+                (Some(cookie), None, Route::Direct, None) => Self::connect_with_cookie(address, cookie)?,
+                (None, None, Route::Direct, None) => Self::connect(address)?,
+                //Only one try each, the first connection already found the display answering
+                (cookie, timeout, route, vetted) => {
+                    Self::connect_with_retries(address, cookie, timeout, &RetryPolicy::ONCE, route, vetted.as_deref())?
+                }
+            };
+            session.screen_num = screen_num;
+            session.cancel = self.cancel.clone();
+            session.on_drawn = self.on_drawn.clone();
+            session.new_fish = self.new_fish.clone();
+            sessions.push(session);
+        }
+        sessions.insert(home.min(sessions.len()), self);
+
+        Ok(thread::scope(|scope| {
+            let drawings: Vec<_> = sessions
+                .iter()
+                .map(|session| scope.spawn(move || session.draw(fish, options, deadline)))
+                .collect();
+            drawings
+                .into_iter()
+                .map(|drawing| drawing.join().unwrap_or_else(|_| Err("drawing thread panicked".into())))
+                .collect()
+        }))
+    }
+
+    //For callers that want to get on with things once the fish is there, without waiting for it to be closed
+    pub fn with_on_drawn(mut self, on_drawn: OnDrawn) -> Self {
+        self.on_drawn = Some(on_drawn);
+        self
+    }
+
+    //Without one, clicking does nothing, the keys still work
+    pub fn with_new_fish(mut self, new_fish: NewFish) -> Self {
+        self.new_fish = Some(new_fish);
+        self
+    }
+
+    pub(crate) fn drawn(&self, report: &DrawReport) {
+        if let Some(on_drawn) = &self.on_drawn {
+            on_drawn(report);
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.conn.setup().roots[self.screen_num]
+    }
+
+    //Open a window, draw the fish in it, and wait until it is closed or the deadline passes
+    pub fn draw(&self, fish: &Fish, options: &DrawOptions, deadline: Instant) -> Result<DrawReport, Error> {
+        let points: usize = fish.iter().map(Vec::len).sum();
+        let _span = tracing::info_span!(
+            "draw",
+            screen = self.screen_num,
+            lines = fish.len(),
+            points,
+            //Roughly what the lines cost on the wire, before any retries or expose repaints
+            request_bytes = fish.len() * POLY_LINE_HEADER + points * POLY_LINE_POINT,
+        )
+        .entered();
+        let options = &self.place(self.with_contrast(options)).context("working out where the window goes")?;
+        let deadline = deadline.min(Instant::now() + MAX_WINDOW_LIFETIME);
+        if let Ok(mut draw_started) = self.draw_started.lock() {
+            *draw_started = Some(Instant::now());
+        }
+        //Whichever way the fish went up, and even if it was closed before it finished
+        //Only reports made as the fish finished know how long it took, the rest would count the time it was up
+        let result = self.draw_on_target(fish, options, deadline).map(|report| DrawReport {
+            draw_time: report.draw_time,
+            closed_after: report.closed_after,
+            ..self.report(fish, report.window, report.proof_png)
+        });
+        //Connections that errored are left out of the pool, they might be broken
+        if let (Ok(_), Some(key), false) = (&result, &self.pool_key, options.target == Target::Root) {
+            pool::put(key, self.conn.clone(), self.screen_num);
+        }
+        result
+    }
+
+    fn draw_on_target(&self, fish: &Fish, options: &DrawOptions, deadline: Instant) -> Result<DrawReport, Error> {
+        if options.target == Target::Root {
+            return self.draw_root(fish, options).context("drawing on the desktop");
+        }
+        if options.mode == Mode::XTest {
+            return self.draw_xtest(fish, options, deadline).context("drawing with the pointer");
+        }
+        if options.tray {
+            return self.draw_tray(fish, options, deadline).context("swimming in the tray");
+        }
+        if let Some(win_id) = options.window {
+            return self.draw_existing(fish, options, deadline, win_id).context("drawing on their window");
+        }
+        if let Some(query) = &options.find {
+            let win_id = find::find_window(&*self.conn, self.screen(), &self.atoms, query)?;
+            return self.draw_existing(fish, options, deadline, win_id).context("drawing on their window");
+        }
+
+        let conn = &*self.conn;
+        let screen = self.screen();
+        let atoms = &self.atoms;
+        //Servers without an alpha visual just get the usual white window
+        let background = color::alloc_pixel(conn, screen, options.background.as_deref(), screen.white_pixel);
+        let mut surface = if options.transparent {
+            Surface::transparent(conn, screen).context("finding a transparent visual")?.unwrap_or_else(|| {
+                println!("No 32 bit visual for a transparent window, using an opaque one");
+                Surface::opaque(screen, background)
+            })
+        } else {
+            Surface::opaque(screen, background)
+        };
+        //Guards take the window and GC back off the server however we leave, errors included
+        let _colormap = surface.colormap.map(|colormap| ColormapGuard::new(conn, colormap));
+        let ewmh = hints::has_ewmh(conn, atoms, screen.root);
+        if !ewmh {
+            println!("No EWMH window manager, sticking to ICCCM hints");
+        }
+        let window = WindowGuard::new(
+            conn,
+            create_window(conn, screen, atoms, options, &surface, ewmh).context("creating the window")?,
+        );
+        //_NET_WM_ICON is EWMH too, older window managers take a bitmap in WM_HINTS, which isn't worth it for a fish
+        if ewmh {
+            let icon_background = options
+                .background
+                .as_deref()
+                .and_then(|background| color::find_rgb(conn, screen, background));
+            icon::set_icon(
+                conn,
+                atoms,
+                window.id,
+                fish,
+                color::lookup_rgb(conn, screen, options.color.as_deref()),
+                icon_background.unwrap_or((0xffff, 0xffff, 0xffff)),
+            )
+            .context("setting the icon")?;
+        }
+
+        //A new theme keeps the window but needs new everything else
+        let mut options = Cow::Borrowed(options);
+        let mut scene = Scene {
+            fish: Cow::Borrowed(fish),
+            report: None,
+            clipboard: options.clipboard.then(|| Clipboard::new(fish, &options)),
+            ewmh,
+            opened: Instant::now(),
+            closed_after: None,
+        };
+        if let Some(clipboard) = &scene.clipboard {
+            clipboard.claim(conn, atoms, window.id).context("claiming the clipboard")?;
+        }
+        loop {
+            let pen_context = "setting up the lines";
+            let gc = GcGuard::new(conn, conn.generate_id().context(pen_context)?);
+            let foreground = color::alloc_pixel(conn, screen, options.color.as_deref(), screen.black_pixel);
+            let foreground = surface.pixel(foreground);
+            create_line_gc(conn, gc.id, window.id, foreground, &options).context(pen_context)?;
+            let _stipple = color::dither(conn, screen, gc.id, window.id, options.color.as_deref())
+                .context(pen_context)?
+                .map(|stipple| PixmapGuard::new(conn, stipple));
+            let eraser = self.eraser(&options, &surface, window.id).context(pen_context)?;
+            let brush = self.brush(&options, &surface).context(pen_context)?;
+            let _fill = brush.map(|brush| PictureGuard::new(conn, brush.fill));
+            let caption = Caption::new(conn, window.id, &options, foreground, surface.background)
+                .context("setting up the caption")?;
+            let _caption_gc = caption.as_ref().map(|caption| GcGuard::new(conn, caption.gc));
+            let pen = Pen {
+                gc: gc.id,
+                brush,
+                eraser: eraser.as_ref().map(|eraser| eraser.id),
+                palette: self.palette(&options, &surface),
+                caption,
+            };
+
+            let ending = match options.mode {
+                Mode::Still => self.draw_still(&mut scene, &options, deadline, &surface, window.id, &pen),
+                Mode::Aquarium => self.swim(&mut scene, &options, deadline, &surface, window.id, &pen),
+            };
+            let ending = ending.context("drawing the fish")?;
+            match ending {
+                Ending::Closed => return Ok(scene.into_report()),
+                Ending::Destroyed => {
+                    window.forget();
+                    return Ok(scene.into_report());
+                }
+                Ending::Restyle => {
+                    let mut restyled = options.into_owned();
+                    //Picking a theme means picking its colors, not the ones from the request
+                    let theme = theme::Theme::next(restyled.theme);
+                    theme.apply(&mut restyled);
+                    options = Cow::Owned(self.with_contrast(&restyled));
+                    if !options.transparent {
+                        let background =
+                            color::alloc_pixel(conn, screen, options.background.as_deref(), screen.white_pixel);
+                        surface = Surface::opaque(screen, background);
+                        conn.change_window_attributes(
+                            window.id,
+                            &ChangeWindowAttributesAux::new().background_pixel(background),
+                        )
+                        .context("switching themes")?;
+                    }
+                    println!("Switching to the {} theme", theme.name);
+                }
+            }
+        }
+    }
+
+    //A slow fish can take long enough that the window manager starts to wonder about us, so keep answering it
+    //Everything else waits until the fish is done
+    fn answer_pings(&self, pending: &mut VecDeque<Event>) -> Result<(), ConnectionError> {
+        while let Some(event) = self.conn.poll_for_event()? {
+            match event {
+                Event::ClientMessage(event) if hints::is_ping(&self.atoms, &event) => {
+                    hints::answer_ping(&*self.conn, self.screen().root, event)?;
+                }
+                event => pending.push_back(event),
+            }
+        }
+        Ok(())
+    }
+
+    //The first fish to finish gets its proof taken and reported, anything after that was just for fun
+    fn finished(&self, scene: &mut Scene, options: &DrawOptions, win_id: Window, pixmap: Pixmap, size: (u16, u16)) {
+        if scene.report.is_some() {
+            return;
+        }
+        let report = self.report(&scene.fish, Some(win_id), self.take_proof(options, win_id, Some(pixmap), size));
+        //Nice to have, the fish is there either way
+        if options.attention {
+            let root = self.screen().root;
+            if let Err(err) = hints::demand_attention(&*self.conn, &self.atoms, root, win_id, scene.ewmh) {
+                println!("Couldn't ask for attention: {}", err);
+            }
+        }
+        self.drawn(&report);
+        scene.report = Some(report);
+    }
+
+    //Draw the fish once, slowly, then keep it up until the window is closed
+    //Interactive windows also take clicks for a new fish, and keys to change theme or close
+    fn draw_still(
+        &self,
+        scene: &mut Scene,
+        options: &DrawOptions,
+        deadline: Instant,
+        surface: &Surface,
+        win_id: Window,
+        pen: &Pen,
+    ) -> Result<Ending, Error> {
+        let conn = &*self.conn;
+        let atoms = &self.atoms;
+        let keymap = if options.interactive {
+            Some(Keymap::new(conn)?)
+        } else {
+            None
+        };
+
+        //Keep a finished copy of the fish on the server, so re-exposes don't replay the whole animation
+        let size = options.size;
+        let mut lines = fit_fish(&scene.fish, pen.room(size));
+        let mut pixmap = PixmapGuard::new(conn, render_pixmap(conn, surface, win_id, pen, &lines, size)?);
+        if options.shaped {
+            shape::fit_window(conn, win_id, &lines, size, options)?;
+        }
+        //Coming back from a new theme, the window needs wiping and drawing again
+        if scene.report.is_some() {
+            conn.clear_area(true, win_id, 0, 0, 0, 0)?;
+        }
+
+        conn.flush()?;
+
+        //How many strokes are on already, for picking up again after being hidden halfway through
+        let mut progress = 0;
+        //Events that came in while the fish was being drawn, waiting their turn
+        let mut pending = VecDeque::new();
+        let mut state = SessionState::new(atoms, win_id, options.size, deadline);
+
+        let _span = tracing::info_span!("event_loop").entered();
+        loop {
+            //Polling instead of waiting, so a WM that never closes the window can't hang us forever
+            let next = match pending.pop_front() {
+                Some(event) => Some(event),
+                None => conn.poll_for_event()?,
+            };
+            let Some(event) = next else {
+                if let Action::Close(why) = state.idle(Instant::now(), self.cancelled()) {
+                    println!("{}", why);
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            };
+            match state.handle_event(event) {
+                Action::Nothing => {}
+                Action::Animate if options.instant => {
+                    let _span = tracing::info_span!("animate", instant = true).entered();
+                    pen.caption(conn, win_id, state.size())?;
+                    for stroke in pen.plan(&lines) {
+                        pen.stroke(conn, win_id, &stroke)?;
+                    }
+                    conn.flush()?;
+                    state.animated();
+                    self.finished(scene, options, win_id, pixmap.id, state.size());
+                }
+                Action::Animate => {
+                    let _span = tracing::info_span!("animate", instant = false, progress).entered();
+                    pen.caption(conn, win_id, state.size())?;
+                    //Whatever went on before a pause went with the window contents, so that comes back in one go
+                    let mut strokes = pen.plan(&lines).into_iter();
+                    for stroke in strokes.by_ref().take(progress) {
+                        pen.stroke(conn, win_id, &stroke)?;
+                    }
+                    conn.flush()?;
+                    let mut paused = false;
+                    for stroke in strokes {
+                        if self.cancelled() {
+                            break;
+                        }
+                        pen.stroke(conn, win_id, &stroke)?;
+                        thread::sleep(options.line_delay.min(MAX_LINE_DELAY));
+                        conn.flush()?;
+                        progress += 1;
+                        self.answer_pings(&mut pending)?;
+                        //Nobody can see it, so no point drawing slowly for them, carry on at the next expose
+                        if visibility(&pending, win_id) == Some(false) {
+                            paused = true;
+                            break;
+                        }
+                    }
+                    if paused {
+                        println!("Window got hidden, pausing the fish");
+                        continue;
+                    }
+                    state.animated();
+                    progress = 0;
+                    self.finished(scene, options, win_id, pixmap.id, state.size());
+                }
+                //Fish has already been drawn once, just patch up the parts that got uncovered
+                Action::Repair(damage) => {
+                    repair(conn, pixmap.id, win_id, pen.gc, &damage, state.size())?;
+                    conn.flush()?;
+                }
+                //Window got resized, so the fish has to be too
+                Action::Resize(size) => {
+                    lines = fit_fish(&scene.fish, pen.room(size));
+                    //Old pixmap gets freed when its guard is replaced
+                    pixmap = PixmapGuard::new(conn, render_pixmap(conn, surface, win_id, pen, &lines, size)?);
+                    if options.shaped {
+                        shape::fit_window(conn, win_id, &lines, size, options)?;
+                    }
+                    //Clearing with exposures on makes the server send an Expose for the whole window
+                    conn.clear_area(true, win_id, 0, 0, 0, 0)?;
+                    conn.flush()?;
+                }
+                Action::Ping(event) => hints::answer_ping(conn, self.screen().root, event)?,
+                Action::Close(why) => {
+                    println!("{}", why);
+                    break;
+                }
+                Action::Deleted => {
+                    scene.closed_by_recipient();
+                    break;
+                }
+                //Iconified, it'll be back with an expose
+                Action::Unmapped if hints::is_iconic(conn, atoms, win_id) => {}
+                Action::Unmapped => {
+                    println!("Window was unmapped, taking that as closed");
+                    break;
+                }
+                Action::Destroyed => {
+                    println!("Window was destroyed");
+                    return Ok(Ending::Destroyed);
+                }
+                Action::Fail(err) => return Err(FishError::from(err).into()),
+                //A click swaps in a whole new fish, drawn slowly like the first one
+                Action::Other(Event::ButtonPress(_event)) if options.interactive && state.is_animated() => {
+                    let Some(new_fish) = &self.new_fish else {
+                        continue;
+                    };
+                    match new_fish() {
+                        Ok(fish) => {
+                            let size = state.size();
+                            scene.fish = Cow::Owned(fish);
+                            lines = fit_fish(&scene.fish, pen.room(size));
+                            pixmap = PixmapGuard::new(conn, render_pixmap(conn, surface, win_id, pen, &lines, size)?);
+                            if options.shaped {
+                                shape::fit_window(conn, win_id, &lines, size, options)?;
+                            }
+                            state.restart();
+                            progress = 0;
+                            conn.clear_area(true, win_id, 0, 0, 0, 0)?;
+                            conn.flush()?;
+                        }
+                        Err(err) => println!("Couldn't get another fish: {}", err),
+                    }
+                }
+                Action::Other(Event::KeyPress(event)) if options.interactive => {
+                    let keysym = keymap.as_ref().map_or(0, |keymap| keymap.keysym(event.detail));
+                    if keysym == input::KEY_Q {
+                        println!("q was pressed, closing");
+                        break;
+                    }
+                    if keysym == input::KEY_N && state.is_animated() {
+                        return Ok(Ending::Restyle);
+                    }
+                }
+                Action::Other(Event::SelectionRequest(event)) => {
+                    if let Some(clipboard) = &scene.clipboard {
+                        clipboard.answer(conn, atoms, &event)?;
+                    }
+                }
+                //Somebody copied something else, which is fair enough
+                Action::Other(Event::SelectionClear(_)) => scene.clipboard = None,
+                Action::Other(ev) => println!("Got an unknown event: {:?}", ev),
+            }
+        }
+
+        Ok(Ending::Closed)
+    }
+
+    //Make the fish the desktop background, it stays after we hang up
+    fn draw_root(&self, fish: &Fish, options: &DrawOptions) -> Result<DrawReport, Error> {
+        let conn = &*self.conn;
+        let screen = self.screen();
+        let atoms = &self.atoms;
+        let size = (screen.width_in_pixels, screen.height_in_pixels);
+
+        let gc = GcGuard::new(conn, conn.generate_id()?);
+        let foreground = color::alloc_pixel(conn, screen, options.color.as_deref(), screen.black_pixel);
+        create_line_gc(conn, gc.id, screen.root, foreground, options)?;
+        let stipple = color::dither(conn, screen, gc.id, screen.root, options.color.as_deref())?
+            .map(|stipple| PixmapGuard::new(conn, stipple));
+        let background = color::alloc_pixel(conn, screen, options.background.as_deref(), screen.white_pixel);
+        let surface = Surface::opaque(screen, background);
+        let eraser = self.eraser(options, &surface, screen.root)?;
+        let brush = self.brush(options, &surface)?;
+        let fill = brush.map(|brush| PictureGuard::new(conn, brush.fill));
+        let caption = Caption::new(conn, screen.root, options, foreground, surface.background)?;
+        let caption_gc = caption.as_ref().map(|caption| GcGuard::new(conn, caption.gc));
+        let pen = Pen {
+            gc: gc.id,
+            brush,
+            eraser: eraser.as_ref().map(|eraser| eraser.id),
+            palette: self.palette(options, &surface),
+            caption,
+        };
+        //No guard on the pixmap, it has to outlive us to stay on the desktop
+        let pixmap_id = render_pixmap(conn, &surface, screen.root, &pen, &fit_fish(fish, pen.room(size)), size)?;
+        drop(fill);
+        drop(stipple);
+        drop(caption_gc);
+        drop(eraser);
+        drop(gc);
+
+        conn.change_window_attributes(screen.root, &ChangeWindowAttributesAux::new().background_pixmap(pixmap_id))?;
+        conn.clear_area(false, screen.root, 0, 0, 0, 0)?;
+        //Whoever set the last wallpaper like this left their pixmap behind on purpose, same as we're about to
+        //Killing the client it belonged to is how xsetroot and feh free it, or every fish leaves a screenful behind
+        let old = root_pixmap(conn, screen.root, atoms.ESETROOT_PMAP_ID);
+        if let Some(old) = old.filter(|&old| Some(old) == root_pixmap(conn, screen.root, atoms._XROOTPMAP_ID)) {
+            conn.kill_client(old)?.ignore_error();
+        }
+        //Desktop environments and compositors look here to find the wallpaper
+        for atom in [atoms._XROOTPMAP_ID, atoms.ESETROOT_PMAP_ID] {
+            conn.change_property32(PropMode::REPLACE, screen.root, atom, AtomEnum::PIXMAP, &[pixmap_id])?;
+        }
+        //Otherwise the server throws the pixmap away as soon as we disconnect
+        //Which is why this connection never goes back in the pool, everything made on it from now on would stay too
+        conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)?;
+        conn.flush()?;
+
+        Ok(self.report(fish, Some(screen.root), self.take_proof(options, screen.root, Some(pixmap_id), size)))
+    }
+
+    //Everything the sender gets told about their fish, as of now
+    pub(crate) fn report(&self, fish: &Fish, window: Option<Window>, proof_png: Option<Vec<u8>>) -> DrawReport {
+        let setup = self.conn.setup();
+        let draw_started = self.draw_started.lock().ok().and_then(|draw_started| *draw_started);
+        DrawReport {
+            proof_png,
+            connect_attempts: self.connect_attempts,
+            address_family: self.address_family,
+            lines: fish.len(),
+            points: fish.iter().map(Vec::len).sum(),
+            draw_time: draw_started.map(|draw_started| draw_started.elapsed()),
+            connect_time: self.connect_time,
+            vendor: String::from_utf8_lossy(&setup.vendor).into_owned(),
+            release: setup.release_number,
+            window,
+            closed_after: None,
+        }
+    }
+
+    //A background with no fish color gets black or white, whichever shows up on it
+    //Backgrounds the server has never heard of come out white, so those get black
+    fn with_contrast(&self, options: &DrawOptions) -> DrawOptions {
+        let mut options = options.clone();
+        if let (None, Some(background)) = (&options.color, options.background.as_deref()) {
+            let rgb = color::find_rgb(&*self.conn, self.screen(), background).unwrap_or((0xffff, 0xffff, 0xffff));
+            options.color = Some(color::contrasting(rgb).to_string());
+        }
+        options
+    }
+
+    //Pin down where the window goes, so it isn't left straddling two monitors at 0,0
+    //Fullscreen asks the window manager, but it also has to be the right size for the ones that don't listen
+    fn place(&self, mut options: DrawOptions) -> Result<DrawOptions, FishError> {
+        if options.position.is_some() && !options.fullscreen {
+            return Ok(options);
+        }
+        let monitor = monitor::pick(&*self.conn, self.screen(), options.monitor)?;
+        if options.fullscreen {
+            options.position = Some((monitor.x, monitor.y));
+            options.size = (
+                monitor.width.clamp(MIN_WINDOW_SIZE.0, MAX_WINDOW_SIZE.0),
+                monitor.height.clamp(MIN_WINDOW_SIZE.1, MAX_WINDOW_SIZE.1),
+            );
+            if !options.states.contains(&WindowState::Fullscreen) {
+                options.states.push(WindowState::Fullscreen);
+            }
+        } else {
+            let usable = match monitor::work_area(&*self.conn, self.screen(), &self.atoms) {
+                Some(area) => monitor.within(&area),
+                None => monitor,
+            };
+            options.position = Some(usable.center(options.size));
+        }
+        Ok(options)
+    }
+
+    //Smooth lines if they're wanted and the server can do them, otherwise core lines it is
+    //RENDER has no idea what dashes are, so dashed fish get core lines too, and filled ones get core fills
+    //Brushes only come in one color, so anything fancier than a solid palette goes through the GC
+    fn brush(&self, options: &DrawOptions, surface: &Surface) -> Result<Option<Brush>, ReplyOrIdError> {
+        if !options.anti_alias || !options.dashes.is_empty() || options.filled || options.palette != Palette::Solid {
+            return Ok(None);
+        }
+        //Nothing to blend with on a black and white screen
+        if color::strategy(self.screen()) == color::Strategy::Mono {
+            return Ok(None);
+        }
+        Brush::new(&*self.conn, self.screen(), surface, options)
+    }
+
+    //Pixels for each color in the palette, empty means stick with the GC's color
+    fn palette(&self, options: &DrawOptions, surface: &Surface) -> Vec<u32> {
+        let conn = &*self.conn;
+        let screen = self.screen();
+        match options.palette {
+            Palette::Solid => Vec::new(),
+            Palette::Rainbow => (0..style::RAINBOW_STEPS)
+                .map(|step| {
+                    let rgb = color::hsv(style::rainbow_hue(step), 1.0, 1.0);
+                    surface.pixel(color::alloc_rgb(conn, screen, rgb).unwrap_or(screen.black_pixel))
+                })
+                .collect(),
+        }
+    }
+
+    //Filled fish need a second GC in the background color, for the parts that sit inside other parts
+    fn eraser(
+        &self,
+        options: &DrawOptions,
+        surface: &Surface,
+        drawable: Drawable,
+    ) -> Result<Option<GcGuard<'_, RustConnection>>, ReplyOrIdError> {
+        if !options.filled {
+            return Ok(None);
+        }
+        let eraser = GcGuard::new(&*self.conn, self.conn.generate_id()?);
+        create_line_gc(&*self.conn, eraser.id, drawable, surface.background, options)?;
+        Ok(Some(eraser))
+    }
+
+    //Screenshot of what got drawn, when the options ask for one
+    //The window is tried first, since that's what people actually see
+    pub(crate) fn take_proof(
+        &self,
+        options: &DrawOptions,
+        drawable: Drawable,
+        fallback: Option<Drawable>,
+        size: (u16, u16),
+    ) -> Option<Vec<u8>> {
+        if !options.proof {
+            return None;
+        }
+        #[cfg(feature = "png")]
+        return self.capture(drawable, fallback, size);
+        //Nothing to encode it with
+        #[cfg(not(feature = "png"))]
+        {
+            let _ = (drawable, fallback, size);
+            None
+        }
+    }
+}
+
+//GC for the fish's lines, in the color, width and dashes the options ask for
+fn create_line_gc(
+    conn: &impl Connection,
+    gc_id: Gcontext,
+    drawable: Drawable,
+    foreground: u32,
+    options: &DrawOptions,
+) -> Result<(), ConnectionError> {
+    let cap_style = match options.cap {
+        Cap::Butt => CapStyle::BUTT,
+        Cap::Round => CapStyle::ROUND,
+        Cap::Square => CapStyle::PROJECTING,
+    };
+    let line_style = if options.dashes.is_empty() {
+        LineStyle::SOLID
+    } else {
+        LineStyle::ON_OFF_DASH
+    };
+    conn.create_gc(
+        gc_id,
+        drawable,
+        &CreateGCAux::default()
+            .foreground(foreground)
+            .graphics_exposures(0)
+            .line_width(options.line_width as u32)
+            .line_style(line_style)
+            .cap_style(cap_style)
+            .join_style(JoinStyle::ROUND),
+    )?;
+    if !options.dashes.is_empty() {
+        conn.set_dashes(gc_id, 0, &options.dashes)?;
+    }
+    Ok(())
+}
+
+//How lines get drawn: a brush means anti-aliased through RENDER, otherwise core lines with the GC
+//The GC is needed either way, for copying pixmaps around
+//An eraser means the fish gets filled in, with the eraser for the parts drawn in the background color
+//A palette means the GC's color changes from line to line
+#[derive(Debug, Clone)]
+pub(crate) struct Pen {
+    pub gc: Gcontext,
+    pub brush: Option<Brush>,
+    pub eraser: Option<Gcontext>,
+    pub palette: Vec<u32>,
+    pub caption: Option<Caption>,
+}
+
+impl Pen {
+    //The part of the window left for the fish, once the caption has taken its bit off the bottom
+    pub fn room(&self, (width, height): (u16, u16)) -> (u16, u16) {
+        match &self.caption {
+            Some(caption) => (width, height.saturating_sub(caption.height()).max(1)),
+            None => (width, height),
+        }
+    }
+
+    pub fn caption(&self, conn: &impl Connection, drawable: Drawable, size: (u16, u16)) -> Result<(), ReplyOrIdError> {
+        match &self.caption {
+            Some(caption) => caption.draw(conn, drawable, size),
+            None => Ok(()),
+        }
+    }
+
+    //Which order to draw the lines in and how, see fill::plan
+    pub fn plan<'l>(&self, lines: &'l [Vec<Point>]) -> Vec<Stroke<'l>> {
+        fill::plan(lines, self.eraser.is_some())
+    }
+
+    pub fn stroke(&self, conn: &impl Connection, drawable: Drawable, stroke: &Stroke) -> Result<(), ReplyOrIdError> {
+        let gc = match (stroke.erase, self.eraser) {
+            (true, Some(eraser)) => eraser,
+            _ => self.gc,
+        };
+        if gc == self.gc && !self.palette.is_empty() {
+            let foreground = self.palette[stroke.index % self.palette.len()];
+            conn.change_gc(gc, &ChangeGCAux::new().foreground(foreground))?;
+        }
+        //FillPoly can't be split up like PolyLine can, so shapes too big for one request only get their outline
+        let fill_bytes = FILL_POLY_HEADER + POLY_LINE_POINT * stroke.points.len();
+        if stroke.closed && fill_bytes <= conn.maximum_request_bytes() {
+            conn.fill_poly(drawable, gc, PolyShape::COMPLEX, CoordMode::ORIGIN, stroke.points)?;
+        }
+        if gc == self.gc {
+            self.line(conn, drawable, stroke.points)
+        } else {
+            Ok(poly_line(conn, drawable, gc, stroke.points)?)
+        }
+    }
+
+    pub fn line(&self, conn: &impl Connection, drawable: Drawable, points: &[Point]) -> Result<(), ReplyOrIdError> {
+        match &self.brush {
+            Some(brush) => brush.line(conn, drawable, points),
+            None => Ok(poly_line(conn, drawable, self.gc, points)?),
+        }
+    }
+}
+
+//FillPoly is 16 bytes of header, points are the same as PolyLine's
+const FILL_POLY_HEADER: usize = 16;
+//PolyLine is 12 bytes of header and 4 bytes per point
+const POLY_LINE_HEADER: usize = 12;
+const POLY_LINE_POINT: usize = 4;
+
+//Draw a line, split over as many requests as it takes to fit the server's max request size
+//Each chunk starts where the last one ended, so the line stays connected
+//x11rb turns on BIG-REQUESTS by itself if the server has it, which makes the limit a lot bigger
+pub(crate) fn poly_line(
+    conn: &impl Connection,
+    drawable: Drawable,
+    gc_id: Gcontext,
+    points: &[Point],
+) -> Result<(), ConnectionError> {
+    let max_points = (conn.maximum_request_bytes().saturating_sub(POLY_LINE_HEADER) / POLY_LINE_POINT).max(2);
+    if points.len() <= max_points {
+        conn.poly_line(CoordMode::ORIGIN, drawable, gc_id, points)?;
+        return Ok(());
+    }
+    let mut start = 0;
+    while start + 1 < points.len() {
+        let end = (start + max_points).min(points.len());
+        conn.poly_line(CoordMode::ORIGIN, drawable, gc_id, &points[start..end])?;
+        start = end - 1;
+    }
+    Ok(())
+}
+
+//Draw the whole fish into a fresh pixmap, ready to be copied to the window
+fn render_pixmap(
+    conn: &impl Connection,
+    surface: &Surface,
+    win_id: Window,
+    pen: &Pen,
+    lines: &[Vec<Point>],
+    (width, height): (u16, u16),
+) -> Result<Pixmap, ReplyOrIdError> {
+    let pixmap_id = conn.generate_id()?;
+    conn.create_pixmap(surface.depth, pixmap_id, win_id, width, height)?;
+    paint_pixmap(conn, surface, pixmap_id, pen, lines, (width, height))?;
+    Ok(pixmap_id)
+}
+
+//Wipe a pixmap back to the background and draw the lines on it
+fn paint_pixmap(
+    conn: &impl Connection,
+    surface: &Surface,
+    pixmap_id: Pixmap,
+    pen: &Pen,
+    lines: &[Vec<Point>],
+    (width, height): (u16, u16),
+) -> Result<(), ReplyOrIdError> {
+    let background_gc_id = conn.generate_id()?;
+    conn.create_gc(
+        background_gc_id,
+        pixmap_id,
+        &CreateGCAux::default().foreground(surface.background),
+    )?;
+    conn.poly_fill_rectangle(
+        pixmap_id,
+        background_gc_id,
+        &[Rectangle { x: 0, y: 0, width, height }],
+    )?;
+    conn.free_gc(background_gc_id)?;
+    for stroke in pen.plan(lines) {
+        pen.stroke(conn, pixmap_id, &stroke)?;
+    }
+    pen.caption(conn, pixmap_id, (width, height))?;
+    Ok(())
+}
+
+//Whether the latest news in the queue says the window can be seen, if there's any news at all
+fn visibility(pending: &VecDeque<Event>, win_id: Window) -> Option<bool> {
+    pending.iter().rev().find_map(|event| match event {
+        Event::UnmapNotify(event) if event.window == win_id => Some(false),
+        Event::MapNotify(event) if event.window == win_id => Some(true),
+        Event::VisibilityNotify(event) if event.window == win_id => Some(event.state != Visibility::FULLY_OBSCURED),
+        _ => None,
+    })
+}
+
+//Copy just the damaged parts of the finished fish back onto the window, in one request
+//The clip has to come off again afterwards, the same GC draws the lines
+fn repair(
+    conn: &impl Connection,
+    pixmap_id: Pixmap,
+    win_id: Window,
+    gc_id: Gcontext,
+    damage: &[Rectangle],
+    (width, height): (u16, u16),
+) -> Result<(), ConnectionError> {
+    conn.set_clip_rectangles(ClipOrdering::UNSORTED, gc_id, 0, 0, damage)?;
+    conn.copy_area(pixmap_id, win_id, gc_id, 0, 0, 0, 0, width, height)?;
+    conn.change_gc(gc_id, &ChangeGCAux::new().clip_mask(NONE))?;
+    Ok(())
+}
+
+fn create_window(
+    conn: &impl Connection,
+    screen: &Screen,
+    atoms: &Atoms,
+    options: &DrawOptions,
+    surface: &Surface,
+    ewmh: bool,
+) -> Result<Window, ReplyOrIdError> {
+    let (width, height) = options.size;
+    let (x, y) = options.position.unwrap_or_default();
+    let _span = tracing::info_span!("create_window", width, height, depth = surface.depth).entered();
+    let win_id = conn.generate_id()?;
+    //Visibility is for pausing the slow drawing while nobody can see it
+    let mut event_mask = EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY | EventMask::VISIBILITY_CHANGE;
+    if options.interactive {
+        event_mask |= EventMask::BUTTON_PRESS | EventMask::KEY_PRESS;
+    }
+    //Swimming fish follow the pointer around the tank
+    if options.interactive && options.mode == Mode::Aquarium {
+        event_mask |= EventMask::POINTER_MOTION | EventMask::LEAVE_WINDOW;
+    }
+    let mut win_aux = CreateWindowAux::new()
+        .event_mask(event_mask)
+        .background_pixel(surface.background);
+    //A window with a different depth than its parent can't borrow the parent's border or colormap
+    if let Some(colormap) = surface.colormap {
+        win_aux = win_aux.border_pixel(0).colormap(colormap);
+    }
+    //Nobody will be around to close it, so the deadline is what takes it down
+    if options.popup {
+        win_aux = win_aux.override_redirect(1);
+    }
+
+    conn.create_window(
+        surface.depth,
+        win_id,
+        screen.root,
+        x,
+        y,
+        width,
+        height,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        surface.visual,
+        &win_aux,
+    )?;
+
+    //WM_NAME is a STRING, which means Latin-1, newer window managers read the UTF-8 one instead
+    conn.change_property8(
+        PropMode::REPLACE,
+        win_id,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        &caption::latin1(&options.title),
+    )?;
+    if ewmh {
+        conn.change_property8(
+            PropMode::REPLACE,
+            win_id,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            options.title.as_bytes(),
+        )?;
+    }
+    //Two NUL terminated strings back to back
+    let (instance, class) = &options.class;
+    let mut wm_class = caption::latin1(instance);
+    wm_class.push(0);
+    wm_class.extend(caption::latin1(class));
+    wm_class.push(0);
+    conn.change_property8(PropMode::REPLACE, win_id, AtomEnum::WM_CLASS, AtomEnum::STRING, &wm_class)?;
+    //No _NET_WM_PID to go with the ping, it'd be our PID on a machine that isn't theirs,
+    //and a window manager that decided to kill it would kill some random process of theirs instead
+    let protocols: &[Atom] = if ewmh {
+        &[atoms.WM_DELETE_WINDOW, atoms._NET_WM_PING]
+    } else {
+        &[atoms.WM_DELETE_WINDOW]
+    };
+    conn.change_property32(PropMode::REPLACE, win_id, atoms.WM_PROTOCOLS, AtomEnum::ATOM, protocols)?;
+
+    //Window managers mostly ignore the position we create the window at, unless the hints say we meant it
+    //Min size and aspect ratio keep the fish from getting squashed into nothing
+    //Monitors don't come in fish proportions though, so fullscreen goes without the aspect ratio
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let aspect = AspectRatio::new(canvas_width as i32, canvas_height as i32);
+    WmSizeHints {
+        position: Some((WmSizeHintsSpecification::UserSpecified, x as i32, y as i32)),
+        size: Some((WmSizeHintsSpecification::UserSpecified, width as i32, height as i32)),
+        min_size: Some((MIN_WINDOW_SIZE.0 as i32, MIN_WINDOW_SIZE.1 as i32)),
+        aspect: (!options.fullscreen).then_some((aspect, aspect)),
+        ..WmSizeHints::default()
+    }
+    .set_normal_hints(conn, win_id)?;
+    //Window types and states are EWMH only, an ICCCM window manager gets a plain window
+    if ewmh {
+        hints::set_hints(conn, atoms, win_id, options.window_type, &options.states)?;
+    }
+
+    conn.map_window(win_id)?;
+    //Without a window manager to stack it, make sure it comes up on top of everything else
+    if options.popup {
+        conn.configure_window(win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+    }
+    if ewmh {
+        hints::request_states(conn, atoms, screen.root, win_id, &options.states)?;
+    }
+
+    Ok(win_id)
+}
@@ -1,186 +1,1406 @@
-use lambda_http::{service_fn, tracing, Error, IntoResponse, Request, RequestExt};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Datelike, Utc};
+use lambda_http::http::{HeaderValue, Method};
+use lambda_http::request::RequestContext;
+use lambda_http::{service_fn, tracing, Body, Error, IntoResponse, Request, RequestExt, Response};
 use reqwest::StatusCode;
 use std::convert::Infallible;
-use std::thread;
-use std::time::Duration;
-use x11rb::connection::Connection;
-use x11rb::errors::ReplyOrIdError;
-use x11rb::protocol::xproto::{
-    AtomEnum, ConnectionExt, CoordMode, CreateGCAux, CreateWindowAux, Point, PropMode, Screen, Window, WindowClass,
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, OnceCell, Semaphore};
+use tokio::task::JoinSet;
+use x11_make_a_fish::apikeys::ApiKeys;
+use x11_make_a_fish::ascii::AsciiOptions;
+use x11_make_a_fish::audit::{AuditEntry, AuditLog};
+use x11_make_a_fish::cors::{self, CorsPolicy};
+use x11_make_a_fish::dial::RetryPolicy;
+use x11_make_a_fish::drawings::{self, Drawings};
+use x11_make_a_fish::idempotency::{Claim, IdempotencyKey, IdempotencyStore, StoredResponse};
+use x11_make_a_fish::metrics::DrawMetrics;
+#[cfg(feature = "prometheus")]
+use x11_make_a_fish::metrics::prometheus;
+use x11_make_a_fish::ratelimit::dynamo::DynamoRateLimiter;
+use x11_make_a_fish::ratelimit::RateLimiter;
+use x11_make_a_fish::request::RequestConfig;
+use x11_make_a_fish::schedule::ScheduleQueue;
+use x11_make_a_fish::signing::LinkSigner;
+use x11_make_a_fish::ssh::{SshKeys, SshLogin};
+use x11_make_a_fish::stats::FishCounter;
+use x11_make_a_fish::store::FishStore;
+use x11_make_a_fish::transform::Transform;
+use x11_make_a_fish::{
+    ascii, auth, clock, creature, dial, fish_csv, fit_to_canvas, generator, gif, normalize_address, overlay, png, props,
+    school, secrets, svg, upload, AddressPolicy, DrawOptions, DrawReport, Family, Fish, FishError, OnDrawn, Route,
+    Target, XFishSession, MAX_WINDOW_LIFETIME,
 };
-use x11rb::protocol::Event;
-use x11rb::wrapper::ConnectionExt as _;
-use x11rb::{atom_manager, connect};
 
-use x11rb::protocol::xproto::EventMask;
+//How long to keep the fish up if neither Lambda nor the user gave a time limit
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+//Leave some time to clean up and respond before Lambda kills the invocation
+const DEADLINE_SLACK: Duration = Duration::from_millis(500);
 
-atom_manager! {
-    pub Atoms: AtomsCookie {
-        UTF8_STRING,
-        WM_DELETE_WINDOW,
-        WM_PROTOCOLS,
-        _NET_WM_NAME,
-    }
-}
+//Most displays one batch request can send fish to, and how many of them get drawn at once
+const MAX_BATCH_ADDRESSES: usize = 32;
+const BATCH_CONCURRENCY: usize = 8;
+
+//The sender's webhook gets this long to take the news, the window's already gone either way
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+//What a scheduled fish needs left after waiting, to connect and be up for a bit, or it goes on the queue instead
+const SCHEDULED_DRAW_TIME: Duration = Duration::from_secs(10);
+//Longest a fish gets held in memory without Lambda's deadline to go by, past that it's the queue or nothing
+//Otherwise anyone could keep requests and connections open for months by asking for a fish far enough off
+const MAX_HELD_WAIT: Duration = Duration::from_secs(5 * 60);
+
+//Set up once per container, loading AWS config every request would be slow
+static DYNAMO_LIMITER: OnceCell<Option<DynamoRateLimiter>> = OnceCell::const_new();
+static FISH_STORE: OnceCell<Option<FishStore>> = OnceCell::const_new();
+static SSH_KEYS: OnceCell<Option<SshKeys>> = OnceCell::const_new();
+static SCHEDULE_QUEUE: OnceCell<Option<ScheduleQueue>> = OnceCell::const_new();
+static IDEMPOTENCY: OnceCell<Option<IdempotencyStore>> = OnceCell::const_new();
+static AUDIT_LOG: OnceCell<Option<AuditLog>> = OnceCell::const_new();
+static FISH_COUNTER: OnceCell<Option<FishCounter>> = OnceCell::const_new();
+static LINK_SIGNER: OnceCell<Option<LinkSigner>> = OnceCell::const_new();
+static API_KEYS: OnceCell<Option<ApiKeys>> = OnceCell::const_new();
+static DRAWINGS: OnceCell<Drawings> = OnceCell::const_new();
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     // required to enable CloudWatch error logging by the runtime
     tracing::init_default_subscriber();
 
-    let func = service_fn(handler);
-    lambda_http::run(func).await?;
+    //Server builds still work as a Lambda, it's only outside one that they listen for themselves
+    #[cfg(feature = "server")]
+    if std::env::var_os("AWS_LAMBDA_RUNTIME_API").is_none() {
+        return server::run().await;
+    }
+    if cfg!(feature = "sqs") {
+        lambda_runtime::run(lambda_runtime::service_fn(sqs_handler)).await?;
+    } else {
+        lambda_http::run(service_fn(|event| with_cors(event, |event| with_api_key(event, handler)))).await?;
+    }
     Ok(())
 }
 
-pub(crate) async fn handler(event: Request) -> Result<impl IntoResponse, Infallible> {
-    match handle_response(event).await {
-        Ok(res) => Ok(res.into_response().await),
-        Err(err) => Ok((StatusCode::BAD_REQUEST, format!("Error: {}", err))
-            .into_response()
-            .await),
+//The same handler over plain HTTP, listening on XFISH_LISTEN (0.0.0.0:8080 unless it says otherwise)
+#[cfg(feature = "server")]
+mod server {
+    use super::{handler, with_api_key, with_cors};
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::{Bytes, Incoming};
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use lambda_http::http::HeaderValue;
+    use lambda_http::{Body, Error, Request, RequestExt};
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    const DEFAULT_LISTEN: &str = "0.0.0.0:8080";
+
+    pub async fn run() -> Result<(), Error> {
+        let listen = std::env::var("XFISH_LISTEN").unwrap_or_else(|_| DEFAULT_LISTEN.to_string());
+        let listener = TcpListener::bind(&listen).await?;
+        println!("Making fish on {}", listen);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tokio::spawn(async move {
+                let service = hyper::service::service_fn(move |request| serve(request, peer));
+                if let Err(err) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                    println!("Connection from {} went wrong: {}", peer, err);
+                }
+            });
+        }
+    }
+
+    //Dressed up the way lambda_http would hand it over, so the handler can't tell the difference
+    async fn serve(
+        request: hyper::Request<Incoming>,
+        peer: SocketAddr,
+    ) -> Result<hyper::Response<Full<Bytes>>, Infallible> {
+        let (mut parts, body) = request.into_parts();
+        let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(err) => {
+                println!("Couldn't read a request from {}: {}", peer, err);
+                let mut response = hyper::Response::new(Full::new(Bytes::new()));
+                *response.status_mut() = hyper::StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+        };
+        //Like a load balancer would, so the sender's address is the last one there
+        //Behind a reverse proxy that's the proxy, which then needs to do the same
+        let forwarded = match parts.headers.get("x-forwarded-for").and_then(|value| value.to_str().ok()) {
+            Some(forwarded) => format!("{}, {}", forwarded, peer.ip()),
+            None => peer.ip().to_string(),
+        };
+        if let Ok(forwarded) = HeaderValue::from_str(&forwarded) {
+            parts.headers.insert("x-forwarded-for", forwarded);
+        }
+        let mut query: HashMap<String, Vec<String>> = HashMap::new();
+        if let Some(pairs) = parts.uri.query() {
+            for (name, value) in form_urlencoded::parse(pairs.as_bytes()) {
+                query.entry(name.into_owned()).or_default().push(value.into_owned());
+            }
+        }
+        let path = parts.uri.path().to_string();
+        let body = if body.is_empty() {
+            Body::Empty
+        } else {
+            Body::Binary(body.to_vec())
+        };
+        let event = Request::from_parts(parts, body)
+            .with_query_string_parameters(query)
+            .with_raw_http_path(path);
+
+        let response = with_cors(event, |event| with_api_key(event, handler)).await?;
+        let (parts, body) = response.into_parts();
+        Ok(hyper::Response::from_parts(parts, Full::new(Bytes::copy_from_slice(body.as_ref()))))
     }
 }
 
-pub(crate) async fn handle_response(event: Request) -> Result<impl IntoResponse, Error> {
-    //Get the address of the X11 server from URL params
-    let Some(mut address) = event
-        .query_string_parameters_ref()
-        .and_then(|params| params.first("address"))
-        .and_then(|addr| Some(addr.to_string()))
-    else {
-        return Err("need address in query params".into());
+//Outermost, so preflights get answered before anything wants a key from them, and errors get the headers too
+async fn with_cors<F, Fut>(event: Request, next: F) -> Result<Response<Body>, Infallible>
+where
+    F: FnOnce(Request) -> Fut,
+    Fut: Future<Output = Result<Response<Body>, Infallible>>,
+{
+    let policy = CorsPolicy::from_env();
+    let origin = event
+        .headers()
+        .get("origin")
+        .and_then(|origin| origin.to_str().ok())
+        .map(str::to_string);
+    let mut response = if event.method() == Method::OPTIONS {
+        let mut response = Response::new(Body::Empty);
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        let headers = response.headers_mut();
+        if let Ok(methods) = HeaderValue::from_str(&policy.methods) {
+            headers.insert("access-control-allow-methods", methods);
+        }
+        if let Ok(allowed) = HeaderValue::from_str(&policy.headers) {
+            headers.insert("access-control-allow-headers", allowed);
+        }
+        headers.insert("access-control-max-age", cors::MAX_AGE.into());
+        response
+    } else {
+        next(event).await?
+    };
+    let headers = response.headers_mut();
+    //An origin we don't know just doesn't get told it's allowed, the browser does the rest
+    let allowed = policy.allow_origin(origin.as_deref());
+    if let Some(allowed) = allowed.and_then(|allowed| HeaderValue::from_str(&allowed).ok()) {
+        headers.insert("access-control-allow-origin", allowed);
+        headers.insert("access-control-expose-headers", HeaderValue::from_static(cors::EXPOSE_HEADERS));
+    }
+    if policy.varies() {
+        headers.append("vary", HeaderValue::from_static("origin"));
+    }
+    Ok(response)
+}
+
+//Wraps a handler in the x-api-key check, so everything behind it is covered without having to remember to
+//A key list that won't load keeps everyone out, same as signing secrets
+async fn with_api_key<F, Fut>(event: Request, next: F) -> Result<Response<Body>, Infallible>
+where
+    F: FnOnce(Request) -> Fut,
+    Fut: Future<Output = Result<Response<Body>, Infallible>>,
+{
+    match API_KEYS.get_or_try_init(load_api_keys).await {
+        Ok(Some(keys)) => {
+            let given = event.headers().get("x-api-key").and_then(|key| key.to_str().ok());
+            if let Err(err) = keys.check(given) {
+                return Ok(error_response(err.into()));
+            }
+        }
+        Ok(None) => {}
+        Err(err) => return Ok(error_response(err)),
+    }
+    next(event).await
+}
+
+async fn load_api_keys() -> Result<Option<ApiKeys>, Error> {
+    let keys = secrets::from_env("XFISH_API_KEYS").await?;
+    Ok(keys.map(|keys| ApiKeys::parse(&keys)).filter(|keys| !keys.is_empty()))
+}
+
+pub(crate) async fn handler(event: Request) -> Result<Response<Body>, Infallible> {
+    //Retries with the same Idempotency-Key get the first answer, not another fish
+    let given = event.headers().get("idempotency-key").and_then(|key| key.to_str().ok());
+    let store = match given {
+        Some(_) => IDEMPOTENCY.get_or_init(IdempotencyStore::from_env).await.as_ref(),
+        None => None,
+    };
+    let key = match (given, store) {
+        (Some(given), Some(_)) => {
+            //API keys are secrets, so the table only ever sees a SHA-256 of them
+            let caller = match event.headers().get("x-api-key").and_then(|key| key.to_str().ok()) {
+                Some(api_key) => {
+                    let hash = openssl::sha::sha256(api_key.trim().as_bytes());
+                    format!("key:{}", hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+                }
+                None => format!("ip:{}", source_ip(&event).unwrap_or_default()),
+            };
+            let uri = event.uri().to_string();
+            let request = [event.method().as_str().as_bytes(), uri.as_bytes(), event.body().as_ref()];
+            match IdempotencyKey::new(given, &caller, &request) {
+                Ok(key) => Some(key),
+                Err(err) => return Ok(error_response(err.into())),
+            }
+        }
+        _ => None,
+    };
+    if let (Some(key), Some(store)) = (&key, store) {
+        match store.begin(key, in_flight_for(&event)).await {
+            Claim::New => {}
+            Claim::InFlight => {
+                let msg = "a request with that Idempotency-Key is still going, try again in a bit";
+                return Ok(error_response(FishError::Conflict(msg.to_string()).into()));
+            }
+            Claim::Done(Some(stored)) => return Ok(replay(stored)),
+            Claim::Done(None) => {
+                let msg = "that Idempotency-Key was already used, and its answer was too big to keep";
+                return Ok(error_response(FishError::Conflict(msg.to_string()).into()));
+            }
+            Claim::Mismatch => {
+                let msg = "that Idempotency-Key was already used for a different request, try a new one";
+                return Ok(error_response(FishError::Conflict(msg.to_string()).into()));
+            }
+        }
+    }
+
+    let response = match handle_response(event).await {
+        Ok(res) => res.into_response().await,
+        Err(err) => error_response(err),
+    };
+    flush_counts().await;
+    //Server trouble, timeouts and rate limits might go better next time, so those don't get kept
+    let status = response.status();
+    let keep = (status.is_success() || status.is_client_error()) && status != StatusCode::TOO_MANY_REQUESTS;
+    if let (Some(key), Some(store), false) = (&key, store, keep) {
+        if let Err(err) = store.release(key).await {
+            println!("Couldn't let go of an idempotency key: {}", err);
+        }
+    } else if let (Some(key), Some(store)) = (&key, store) {
+        let stored = StoredResponse {
+            status: response.status().as_u16(),
+            content_type: response
+                .headers()
+                .get("content-type")
+                .and_then(|content_type| content_type.to_str().ok())
+                .map(str::to_string),
+            body: response.body().as_ref().to_vec(),
+        };
+        if let Err(err) = store.finish(key, &stored).await {
+            println!("Couldn't remember the answer for an idempotency key: {}", err);
+        }
+    }
+    Ok(response)
+}
+
+//How long a request could still be going, so nobody else gets its Idempotency-Key while it is
+fn in_flight_for(event: &Request) -> Duration {
+    //Nothing can still be going once Lambda has stopped us
+    if let Some(left) = time_left(event) {
+        return left + DEADLINE_SLACK;
+    }
+    //Otherwise it's as long as the window it asked for, and any waiting for its time to come first
+    let body_json = serde_json::from_slice::<serde_json::Value>(event.body().as_ref())
+        .ok()
+        .filter(serde_json::Value::is_object);
+    let query = event.query_string_parameters_ref().into_iter().flat_map(|params| params.iter());
+    let Ok(config) = RequestConfig::from_params(query, body_json.as_ref()) else {
+        //It won't get as far as drawing
+        return DEFAULT_TTL;
+    };
+    let held = if config.deliver_at.is_some() || config.at_1111 {
+        MAX_HELD_WAIT
+    } else {
+        Duration::ZERO
+    };
+    let drawing = deadline_for(event, config.ttl)
+        .saturating_duration_since(Instant::now())
+        .min(MAX_WINDOW_LIFETIME);
+    held + drawing + CALLBACK_TIMEOUT + DEADLINE_SLACK
+}
+
+//The answer the first request with the key got, marked so clients can tell
+fn replay(stored: StoredResponse) -> Response<Body> {
+    let mut response = Response::builder()
+        .status(stored.status)
+        .header("idempotent-replayed", "true");
+    if let Some(content_type) = &stored.content_type {
+        response = response.header("content-type", content_type);
+    }
+    let body = match String::from_utf8(stored.body) {
+        Ok(text) => Body::Text(text),
+        Err(err) => Body::Binary(err.into_bytes()),
+    };
+    response.body(body).unwrap_or_else(|err| error_response(err.into()))
+}
+
+//Marks requests that came off the queue, which only we and whoever IAM lets send to it can put things on
+//Held fish had their signed links checked when they were queued, and the link may well have expired since
+#[derive(Debug, Clone, Copy)]
+struct FromQueue;
+
+//Queued fish, each message body is what a POST would bring, settings under "config" and maybe "addresses"
+//Anything that might go better next time goes back on the queue, and on to the dead letter queue if it never does
+//Requests that were wrong to begin with get dropped, they'd only fail the same way again
+//The event source mapping needs ReportBatchItemFailures turned on, or one failure retries the whole batch
+async fn sqs_handler(event: lambda_runtime::LambdaEvent<serde_json::Value>) -> Result<serde_json::Value, Error> {
+    let records = event.payload.get("Records").and_then(|records| records.as_array()).cloned();
+    let mut deliveries = JoinSet::new();
+    for record in records.unwrap_or_default() {
+        let field = |name: &str| record.get(name).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+        let (id, body) = (field("messageId"), field("body"));
+        let mut request = Request::new(Body::Text(body)).with_lambda_context(event.context.clone());
+        request.extensions_mut().insert(FromQueue);
+        deliveries.spawn(async move {
+            let Err(err) = handle_response(request).await else {
+                return None;
+            };
+            let (status, code, message, _) = describe(err);
+            println!("Queued fish {} wasn't delivered ({}): {}", id, code, message);
+            (status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS).then_some(id)
+        });
+    }
+    let mut failures = Vec::new();
+    while let Some(joined) = deliveries.join_next().await {
+        if let Some(id) = joined? {
+            failures.push(serde_json::json!({ "itemIdentifier": id }));
+        }
+    }
+    flush_counts().await;
+    Ok(serde_json::json!({ "batchItemFailures": failures }))
+}
+
+//Every error gets a status that says whose fault it was, and a code clients can match on
+fn describe(err: Error) -> (StatusCode, &'static str, String, Option<Duration>) {
+    match FishError::classify(err) {
+        Ok(err) => {
+            let status = match err {
+                FishError::BadParams(_) => StatusCode::BAD_REQUEST,
+                FishError::AuthRejected(_) => StatusCode::UNAUTHORIZED,
+                FishError::AddressForbidden(_) => StatusCode::FORBIDDEN,
+                FishError::DnsFailure(_)
+                | FishError::ConnectRefused(_)
+                | FishError::Protocol(_)
+                | FishError::Generator(_) => StatusCode::BAD_GATEWAY,
+                FishError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+                FishError::RateLimited(..) => StatusCode::TOO_MANY_REQUESTS,
+                FishError::NotFound(_) => StatusCode::NOT_FOUND,
+                FishError::Gone(_) => StatusCode::GONE,
+                FishError::AccessDenied(_) => StatusCode::FORBIDDEN,
+                FishError::OutOfResources(_) => StatusCode::SERVICE_UNAVAILABLE,
+                FishError::Conflict(_) => StatusCode::CONFLICT,
+            };
+            let retry_after = match err {
+                FishError::RateLimited(_, retry_after) => Some(retry_after),
+                _ => None,
+            };
+            (status, err.code(), err.to_string(), retry_after)
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, "internal", err.to_string(), None),
+    }
+}
+
+fn error_response(err: Error) -> Response<Body> {
+    let (status, code, message, retry_after) = describe(err);
+    let body = serde_json::json!({ "error": { "code": code, "message": message } });
+    let mut response = Response::builder()
+        .status(status)
+        .header("content-type", "application/json");
+    if let Some(retry_after) = retry_after {
+        response = response.header("retry-after", retry_after.as_secs().to_string());
+    }
+    response
+        .body(Body::Text(body.to_string()))
+        .expect("status and headers are always valid")
+}
+
+async fn load_signer() -> Result<Option<LinkSigner>, Error> {
+    Ok(secrets::from_env("XFISH_SIGNING_SECRET").await?.map(LinkSigner::new))
+}
+
+//The fish counter only writes once a request is done with, however many fish it sent
+async fn flush_counts() {
+    if let Some(counter) = FISH_COUNTER.get_or_init(FishCounter::from_env).await {
+        if let Err(err) = counter.flush().await {
+            println!("Couldn't update the fish counter: {}", err);
+        }
+    }
+}
+
+//How many fish, how many of them made it, and what went wrong with the rest
+async fn stats_response() -> Result<Response<Body>, Error> {
+    let Some(counter) = FISH_COUNTER.get_or_init(FishCounter::from_env).await else {
+        return Err(FishError::NotFound("this fish service doesn't count its fish".to_string()).into());
+    };
+    let stats = counter.stats().await?;
+    Ok(Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::Text(stats.to_json().to_string()))?)
+}
+
+//Everything we answer to, by path
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endpoint {
+    Draw,
+    Daily,
+    Health,
+    FishSvg,
+    FishPng,
+    Stats,
+    #[cfg(feature = "prometheus")]
+    Metrics,
+}
+
+impl Endpoint {
+    //The root is drawing too, it's where the web page has always sent fish
+    fn find(path: &str) -> Option<Self> {
+        match path.trim_end_matches('/') {
+            "" | "/draw" => Some(Endpoint::Draw),
+            "/daily" => Some(Endpoint::Daily),
+            "/health" => Some(Endpoint::Health),
+            "/fish.svg" => Some(Endpoint::FishSvg),
+            "/fish.png" => Some(Endpoint::FishPng),
+            "/stats" => Some(Endpoint::Stats),
+            #[cfg(feature = "prometheus")]
+            "/metrics" => Some(Endpoint::Metrics),
+            _ => None,
+        }
+    }
+
+    //Preflights never get this far, CORS answers those
+    fn methods(self) -> &'static [Method] {
+        match self {
+            Endpoint::Draw | Endpoint::Daily | Endpoint::FishSvg | Endpoint::FishPng => &[Method::GET, Method::POST],
+            Endpoint::Health | Endpoint::Stats => &[Method::GET],
+            #[cfg(feature = "prometheus")]
+            Endpoint::Metrics => &[Method::GET],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Endpoint::Draw => "draw",
+            Endpoint::Daily => "daily",
+            Endpoint::Health => "health",
+            Endpoint::FishSvg => "fish.svg",
+            Endpoint::FishPng => "fish.png",
+            Endpoint::Stats => "stats",
+            #[cfg(feature = "prometheus")]
+            Endpoint::Metrics => "metrics",
+        }
+    }
+}
+
+fn method_not_allowed(endpoint: Endpoint) -> Response<Body> {
+    let allowed: Vec<&str> = endpoint.methods().iter().map(Method::as_str).collect();
+    let message = format!("/{} only takes {}", endpoint.name(), allowed.join(" or "));
+    let body = serde_json::json!({ "error": { "code": "method_not_allowed", "message": message } });
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header("allow", allowed.join(", "))
+        .header("content-type", "application/json")
+        .body(Body::Text(body.to_string()))
+        .expect("status and headers are always valid")
+}
+
+//A canary for deploys, a whole fish made and rendered start to finish with everything but the X server
+//Each step gets timed, and a failure says which step it was with a 503 so pipelines notice
+async fn health_response() -> Result<Response<Body>, Error> {
+    let started = Instant::now();
+    let mut timings = serde_json::Map::new();
+    let mut step = |name: &str, since: Instant| {
+        timings.insert(format!("{}_ms", name), (since.elapsed().as_millis() as u64).into());
+        Instant::now()
     };
+    let result: Result<(usize, usize), (&str, Error)> = async {
+        let now = Instant::now();
+        let csv = generator::generate_csv(generator::random_seed()).await.map_err(|err| ("generate", err))?;
+        let now = step("generate", now);
+        let fish = fish_csv::parse(&csv).map_err(|err| ("parse", err.into()))?;
+        let now = step("parse", now);
+        let png = png::render_png(&fish, &DrawOptions::default()).map_err(|err| ("render", err))?;
+        step("render", now);
+        Ok((fish.len(), png.len()))
+    }
+    .await;
+    let total_ms = started.elapsed().as_millis() as u64;
+    let (status, body) = match result {
+        Ok((lines, png_bytes)) => (
+            StatusCode::OK,
+            serde_json::json!({
+                "ok": true,
+                "version": env!("CARGO_PKG_VERSION"),
+                "lines": lines,
+                "png_bytes": png_bytes,
+                "total_ms": total_ms,
+                "timings": timings,
+            }),
+        ),
+        Err((failed, err)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({
+                "ok": false,
+                "version": env!("CARGO_PKG_VERSION"),
+                "failed": failed,
+                "error": err.to_string(),
+                "total_ms": total_ms,
+                "timings": timings,
+            }),
+        ),
+    };
+    Ok(Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::Text(body.to_string()))?)
+}
 
-    //Similar process to check if clientside JS reported that it is 11:11
-    //If param is missing, it is probably Mia testing code, so send a fish anyway
-    let fish_str = match event.query_string_parameters_ref().unwrap().first("time") {
-        Some("bad") => {
-            include_str!("../comeback.csv")
+pub(crate) async fn handle_response(event: Request) -> Result<Response<Body>, Error> {
+    //Without the API Gateway stage, and empty for requests that never came through one, like queued fish
+    let path = event.raw_http_path().to_string();
+    let Some(endpoint) = Endpoint::find(&path) else {
+        return Err(FishError::NotFound(format!("there's nothing at {}", path)).into());
+    };
+    if !endpoint.methods().contains(event.method()) {
+        return Ok(method_not_allowed(endpoint));
+    }
+    #[cfg(feature = "prometheus")]
+    prometheus::request(endpoint.name());
+    match endpoint {
+        Endpoint::Draw => draw_response(event, None, false).await,
+        Endpoint::Daily => draw_response(event, None, true).await,
+        Endpoint::Health => health_response().await,
+        Endpoint::FishSvg => draw_response(event, Some(Picture::Svg), false).await,
+        Endpoint::FishPng => draw_response(event, Some(Picture::Png), false).await,
+        Endpoint::Stats => stats_response().await,
+        #[cfg(feature = "prometheus")]
+        Endpoint::Metrics => {
+            let (content_type, body) = prometheus::render();
+            Ok(Response::builder()
+                .header("content-type", content_type)
+                .body(Body::Binary(body))?)
         }
-        _ => {
+    }
+}
+
+//A fish, on a display or as a picture, and today's fish for /daily
+async fn draw_response(event: Request, picture: Option<Picture>, daily: bool) -> Result<Response<Body>, Error> {
+    //JSON objects in the body are settings (and maybe a batch), anything else is a drawing
+    let body_json = serde_json::from_slice::<serde_json::Value>(event.body().as_ref())
+        .ok()
+        .filter(serde_json::Value::is_object);
+    let query = event.query_string_parameters_ref().into_iter().flat_map(|params| params.iter());
+    let mut config = RequestConfig::from_params(query, body_json.as_ref())?;
+    config.daily |= daily;
+    //With a signing secret, displays only get fish from links made for them
+    //A secret that won't load means nobody gets through, not everybody
+    //Queued ones were checked on the way in, when the link was still good
+    let queued = event.extensions().get::<FromQueue>().is_some();
+    if let (false, Some(signer)) = (queued, LINK_SIGNER.get_or_try_init(load_signer).await?) {
+        if config.batch {
+            return Err(FishError::AccessDenied("signed links are for one display, not a batch".to_string()).into());
+        }
+        if let Some(address) = config.address.as_deref() {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            signer.verify(address, config.expires, config.sig.as_deref(), now)?;
+        }
+    }
+    //Same seed, same fish, so people can get their fish back later
+    //Or everyone gets the same one all day, to compare catches
+    let seed = if config.daily {
+        if config.seed.is_some() {
+            return Err(FishError::BadParams("today's fish has its own seed, leave seed out".to_string()).into());
+        }
+        clock::daily_seed(Utc::now())
+    } else {
+        config.seed.unwrap_or_else(generator::random_seed)
+    };
+    //Fish can wait for 11:11 at the other end, or any other time the sender likes
+    let scheduled = match (config.deliver_at.as_deref(), config.at_1111, config.tz.as_deref()) {
+        (Some(time), ..) => Some(clock::parse_time(time)?),
+        (None, true, Some(tz)) => Some(clock::next_eleven_eleven(tz)?),
+        _ => None,
+    };
+    let scheduled = scheduled.filter(|&when| when > Utc::now());
+
+    let store = FISH_STORE.get_or_init(FishStore::from_env).await.as_ref();
+    //Fresh fish get kept so they can be shared, stored ones already have an ID
+    let mut fish_id = None;
+    let mut generated = false;
+
+    //A batch brings a list of displays in the body instead of a drawing
+    let batch = if config.batch {
+        Some(parse_batch(event.body().as_ref())?)
+    } else {
+        None
+    };
+
+    let uploaded = batch.is_none() && body_json.is_none() && !event.body().as_ref().is_empty();
+    let mut fish = if let Some(id) = config.fish_id.as_deref() {
+        let Some(store) = store else {
+            return Err(FishError::BadParams("this fish service doesn't keep fish to draw again".to_string()).into());
+        };
+        fish_id = Some(id.to_string());
+        store.load(id).await?
+    } else if uploaded {
+        //Someone brought their own drawing
+        let content_type = event
+            .headers()
+            .get("content-type")
+            .and_then(|content_type| content_type.to_str().ok());
+        upload::parse(event.body().as_ref(), content_type)?
+    } else {
+        //With a time zone we can check for 11:11 ourselves, otherwise trust what clientside JS reported
+        //If both are missing, it is probably Mia testing code, so send a fish anyway
+        let wrong_time = match config.tz.as_deref() {
+            //It will be by the time it gets there
+            Some(_) if config.at_1111 => false,
+            Some(tz) => !clock::is_eleven_eleven(tz)?,
+            None => config.time.as_deref() == Some("bad"),
+        };
+        if wrong_time {
+            DRAWINGS.get_or_init(Drawings::from_env).await.get(drawings::COMEBACK).await?
+        } else {
             //who needs API gateway when you have reqwest 😤
-            &reqwest::get("https://j7qpm35ughmqz53afoye64up7a0wpawg.lambda-url.us-east-1.on.aws/")
-                .await?
-                .text()
-                .await?
-        }
-    };
-
-    // Each row is a list of points that make up a connected line
-    // Each row is not connected
-    // Fish_str is CSV but it's so simple, it can be parsed manually
-    let fish: Vec<Vec<Point>> = fish_str
-        .split("\n")
-        .map(|line| {
-            // Split the line by comma, parse each item as float, then convert to i16
-            line.split(',')
-                .filter_map(|item| item.trim().parse::<f64>().ok().and_then(|i| Some(i as i16)))
-                .collect::<Vec<i16>>() //Chunk is necessary for chunking
-                .chunks(2)
-                .map(|item| Point { x: item[0], y: item[1] })
-                .collect()
+            //A whole school of fish, each with the next seed along so the school is reproducible too
+            let count = config.count;
+            let creature = match config.creature.as_deref() {
+                None => creature::CREATURES[0],
+                Some(name) => creature::find(name).ok_or_else(|| {
+                    FishError::BadParams(format!(
+                        "don't know the {:?} creature, try one of: {}",
+                        name,
+                        creature::names().join(", ")
+                    ))
+                })?,
+            };
+            //Every fish in the school gets dressed up for the day
+            let today = clock::today(config.tz.as_deref())?;
+            let overlay = config.overlay.pick((today.month(), today.day()));
+            let mut fishes = Vec::with_capacity(count);
+            for i in 0..count as u64 {
+                let mut fish = fish_csv::parse(&creature.generate_csv(seed.wrapping_add(i)).await?)?;
+                if let Some(overlay) = overlay {
+                    overlay::apply(&mut fish, overlay);
+                }
+                fishes.push(fish);
+            }
+            generated = true;
+            school::arrange(&fishes)
+        }
+    };
+    //Turned around however they like, then the scenery goes around whichever fish it turned out to be
+    //The whole scene gets fitted to the window, and scale is on top of that, or it'd be fitted right back
+    let transform = config.transform;
+    Transform { scale: 1.0, ..transform }.apply(&mut fish);
+    props::arrange(&mut fish, &config.props);
+    if config.fit {
+        fit_to_canvas(&mut fish, config.margin);
+    }
+    Transform { flip: None, rotate: 0.0, ..transform }.apply(&mut fish);
+    let options = config.options;
+
+    //No X server needed for a picture of a fish
+    //Without an address to draw on, the Accept header gets a say, so curl gets text and browsers get pictures
+    //Paths that name a picture get that picture, whatever else the request says
+    let picture = match (picture, config.format.as_deref()) {
+        (Some(picture), _) => Some(picture),
+        (None, Some(format)) => Some(Picture::from_format(format)?),
+        (None, None) if config.address.is_none() && batch.is_none() => event
+            .headers()
+            .get("accept")
+            .and_then(|accept| accept.to_str().ok())
+            .and_then(negotiate),
+        (None, None) => None,
+    };
+    if let Some(picture) = picture {
+        let mut response = picture.respond(&fish, &options, &config.ascii)?;
+        //GIFs take a while to make, so keep a copy where it can be shared without making it again
+        if let (Picture::Gif, Some(store), Body::Binary(gif)) = (picture, store, response.body()) {
+            match store.save_gif(&fish, gif.clone()).await {
+                Ok(url) => {
+                    response.headers_mut().insert("content-location", url.parse()?);
+                }
+                Err(err) => println!("Couldn't keep the GIF: {}", err),
+            }
+        }
+        return Ok(response);
+    }
+
+    //Saving is a nice to have, a broken bucket shouldn't stop the fish
+    if let (true, Some(store)) = (generated, store) {
+        match store.save(&fish, Some(svg::render_svg(&fish, &options))).await {
+            Ok(id) => fish_id = Some(id),
+            Err(err) => println!("Couldn't keep the fish: {}", err),
+        }
+    }
+    let share_url = fish_id.as_ref().zip(store).map(|(id, store)| store.url_for(id));
+
+    //Soon enough and we wait here, otherwise the fish goes on the queue and comes back nearer the time
+    if let Some(when) = scheduled {
+        let wait = (when - Utc::now()).to_std().unwrap_or_default();
+        let hold_here = match time_left(&event) {
+            Some(left) => wait + SCHEDULED_DRAW_TIME <= left,
+            None => wait <= MAX_HELD_WAIT,
+        };
+        if hold_here {
+            println!("Holding the fish for {:?}, until {}", wait, when);
+            tokio::time::sleep(wait).await;
+        } else {
+            let Some(queue) = SCHEDULE_QUEUE.get_or_init(ScheduleQueue::from_env).await else {
+                let msg = format!("{} is too far off, this fish service can't hold fish that long", when.to_rfc3339());
+                return Err(FishError::BadParams(msg).into());
+            };
+            //A drawing of their own has nowhere to wait unless it was kept
+            if uploaded && fish_id.is_none() {
+                let msg = "this fish service can't hold a drawing that far off, only fish it makes itself";
+                return Err(FishError::BadParams(msg.to_string()).into());
+            }
+            let held = held_request(&event, body_json.as_ref(), batch.as_ref(), when, seed, fish_id.as_deref());
+            queue.hold(&held, when).await?;
+            let body = serde_json::json!({
+                "message": format!("Understandable, your fish will swim over at {} (seed {})", when.to_rfc3339(), seed),
+                "scheduled_for": when.to_rfc3339(),
+                "seed": seed,
+                "fish_id": fish_id,
+                "url": share_url,
+            });
+            return Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .header("content-type", "application/json")
+                .body(Body::Text(body.to_string()))?);
+        }
+    }
+
+    let deadline = deadline_for(&event, config.ttl);
+    //Callers can ask for a shorter connect timeout than the configured one, not a longer one
+    let max_connect_timeout = dial::connect_timeout_from_env();
+    let connect_timeout = config
+        .connect_timeout
+        .map_or(max_connect_timeout, |timeout| timeout.min(max_connect_timeout));
+    //Same for retries, fewer is fine, more than the deployment allows isn't
+    let retry = RetryPolicy::from_env();
+    let retry = config.connect_attempts.map_or(retry, |attempts| retry.at_most(attempts));
+    //However many tries that is, they all have to fit before the window's deadline
+    let retry = retry.until(deadline);
+    //Cookie can come as a param or a header, the header keeps it out of access logs
+    let cookie = config
+        .cookie
+        .as_deref()
+        .or_else(|| {
+            event
+                .headers()
+                .get("x-xauth-cookie")
+                .and_then(|cookie| cookie.to_str().ok())
         })
-        .collect();
+        .map(auth::parse_cookie)
+        .transpose()?;
+
+    //Straight to the display unless the request says otherwise
+    let route = match (config.proxy, config.ssh) {
+        (Some(proxy), _) => Route::Proxy(proxy),
+        (None, Some(ssh)) => {
+            let Some(keys) = SSH_KEYS.get_or_init(SshKeys::from_env).await else {
+                return Err(FishError::BadParams("this fish service doesn't do SSH".to_string()).into());
+            };
+            Route::Ssh(SshLogin {
+                user: ssh.user,
+                port: ssh.port,
+                key: keys.load(&ssh.key).await?,
+            })
+        }
+        (None, None) => Route::Direct,
+    };
+
+    let source = source_ip(&event);
+    let proof = options.proof;
+    let all_screens = config.all_screens;
+    let delivery = Arc::new(Delivery {
+        fish,
+        options,
+        cookie,
+        connect_timeout,
+        retry,
+        route,
+        deadline,
+        all_screens,
+        detach: config.detach,
+        fit: config.fit.then_some(config.margin),
+        seed,
+        fish_id: fish_id.clone(),
+        callback_url: config.callback_url,
+        source: source.clone(),
+    });
+
+    if let Some(addresses) = batch {
+        //The sender's cooldown covers the whole batch, so it's started once here and not again per display
+        //A sender that's still cooling down gets none of it
+        if let Some(source) = source.as_deref() {
+            claim_source(source).await?;
+        }
+        let semaphore = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+        let mut deliveries = JoinSet::new();
+        for (index, address) in addresses.into_iter().enumerate() {
+            let semaphore = semaphore.clone();
+            let delivery = delivery.clone();
+            deliveries.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let result = deliver(&address, &delivery, None).await;
+                (index, address, result)
+            });
+        }
+        let mut results = Vec::new();
+        while let Some(joined) = deliveries.join_next().await {
+            results.push(joined?);
+        }
+        results.sort_by_key(|(index, ..)| *index);
 
-    //Add a default display/screen (?) number if user did not supply it
-    if !address.contains(":") {
-        address = address + ":0.0";
-    }
-
-    let (conn, screen_num) = connect(Some(&address))?;
-
-    let screen = &conn.setup().roots[screen_num];
-    let atoms = Atoms::new(&conn)?.reply()?;
-    let win_id = create_window(&conn, screen, &atoms, (520, 320))?;
-    let gc_id = conn.generate_id().unwrap();
-
-    conn.create_gc(
-        gc_id,
-        win_id,
-        &CreateGCAux::default()
-            .foreground(screen.black_pixel)
-            .graphics_exposures(0),
-    )?;
-
-    conn.flush()?;
-
-    //Event loop time! This is a simple one as the program doesn't take user input
-    loop {
-        let event = conn.wait_for_event().unwrap();
-        match event {
-            //Window is visible, so the fish can be drawn
-            Event::Expose(_event) => {
-                for poly_line in &fish {
-                    conn.poly_line(CoordMode::ORIGIN, win_id, gc_id, &poly_line)?;
-                    //Create a slow drawing effect
-                    thread::sleep(Duration::from_millis(7));
-                    conn.flush()?;
+        let report: Vec<serde_json::Value> = results
+            .into_iter()
+            .map(|(_, address, result)| {
+                //A display with more than one screen counts as a fish if any of them got one
+                let result = result.and_then(|screens| {
+                    let mut last_err = None;
+                    for screen in screens {
+                        match screen {
+                            Ok(report) => return Ok(report),
+                            Err(err) => last_err = Some(err),
+                        }
+                    }
+                    Err(last_err.unwrap_or_else(|| "no screens to draw on".into()))
+                });
+                match result {
+                    Ok(report) => serde_json::json!({
+                        "address": address,
+                        "ok": true,
+                        "attempts": report.connect_attempts,
+                        "address_family": report.address_family.map(Family::as_str),
+                        "stats": stats(&report),
+                    }),
+                    Err(err) => {
+                        let (_, code, message, _) = describe(err);
+                        serde_json::json!({
+                            "address": address,
+                            "ok": false,
+                            "error": { "code": code, "message": message },
+                        })
+                    }
                 }
+            })
+            .collect();
+        let drawn = report.iter().filter(|result| result["ok"] == true).count();
+        let body = serde_json::json!({
+            "message": format!("Understandable, {} of {} fish delivered (seed {})", drawn, report.len(), seed),
+            "seed": seed,
+            "fish_id": fish_id,
+            "url": share_url,
+            "results": report,
+        });
+        return Ok(Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::Text(body.to_string()))?);
+    }
+
+    //Get the address of the X11 server from URL params
+    let Some(address) = config.address.as_deref() else {
+        return Err(FishError::BadParams("need address in query params".to_string()).into());
+    };
+    let results = deliver(address, &delivery, source.as_deref()).await?;
+
+    //Some screens having a fish is good enough, it's only a failure if none of them do
+    let screens = results.len();
+    let mut reports = Vec::new();
+    let mut last_err = None;
+    for (screen, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                println!("No fish on screen {}: {}", screen, err);
+                last_err = Some(err);
             }
-            Event::ClientMessage(event) => {
-                let data = event.data.as_data32();
-                if event.format == 32 && event.window == win_id && data[0] == atoms.WM_DELETE_WINDOW {
-                    println!("Window was asked to close");
-                    break;
+        }
+    }
+    if let (true, Some(err)) = (reports.is_empty(), last_err) {
+        return Err(err);
+    }
+    let mut message = match &share_url {
+        Some(url) => format!("Understandable, have a nice fish (seed {}), share it: {}", seed, url),
+        None => format!("Understandable, have a nice fish (seed {})", seed),
+    };
+    if all_screens {
+        message.push_str(&format!(", on {} of {} screens", reports.len(), screens));
+    }
+    //Always JSON, so the front end has some fun stats to show about the fish it just sent
+    let first = reports.first();
+    let mut body = serde_json::json!({
+        "message": message,
+        "seed": seed,
+        "fish_id": fish_id,
+        "url": share_url,
+        "attempts": first.map_or(0, |report| report.connect_attempts),
+        "address_family": first.and_then(|report| report.address_family).map(Family::as_str),
+        "stats": first.map(stats),
+    });
+    //Every screen that got a fish, in screen order
+    if all_screens {
+        body["screens"] = reports.iter().map(stats).collect();
+    }
+    //With the screenshot inline so the front end can show it straight away
+    if proof {
+        let proofs: Vec<Option<String>> = reports
+            .into_iter()
+            .map(|report| report.proof_png.map(|png| format!("data:image/png;base64,{}", BASE64.encode(png))))
+            .collect();
+        body["proof"] = serde_json::json!(proofs.first());
+        if all_screens {
+            body["proofs"] = serde_json::json!(proofs);
+        }
+    }
+    Ok(Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::Text(body.to_string()))?)
+}
+
+//How the fish went, for the curious
+fn stats(report: &DrawReport) -> serde_json::Value {
+    let millis = |time: Option<Duration>| time.map(|time| time.as_millis() as u64);
+    serde_json::json!({
+        "lines": report.lines,
+        "points": report.points,
+        "draw_ms": millis(report.draw_time),
+        "connect_ms": millis(report.connect_time),
+        "server": { "vendor": report.vendor, "release": report.release },
+        "window": report.window.map(|window| format!("{:#x}", window)),
+    })
+}
+
+//Kinds of fish that don't need an X server
+#[derive(Debug, Clone, Copy)]
+enum Picture {
+    Svg,
+    Png,
+    Gif,
+    Csv,
+    Text,
+}
+
+impl Picture {
+    fn from_format(format: &str) -> Result<Self, FishError> {
+        match format {
+            "svg" => Ok(Picture::Svg),
+            "png" => Ok(Picture::Png),
+            "gif" => Ok(Picture::Gif),
+            "csv" => Ok(Picture::Csv),
+            "text" | "ascii" => Ok(Picture::Text),
+            _ => Err(FishError::BadParams(format!(
+                "don't know the {:?} format, try svg, png, gif, csv or text",
+                format
+            ))),
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "image/svg+xml" => Some(Picture::Svg),
+            "image/png" => Some(Picture::Png),
+            "image/gif" => Some(Picture::Gif),
+            "text/csv" => Some(Picture::Csv),
+            "text/plain" => Some(Picture::Text),
+            _ => None,
+        }
+    }
+
+    fn respond(self, fish: &Fish, options: &DrawOptions, text: &AsciiOptions) -> Result<Response<Body>, Error> {
+        let (content_type, body) = match self {
+            Picture::Svg => ("image/svg+xml", Body::Text(svg::render_svg(fish, options))),
+            Picture::Png => ("image/png", Body::Binary(png::render_png(fish, options)?)),
+            Picture::Gif => ("image/gif", Body::Binary(gif::render_gif(fish, options)?)),
+            Picture::Csv => ("text/csv", Body::Text(fish_csv::write(fish))),
+            Picture::Text => ("text/plain; charset=utf-8", Body::Text(ascii::render_ascii(fish, text))),
+        };
+        Ok(Response::builder()
+            .header("content-type", content_type)
+            .header("vary", "accept")
+            .body(body)?)
+    }
+}
+
+//Whichever kind of fish the client likes best, by q value and then by the order they listed them
+//Wildcards don't count, a browser saying */* would rather have an error than a surprise download
+fn negotiate(accept: &str) -> Option<Picture> {
+    let mut offers: Vec<(f32, Picture)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q=")?.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let picture = Picture::from_media_type(&media_type)?;
+            (quality > 0.0).then_some((quality, picture))
+        })
+        .collect();
+    offers.sort_by(|a, b| b.0.total_cmp(&a.0));
+    offers.first().map(|(_, picture)| *picture)
+}
+
+//Everything a drawing thread needs, shared between every display in a batch
+struct Delivery {
+    fish: Fish,
+    options: DrawOptions,
+    cookie: Option<Vec<u8>>,
+    connect_timeout: Duration,
+    retry: RetryPolicy,
+    route: Route,
+    deadline: Instant,
+    all_screens: bool,
+    detach: bool,
+    //The margin fish got fitted with, so the ones clicked for after get the same, none if it wasn't fitted
+    fit: Option<f64>,
+    //For telling the sender when the recipient closes the window
+    seed: u64,
+    fish_id: Option<String>,
+    callback_url: Option<String>,
+    //Everyone in a batch gets the sender's address in the audit log, not only the one counted for rate limits
+    source: Option<String>,
+}
+
+//Send the fish, and log how it went as metrics for the dashboards
+async fn deliver(
+    address: &str,
+    delivery: &Arc<Delivery>,
+    source: Option<&str>,
+) -> Result<Vec<Result<DrawReport, Error>>, Error> {
+    let started = Instant::now();
+    let (outcome, result) = match send_fish(address, delivery, source).await {
+        Ok(results) => {
+            let mut outcome = None;
+            let results: Vec<_> = results
+                .into_iter()
+                .map(|result| {
+                    result.map_err(|err| {
+                        let (code, err) = error_code(err);
+                        outcome.get_or_insert(code);
+                        err
+                    })
+                })
+                .collect();
+            //Any screen getting a fish counts
+            let outcome = if results.iter().any(Result::is_ok) {
+                "drawn"
+            } else {
+                outcome.unwrap_or("internal")
+            };
+            (outcome, Ok(results))
+        }
+        Err(err) => {
+            let (code, err) = error_code(err);
+            (code, Err(err))
+        }
+    };
+    DrawMetrics {
+        target: address,
+        outcome,
+        duration: started.elapsed(),
+        fish: &delivery.fish,
+    }
+    .emit();
+    #[cfg(feature = "prometheus")]
+    {
+        prometheus::fish(outcome);
+        for report in result.iter().flatten().flatten() {
+            prometheus::timings(report.connect_time, report.draw_time);
+        }
+    }
+    if let Some(counter) = FISH_COUNTER.get_or_init(FishCounter::from_env).await {
+        counter.count(outcome);
+    }
+    //Also nice to have, a fish that went out went out whether or not we wrote it down
+    if let Some(audit) = AUDIT_LOG.get_or_init(AuditLog::from_env).await {
+        let entry = AuditEntry {
+            target: address,
+            source: delivery.source.as_deref(),
+            outcome,
+            fish_id: delivery.fish_id.as_deref(),
+        };
+        if let Err(err) = audit.record(&entry).await {
+            println!("Couldn't write to the audit log: {}", err);
+        }
+    }
+    result
+}
+
+//The code clients would see for an error, with the error sorted into a FishError if it can be
+fn error_code(err: Error) -> (&'static str, Error) {
+    match FishError::classify(err) {
+        Ok(err) => (err.code(), err.into()),
+        Err(err) => ("internal", err),
+    }
+}
+
+//The sender's cooldown on its own, in memory and in DynamoDB if there's a table
+async fn claim_source(source: &str) -> Result<(), Error> {
+    let limiter = RateLimiter::from_env();
+    limiter.check_source(source)?;
+    if let Some(dynamo) = DYNAMO_LIMITER.get_or_init(DynamoRateLimiter::from_env).await {
+        dynamo.claim(&format!("source:{}", source), "your address", limiter.source_cooldown).await?;
+    }
+    Ok(())
+}
+
+//One fish per display (and per sender) every so often, then the drawing itself
+//X11 is all blocking calls and sleeps, so it gets kept off the async runtime
+async fn send_fish(
+    address: &str,
+    delivery: &Arc<Delivery>,
+    source: Option<&str>,
+) -> Result<Vec<Result<DrawReport, Error>>, Error> {
+    let target = normalize_address(address)?;
+    //Anyone can ask us to connect anywhere, so make sure anywhere isn't somewhere it shouldn't be
+    //That goes first, somewhere we won't draw shouldn't use up anyone's cooldown
+    //What it checked is where we go, the name doesn't get looked up again for DNS to answer differently
+    let vetted = {
+        let address = address.to_string();
+        let delivery = delivery.clone();
+        tokio::task::spawn_blocking(move || match delivery.route.proxy() {
+            Some(proxy) => AddressPolicy::from_env().check_proxied(&address, proxy),
+            None => AddressPolicy::from_env().check(&address),
+        })
+        .await??
+    };
+
+    //The sender's cooldown before the display's, so someone who's rate limited can't hold a display up for everyone
+    let limiter = RateLimiter::from_env();
+    let dynamo = DYNAMO_LIMITER.get_or_init(DynamoRateLimiter::from_env).await.as_ref();
+    if let (Some(dynamo), Some(source)) = (dynamo, source) {
+        dynamo.claim(&format!("source:{}", source), "your address", limiter.source_cooldown).await?;
+    }
+    limiter.check(&target, source)?;
+    if let Some(dynamo) = dynamo {
+        dynamo.claim(&format!("target:{}", target), &target, limiter.target_cooldown).await?;
+    }
+
+    let address = address.to_string();
+    let delivery = delivery.clone();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let session_cancel = cancel.clone();
+    let detach = delivery.detach;
+    let (drawn_sender, mut drawn) = mpsc::unbounded_channel();
+    let on_drawn: OnDrawn = Arc::new(move |report: &DrawReport| {
+        let _ = drawn_sender.send(report.clone());
+    });
+    let mut drawing = tokio::task::spawn_blocking(move || {
+        let Delivery {
+            fish,
+            options,
+            cookie,
+            connect_timeout,
+            retry,
+            route,
+            deadline,
+            all_screens,
+            fit,
+            callback_url,
+            ..
+        } = &*delivery;
+        let fit = *fit;
+        let timeout = *connect_timeout;
+        //Wallpapers outlive their connection, so they get one of their own that nobody else will use
+        let session = if options.target == Target::Root {
+            XFishSession::connect_with_retries(&address, cookie.as_deref(), Some(timeout), retry, route, Some(&vetted))?
+        } else {
+            XFishSession::connect_pooled(&address, cookie.as_deref(), timeout, retry, route, Some(&vetted))?
+        };
+        let session = session
+            .with_cancel(session_cancel)
+            .with_on_drawn(on_drawn)
+            //Interactive windows get a brand new fish for every click
+            .with_new_fish(Arc::new(move || {
+                let mut fish = fish_csv::parse(&generator::generate_csv_blocking(generator::random_seed())?)?;
+                if let Some(margin) = fit {
+                    fit_to_canvas(&mut fish, margin);
                 }
+                Ok(fish)
+            }));
+        let results = if *all_screens {
+            session.draw_every_screen(&address, cookie.as_deref(), Some(*connect_timeout), fish, options, *deadline)?
+        } else {
+            vec![Ok(session.draw(fish, options, *deadline)?)]
+        };
+        //Still on the drawing thread, so it happens even after a detached request has been answered
+        if let Some(url) = callback_url {
+            if let Err(err) = notify_closed(url, &address, &delivery, &results) {
+                println!("Couldn't tell {} the fish was seen: {}", url, err);
             }
-            Event::Error(err) => return Err(format!("Got an unexpected error: {:?}", err).into()),
-            ev => println!("Got an unknown event: {:?}", ev),
-        }
-    }
-    Ok(format!("Understandable, have a nice fish").into_response().await)
-}
-
-fn create_window(
-    conn: &impl Connection,
-    screen: &Screen,
-    atoms: &Atoms,
-    (width, height): (u16, u16),
-) -> Result<Window, ReplyOrIdError> {
-    let win_id = conn.generate_id()?;
-    let win_aux = CreateWindowAux::new()
-        .event_mask(EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY)
-        .background_pixel(screen.white_pixel);
-
-    conn.create_window(
-        screen.root_depth,
-        win_id,
-        screen.root,
-        0,
-        0,
-        width,
-        height,
-        0,
-        WindowClass::INPUT_OUTPUT,
-        0,
-        &win_aux,
-    )?;
-
-    let title = "X11:11 makeafish";
-    conn.change_property8(
-        PropMode::REPLACE,
-        win_id,
-        AtomEnum::WM_NAME,
-        AtomEnum::STRING,
-        title.as_bytes(),
-    )?;
-    conn.change_property8(
-        PropMode::REPLACE,
-        win_id,
-        atoms._NET_WM_NAME,
-        atoms.UTF8_STRING,
-        title.as_bytes(),
-    )?;
-    conn.change_property32(
-        PropMode::REPLACE,
-        win_id,
-        atoms.WM_PROTOCOLS,
-        AtomEnum::ATOM,
-        &[atoms.WM_DELETE_WINDOW],
-    )?;
-
-    conn.map_window(win_id)?;
-
-    Ok(win_id)
+        }
+        Ok(results)
+    });
+    //If Lambda drops this request, the drawing thread finds out and stops too
+    let cancel_on_drop = CancelOnDrop(cancel);
+    if !detach {
+        return drawing.await?;
+    }
+    //Answer as soon as the fish is up and leave the thread to take it down at the deadline
+    //Lambda freezes us between requests, the window stays mapped through that and goes when we thaw or get reaped
+    tokio::select! {
+        result = &mut drawing => result?,
+        Some(report) = drawn.recv() => {
+            cancel_on_drop.detach();
+            Ok(vec![Ok(report)])
+        }
+    }
+}
+
+//One small JSON event per window the recipient closed, so the sender knows their fish was seen
+//Windows that timed out or were cancelled were never closed by anyone, so they don't count
+fn notify_closed(
+    url: &str,
+    address: &str,
+    delivery: &Delivery,
+    results: &[Result<DrawReport, Error>],
+) -> Result<(), Error> {
+    let closed: Vec<Duration> = results.iter().filter_map(|result| result.as_ref().ok()?.closed_after).collect();
+    if closed.is_empty() {
+        return Ok(());
+    }
+    //The webhook is somewhere a stranger told us to connect to, same as the display
+    let url = reqwest::Url::parse(url)?;
+    let host = url.host_str().ok_or("callback_url has no host")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let vetted = AddressPolicy::from_env().check_host(host, url.port_or_known_default().unwrap_or(80))?;
+    //A redirect could take us anywhere, the policy included, and so could looking the host up a second time
+    let client = reqwest::blocking::Client::builder()
+        .resolve_to_addrs(host, &vetted)
+        //A proxy from the environment would do its own looking up
+        .no_proxy()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(CALLBACK_TIMEOUT)
+        .build()?;
+    for lived in closed {
+        let event = serde_json::json!({
+            "event": "closed",
+            "fish_id": delivery.fish_id,
+            "seed": delivery.seed,
+            "address": address,
+            "lived_ms": lived.as_millis() as u64,
+        });
+        client
+            .post(url.clone())
+            .header("content-type", "application/json")
+            .body(event.to_string())
+            .send()?
+            .error_for_status()?;
+    }
+    Ok(())
+}
+
+//A JSON list of addresses, or an object with the list under "addresses"
+fn parse_batch(body: &[u8]) -> Result<Vec<String>, FishError> {
+    let bad = |why: String| FishError::BadParams(format!("a batch should be a JSON list of addresses, {}", why));
+    let value: serde_json::Value = serde_json::from_slice(body).map_err(|err| bad(err.to_string()))?;
+    let list = value.get("addresses").unwrap_or(&value).clone();
+    let addresses: Vec<String> = serde_json::from_value(list).map_err(|err| bad(err.to_string()))?;
+    if addresses.is_empty() {
+        return Err(bad("this one is empty".to_string()));
+    }
+    if addresses.len() > MAX_BATCH_ADDRESSES {
+        return Err(bad(format!("at most {} of them", MAX_BATCH_ADDRESSES)));
+    }
+    Ok(addresses)
+}
+
+//Where the request came from, as API Gateway saw it
+fn source_ip(event: &Request) -> Option<String> {
+    let from_context = match event.request_context_ref()? {
+        RequestContext::ApiGatewayV2(context) => context.http.source_ip.clone(),
+        RequestContext::ApiGatewayV1(context) => context.identity.source_ip.clone(),
+        _ => None,
+    };
+    //Load balancers put the real address last in X-Forwarded-For, anything before it is up to the client
+    from_context.or_else(|| {
+        event
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|forwarded| forwarded.to_str().ok())
+            .and_then(|forwarded| forwarded.rsplit(',').next())
+            .map(|ip| ip.trim().to_string())
+    })
+}
+
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    //Let the drawing carry on after all
+    fn detach(mut self) {
+        self.0 = Arc::default();
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+//The request as it came in, with the fish and the time pinned down so it's the same fish when it comes back
+//The queue gets everything in one body, the way an SQS message brings it
+fn held_request(
+    event: &Request,
+    body: Option<&serde_json::Value>,
+    batch: Option<&Vec<String>>,
+    when: DateTime<Utc>,
+    seed: u64,
+    fish_id: Option<&str>,
+) -> serde_json::Value {
+    let mut config = body
+        .and_then(|body| body.get("config"))
+        .and_then(|config| config.as_object())
+        .cloned()
+        .unwrap_or_default();
+    //Query string wins, same as when it was first read
+    for (name, value) in event.query_string_parameters_ref().into_iter().flat_map(|params| params.iter()) {
+        config.insert(name.to_string(), value.into());
+    }
+    if let Some(cookie) = event.headers().get("x-xauth-cookie").and_then(|cookie| cookie.to_str().ok()) {
+        config.entry("cookie").or_insert_with(|| cookie.into());
+    }
+    //The next 11:11 from when it comes back might be the one after this
+    config.remove("at_1111");
+    //Same for today's fish, it might be tomorrow by then, and the seed says which fish it was anyway
+    config.remove("daily");
+    config.insert("deliver_at".to_string(), when.to_rfc3339().into());
+    config.insert("seed".to_string(), seed.into());
+    if let Some(fish_id) = fish_id {
+        config.insert("fish_id".to_string(), fish_id.into());
+    }
+    serde_json::json!({ "config": config, "addresses": batch })
+}
+
+//How long until Lambda stops us, less the slack, none outside Lambda
+fn time_left(event: &Request) -> Option<Duration> {
+    event
+        .lambda_context_ref()
+        .and_then(|ctx| UNIX_EPOCH.checked_add(Duration::from_millis(ctx.deadline)))
+        .and_then(|deadline| deadline.duration_since(SystemTime::now()).ok())
+        .map(|left| left.saturating_sub(DEADLINE_SLACK))
+}
+
+//Work out when to give up on the window, from whichever is sooner of
+//the Lambda timeout and the `ttl` param
+fn deadline_for(event: &Request, ttl: Option<Duration>) -> Instant {
+    let remaining = time_left(event);
+    let budget = match (remaining, ttl) {
+        (Some(remaining), Some(ttl)) => remaining.min(ttl),
+        (Some(limit), None) | (None, Some(limit)) => limit,
+        (None, None) => DEFAULT_TTL,
+    };
+    Instant::now() + budget
 }
@@ -0,0 +1,145 @@
+use crate::{stable_hash, DisplayAddress, Fish};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Where the metrics show up in CloudWatch, XFISH_METRICS_NAMESPACE to put them somewhere else
+pub const DEFAULT_NAMESPACE: &str = "XFish";
+
+//How one attempt at sending a fish went
+//Printed as CloudWatch's embedded metric format, a log line that CloudWatch turns into metrics by itself
+pub struct DrawMetrics<'a> {
+    //The address the fish went to, only ever logged hashed
+    pub target: &'a str,
+    //"drawn", or the error code for whatever went wrong
+    pub outcome: &'static str,
+    pub duration: Duration,
+    pub fish: &'a Fish,
+}
+
+//Stable between builds so the same host always hashes the same
+pub(crate) fn host_hash(target: &str) -> String {
+    let host = DisplayAddress::parse(target).map_or_else(|_| target.to_string(), |address| address.host);
+    stable_hash(host.as_bytes())
+}
+
+impl DrawMetrics<'_> {
+    //Outcome is the only dimension, so there's one set of graphs per kind of failure and one for everything
+    pub fn to_emf(&self, namespace: &str, timestamp: Duration) -> serde_json::Value {
+        let drawn = self.outcome == "drawn";
+        serde_json::json!({
+            "_aws": {
+                "Timestamp": timestamp.as_millis() as u64,
+                "CloudWatchMetrics": [{
+                    "Namespace": namespace,
+                    "Dimensions": [["Outcome"], []],
+                    "Metrics": [
+                        { "Name": "FishDrawn", "Unit": "Count" },
+                        { "Name": "Failures", "Unit": "Count" },
+                        { "Name": "DrawDuration", "Unit": "Milliseconds" },
+                        { "Name": "Lines", "Unit": "Count" },
+                        { "Name": "Points", "Unit": "Count" },
+                    ],
+                }],
+            },
+            "Outcome": self.outcome,
+            "FishDrawn": drawn as u8,
+            "Failures": !drawn as u8,
+            "DrawDuration": self.duration.as_millis() as u64,
+            "Lines": self.fish.len(),
+            "Points": self.fish.iter().map(|line| line.len()).sum::<usize>(),
+            //Not a dimension, there'd be one per host, but handy for searching logs for one display's fish
+            "TargetHash": host_hash(self.target),
+        })
+    }
+
+    pub fn emit(&self) {
+        let namespace = std::env::var("XFISH_METRICS_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string());
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        println!("{}", self.to_emf(&namespace, timestamp));
+    }
+}
+
+//The same numbers for self-hosters, scraped from /metrics instead of pulled out of CloudWatch logs
+//Only means anything in a long running server, a Lambda container's counts go when it does
+#[cfg(feature = "prometheus")]
+pub mod prometheus {
+    use prometheus::{Encoder, Histogram, HistogramOpts, IntCounterVec, Opts, Registry, TextEncoder};
+    use std::sync::LazyLock;
+    use std::time::Duration;
+
+    //Connecting can take a while over proxies and SSH, drawing slowly takes a good few seconds on purpose
+    const CONNECT_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+    const DRAW_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+    struct Metrics {
+        registry: Registry,
+        requests: IntCounterVec,
+        fish: IntCounterVec,
+        connect: Histogram,
+        draw: Histogram,
+    }
+
+    static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(Opts::new("xfish_requests_total", "Requests, by endpoint"), &["endpoint"])
+            .expect("metric names are valid");
+        let fish = IntCounterVec::new(
+            Opts::new("xfish_fish_total", "Attempts at sending a fish, by outcome"),
+            &["outcome"],
+        )
+        .expect("metric names are valid");
+        let connect = Histogram::with_opts(
+            HistogramOpts::new("xfish_connect_seconds", "Connecting to the display, X setup included")
+                .buckets(CONNECT_BUCKETS.to_vec()),
+        )
+        .expect("metric names are valid");
+        let draw = Histogram::with_opts(
+            HistogramOpts::new("xfish_draw_seconds", "From starting to draw to the whole fish being up")
+                .buckets(DRAW_BUCKETS.to_vec()),
+        )
+        .expect("metric names are valid");
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(fish.clone()),
+            Box::new(connect.clone()),
+            Box::new(draw.clone()),
+        ] {
+            registry.register(collector).expect("metrics are only registered once");
+        }
+        Metrics {
+            registry,
+            requests,
+            fish,
+            connect,
+            draw,
+        }
+    });
+
+    pub fn request(endpoint: &str) {
+        METRICS.requests.with_label_values(&[endpoint]).inc();
+    }
+
+    //"drawn", or the error code for whatever went wrong
+    pub fn fish(outcome: &str) {
+        METRICS.fish.with_label_values(&[outcome]).inc();
+    }
+
+    //Pooled connections and fish that never finished don't have times to give
+    pub fn timings(connect: Option<Duration>, draw: Option<Duration>) {
+        if let Some(connect) = connect {
+            METRICS.connect.observe(connect.as_secs_f64());
+        }
+        if let Some(draw) = draw {
+            METRICS.draw.observe(draw.as_secs_f64());
+        }
+    }
+
+    //Prometheus' text format, and the content type that goes with it
+    pub fn render() -> (String, Vec<u8>) {
+        let encoder = TextEncoder::new();
+        let mut buffer = Vec::new();
+        if let Err(err) = encoder.encode(&METRICS.registry.gather(), &mut buffer) {
+            println!("Couldn't encode the metrics: {}", err);
+        }
+        (encoder.format_type().to_string(), buffer)
+    }
+}
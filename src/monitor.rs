@@ -0,0 +1,171 @@
+use crate::{Atoms, FishError};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xinerama::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, Screen, Window};
+
+//The part of the screen one monitor shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Monitor {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    primary: bool,
+}
+
+impl Monitor {
+    //The whole screen, which is all there is without RandR or Xinerama
+    fn whole(screen: &Screen) -> Self {
+        Monitor {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels,
+            height: screen.height_in_pixels,
+            primary: true,
+        }
+    }
+
+    //Just the part of the monitor inside the area, or the whole monitor if they don't overlap at all
+    pub fn within(&self, area: &Monitor) -> Monitor {
+        let left = (self.x as i32).max(area.x as i32);
+        let top = (self.y as i32).max(area.y as i32);
+        let right = (self.x as i32 + self.width as i32).min(area.x as i32 + area.width as i32);
+        let bottom = (self.y as i32 + self.height as i32).min(area.y as i32 + area.height as i32);
+        if right <= left || bottom <= top {
+            return *self;
+        }
+        Monitor {
+            x: left as i16,
+            y: top as i16,
+            width: (right - left) as u16,
+            height: (bottom - top) as u16,
+            primary: self.primary,
+        }
+    }
+
+    //Where a window this big goes to sit in the middle
+    pub fn center(&self, (width, height): (u16, u16)) -> (i16, i16) {
+        let x = self.x as i32 + (self.width as i32 - width as i32) / 2;
+        let y = self.y as i32 + (self.height as i32 - height as i32) / 2;
+        (x.clamp(i16::MIN as i32, i16::MAX as i32) as i16, y.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+    }
+}
+
+//GetMonitors needs RandR 1.5, which is the only version that knows which one is primary
+fn randr_monitors(conn: &impl Connection, screen: &Screen) -> Option<Vec<Monitor>> {
+    conn.extension_information(randr::X11_EXTENSION_NAME).ok()??;
+    let reply = conn.randr_get_monitors(screen.root, true).ok()?.reply().ok()?;
+    let monitors: Vec<Monitor> = reply
+        .monitors
+        .iter()
+        .map(|monitor| Monitor {
+            x: monitor.x,
+            y: monitor.y,
+            width: monitor.width,
+            height: monitor.height,
+            primary: monitor.primary,
+        })
+        .collect();
+    (!monitors.is_empty()).then_some(monitors)
+}
+
+//Older multi-head setups, where the first screen is as close to primary as it gets
+fn xinerama_monitors(conn: &impl Connection) -> Option<Vec<Monitor>> {
+    conn.extension_information(xinerama::X11_EXTENSION_NAME).ok()??;
+    if conn.xinerama_is_active().ok()?.reply().ok()?.state == 0 {
+        return None;
+    }
+    let reply = conn.xinerama_query_screens().ok()?.reply().ok()?;
+    let monitors: Vec<Monitor> = reply
+        .screen_info
+        .iter()
+        .enumerate()
+        .map(|(index, info)| Monitor {
+            x: info.x_org,
+            y: info.y_org,
+            width: info.width,
+            height: info.height,
+            primary: index == 0,
+        })
+        .collect();
+    (!monitors.is_empty()).then_some(monitors)
+}
+
+//Every monitor on the screen, in the order the server lists them
+pub(crate) fn monitors(conn: &impl Connection, screen: &Screen) -> Vec<Monitor> {
+    randr_monitors(conn, screen)
+        .or_else(|| xinerama_monitors(conn))
+        .unwrap_or_else(|| vec![Monitor::whole(screen)])
+}
+
+//The monitor asked for by number, or the primary one, or the first one if nobody picked a primary
+pub(crate) fn pick(conn: &impl Connection, screen: &Screen, index: Option<usize>) -> Result<Monitor, FishError> {
+    let monitors = monitors(conn, screen);
+    match index {
+        Some(index) => monitors.get(index).copied().ok_or_else(|| {
+            FishError::BadParams(format!("there's no monitor {}, only {} of them", index, monitors.len()))
+        }),
+        None => Ok(monitors
+            .iter()
+            .find(|monitor| monitor.primary)
+            .copied()
+            .unwrap_or(monitors[0])),
+    }
+}
+
+//A CARDINAL property as numbers, nothing if it isn't there
+fn cardinals(conn: &impl Connection, window: Window, property: u32, length: u32) -> Option<Vec<u32>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::CARDINAL, 0, length)
+        .ok()?
+        .reply()
+        .ok()?;
+    Some(reply.value32()?.collect())
+}
+
+//What's left of the screen once panels and docks have taken their bits, so the fish doesn't end up under them
+//_NET_WORKAREA has it worked out per desktop, without it add up the struts of every window ourselves
+pub(crate) fn work_area(conn: &impl Connection, screen: &Screen, atoms: &Atoms) -> Option<Monitor> {
+    let desktop = cardinals(conn, screen.root, atoms._NET_CURRENT_DESKTOP, 1)
+        .and_then(|desktop| desktop.first().copied())
+        .unwrap_or(0) as usize;
+    //Four numbers per desktop, x, y, width and height
+    let work_areas = cardinals(conn, screen.root, atoms._NET_WORKAREA, u32::MAX / 4).unwrap_or_default();
+    if let Some(area) = work_areas.chunks_exact(4).nth(desktop).or(work_areas.chunks_exact(4).next()) {
+        return Some(Monitor {
+            x: area[0].min(i16::MAX as u32) as i16,
+            y: area[1].min(i16::MAX as u32) as i16,
+            width: area[2].min(u16::MAX as u32) as u16,
+            height: area[3].min(u16::MAX as u32) as u16,
+            primary: true,
+        });
+    }
+
+    //Struts are left, right, top and bottom, measured in from the edges of the screen
+    let clients = conn
+        .get_property(false, screen.root, atoms._NET_CLIENT_LIST, AtomEnum::WINDOW, 0, u32::MAX / 4)
+        .ok()?
+        .reply()
+        .ok()?;
+    let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+    for client in clients.value32()? {
+        if let Some(&[strut_left, strut_right, strut_top, strut_bottom, ..]) =
+            cardinals(conn, client, atoms._NET_WM_STRUT, 4).as_deref()
+        {
+            left = left.max(strut_left);
+            right = right.max(strut_right);
+            top = top.max(strut_top);
+            bottom = bottom.max(strut_bottom);
+        }
+    }
+    let width = (screen.width_in_pixels as u32).checked_sub(left + right)?;
+    let height = (screen.height_in_pixels as u32).checked_sub(top + bottom)?;
+    Some(Monitor {
+        x: left.min(i16::MAX as u32) as i16,
+        y: top.min(i16::MAX as u32) as i16,
+        width: width as u16,
+        height: height as u16,
+        primary: true,
+    })
+}
@@ -0,0 +1,115 @@
+use crate::{fish_bounds, Fish, FishError};
+use std::f64::consts::TAU;
+use std::str::FromStr;
+
+//Little extras for special days, added after the fish so they draw last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    SantaHat,
+    PartyHat,
+    Bubbles,
+}
+
+//Which days get what, as (month, day)
+//April 1 is poisson d'avril, and Fish Day is 11/11, the most 11:11 day of the year
+const CALENDAR: &[((u32, u32), Overlay)] = &[
+    ((4, 1), Overlay::Bubbles),
+    ((11, 11), Overlay::PartyHat),
+    ((12, 25), Overlay::SantaHat),
+];
+
+//What a request wants, overlay=none for a plain fish whatever the day, or one by name to have it any day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlayChoice {
+    #[default]
+    Calendar,
+    Off,
+    Always(Overlay),
+}
+
+impl FromStr for OverlayChoice {
+    type Err = FishError;
+
+    fn from_str(overlay: &str) -> Result<Self, Self::Err> {
+        match overlay.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(OverlayChoice::Calendar),
+            "none" | "off" => Ok(OverlayChoice::Off),
+            "santa_hat" => Ok(OverlayChoice::Always(Overlay::SantaHat)),
+            "party_hat" => Ok(OverlayChoice::Always(Overlay::PartyHat)),
+            "bubbles" => Ok(OverlayChoice::Always(Overlay::Bubbles)),
+            _ => Err(FishError::BadParams(format!(
+                "don't know the {:?} overlay, try none, auto, santa_hat, party_hat or bubbles",
+                overlay
+            ))),
+        }
+    }
+}
+
+impl OverlayChoice {
+    pub fn pick(self, (month, day): (u32, u32)) -> Option<Overlay> {
+        match self {
+            OverlayChoice::Calendar => CALENDAR
+                .iter()
+                .find(|(date, _)| *date == (month, day))
+                .map(|&(_, overlay)| overlay),
+            OverlayChoice::Off => None,
+            OverlayChoice::Always(overlay) => Some(overlay),
+        }
+    }
+}
+
+//A circle, as near as a dozen straight lines get
+fn ring(x: f64, y: f64, radius: f64) -> Vec<(f64, f64)> {
+    (0..=12)
+        .map(|i| i as f64 * TAU / 12.0)
+        .map(|angle| (x + radius * angle.cos(), y + radius * angle.sin()))
+        .collect()
+}
+
+//Drawn in a 1 by 1 box, y going down like everything else
+fn lines(overlay: Overlay) -> Fish {
+    match overlay {
+        Overlay::SantaHat => vec![
+            //Brim
+            vec![(0.0, 0.8), (1.0, 0.8), (1.0, 1.0), (0.0, 1.0), (0.0, 0.8)],
+            //Floppy top, bending over to the pompom
+            vec![(0.1, 0.8), (0.35, 0.3), (0.65, 0.05), (0.95, 0.2)],
+            vec![(0.9, 0.8), (0.75, 0.35), (0.65, 0.05)],
+            ring(0.95, 0.2, 0.08),
+        ],
+        Overlay::PartyHat => vec![
+            vec![(0.0, 1.0), (0.5, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            //Stripes, from one side of the cone to the other
+            vec![(0.275, 0.45), (0.675, 0.35)],
+            vec![(0.125, 0.75), (0.825, 0.65)],
+            ring(0.5, 0.0, 0.07),
+        ],
+        Overlay::Bubbles => vec![ring(0.2, 0.85, 0.15), ring(0.55, 0.5, 0.11), ring(0.8, 0.15, 0.08)],
+    }
+}
+
+//Add the overlay to the fish, sized and placed by the fish's bounding box
+//Hats sit on top a third of the way along, bubbles rise off the top of the fish
+pub fn apply(fish: &mut Fish, overlay: Overlay) {
+    let Some(((left, top), (right, bottom))) = fish_bounds(fish) else {
+        return;
+    };
+    let (width, height) = (right - left, bottom - top);
+    let (size, x, y) = match overlay {
+        Overlay::SantaHat | Overlay::PartyHat => {
+            let size = width.min(height) * 0.45;
+            (size, left + width / 3.0 - size / 2.0, top + height * 0.1 - size)
+        }
+        Overlay::Bubbles => {
+            let size = height * 0.5;
+            (size, left, top - size)
+        }
+    };
+    //Off the top of the canvas is off the top of the window
+    let (x, y) = (x.max(0.0), y.max(0.0));
+    fish.extend(
+        lines(overlay)
+            .into_iter()
+            .map(|line| line.into_iter().map(|(px, py)| (x + px * size, y + py * size)).collect()),
+    );
+}
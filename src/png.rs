@@ -0,0 +1,105 @@
+use crate::{color, fit_transform, style, Cap, DrawOptions, Error, Fish, Palette};
+use tiny_skia::{Color, IntSize, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Stroke, StrokeDash, Transform};
+
+//Everything needed to put the fish's lines on an image one at a time
+//The GIF renderer takes a picture after every few lines, everything else just draws them all
+pub(crate) struct Canvas {
+    pub pixmap: Pixmap,
+    paint: Paint<'static>,
+    stroke: Stroke,
+    transform: Transform,
+    palette: Palette,
+}
+
+impl Canvas {
+    pub fn new(options: &DrawOptions) -> Result<Self, Error> {
+        let (width, height) = options.size;
+        let mut pixmap = Pixmap::new(width as u32, height as u32).ok_or("image size can't be zero")?;
+        //No colormap to ask here, so only hex colors (and black and white) work
+        //Anything else is a white background and a black fish
+        let to_color = |(red, green, blue): (u16, u16, u16)| {
+            Color::from_rgba8((red >> 8) as u8, (green >> 8) as u8, (blue >> 8) as u8, 255)
+        };
+        let background = options.background.as_deref().and_then(color::parse_basic);
+        pixmap.fill(background.map_or(Color::WHITE, to_color));
+
+        let mut paint = Paint::default();
+        paint.anti_alias = true;
+        let foreground = match &options.color {
+            Some(color) => color::parse_basic(color),
+            None => background.and_then(|background| color::parse_basic(color::contrasting(background))),
+        };
+        paint.set_color(foreground.map_or(Color::BLACK, to_color));
+
+        let (scale, offset_x, offset_y) = fit_transform(options.size);
+        let transform = Transform::from_row(
+            scale as f32,
+            0.0,
+            0.0,
+            scale as f32,
+            offset_x as f32,
+            offset_y as f32,
+        );
+        //Widths are in screen pixels but the path gets scaled, so they get scaled the other way
+        //0 is X's hairline, which comes out about a pixel
+        let pixel = 1.0 / scale as f32;
+        let stroke = Stroke {
+            width: options.line_width.max(1) as f32 * pixel,
+            line_cap: match options.cap {
+                Cap::Butt => LineCap::Butt,
+                Cap::Round => LineCap::Round,
+                Cap::Square => LineCap::Square,
+            },
+            line_join: LineJoin::Round,
+            dash: StrokeDash::new(options.dashes.iter().map(|&length| length as f32 * pixel).collect(), 0.0),
+            ..Stroke::default()
+        };
+
+        Ok(Canvas {
+            pixmap,
+            paint,
+            stroke,
+            transform,
+            palette: options.palette,
+        })
+    }
+
+    //Line number `index` of the fish, which only matters for the rainbow
+    pub fn line(&mut self, index: usize, line: &[(f64, f64)]) {
+        if self.palette == Palette::Rainbow {
+            let (red, green, blue) = color::hsv(style::rainbow_hue(index), 1.0, 1.0);
+            self.paint
+                .set_color_rgba8((red >> 8) as u8, (green >> 8) as u8, (blue >> 8) as u8, 255);
+        }
+        let mut path = PathBuilder::new();
+        let mut points = line.iter();
+        let Some(&(x, y)) = points.next() else {
+            return;
+        };
+        path.move_to(x as f32, y as f32);
+        for &(x, y) in points {
+            path.line_to(x as f32, y as f32);
+        }
+        //Single point lines don't make a path, but they don't make much of a fish either
+        if let Some(path) = path.finish() {
+            self.pixmap
+                .stroke_path(&path, &self.paint, &self.stroke, self.transform, None);
+        }
+    }
+}
+
+//Draw the fish into an image instead of on someone's X server
+pub fn render_png(fish: &Fish, options: &DrawOptions) -> Result<Vec<u8>, Error> {
+    let mut canvas = Canvas::new(options)?;
+    for (index, line) in fish.iter().enumerate() {
+        canvas.line(index, line);
+    }
+    Ok(canvas.pixmap.encode_png()?)
+}
+
+//Straight RGBA pixels to a PNG, for pictures that didn't come from us
+pub fn encode_rgba(width: u16, height: u16, rgba: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let size = IntSize::from_wh(width as u32, height as u32).ok_or("image size can't be zero")?;
+    let pixmap = Pixmap::from_vec(rgba, size).ok_or("image is the wrong size")?;
+    Ok(pixmap.encode_png()?)
+}
@@ -0,0 +1,316 @@
+use crate::{DisplayAddress, Error, FishError, Proxy};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+//Which X servers we're willing to connect to on behalf of strangers on the internet
+//XFISH_ALLOW and XFISH_DENY are comma separated hostnames, IPs, or CIDRs like 10.0.0.0/8
+//Deny always wins, allow lets through addresses that would be blocked as private
+#[derive(Debug, Default, Clone)]
+pub struct AddressPolicy {
+    pub allow: Vec<Rule>,
+    pub deny: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Host(String),
+    Network(IpAddr, u8),
+}
+
+impl Rule {
+    pub fn parse(rule: &str) -> Option<Rule> {
+        let rule = rule.trim();
+        if rule.is_empty() {
+            return None;
+        }
+        if let Some((ip, prefix)) = rule.split_once('/') {
+            let ip: IpAddr = ip.parse().ok()?;
+            let prefix: u8 = prefix.parse().ok()?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            return (prefix <= max).then_some(Rule::Network(ip, prefix));
+        }
+        match rule.parse::<IpAddr>() {
+            Ok(ip) => Some(Rule::Network(ip, if ip.is_ipv4() { 32 } else { 128 })),
+            Err(_) => Some(Rule::Host(rule.to_ascii_lowercase())),
+        }
+    }
+
+    fn matches(&self, host: &str, ip: IpAddr) -> bool {
+        match (self, ip) {
+            (Rule::Host(name), _) => name.eq_ignore_ascii_case(host),
+            (Rule::Network(IpAddr::V4(net), prefix), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*net) & mask == u32::from(ip) & mask
+            }
+            (Rule::Network(IpAddr::V6(net), prefix), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn parse_rules(rules: &str) -> Vec<Rule> {
+    rules.split(',').filter_map(Rule::parse).collect()
+}
+
+//Addresses that are somebody's internal network, or ours
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => is_private_v6(ip) || embedded_v4(ip).is_some_and(is_private_v4),
+    }
+}
+
+//IPv6 addresses that get turned into IPv4 ones somewhere along the way, and which IPv4 address that is
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let [.., a, b, c, d] = ip.octets();
+    //::ffff:a.b.c.d, and the old IPv4 compatible ::a.b.c.d
+    //NAT64, 64:ff9b::a.b.c.d, hands it to whatever a.b.c.d is on the far side of the gateway
+    if (segments[..5] == [0; 5] && (segments[5] == 0xffff || segments[5] == 0))
+        || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+    {
+        return Some(Ipv4Addr::new(a, b, c, d));
+    }
+    //6to4, 2002:aabb:ccdd::/48
+    if segments[0] == 0x2002 {
+        let [_, _, a, b, c, d, ..] = ip.octets();
+        return Some(Ipv4Addr::new(a, b, c, d));
+    }
+    None
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        //169.254.0.0/16 includes the instance metadata service at 169.254.169.254
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        //Carrier grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        //"This network", 0.0.0.0/8, which Linux takes to mean this machine
+        || a == 0
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        //Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        //Link local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        //Local use NAT64, 64:ff9b:1::/48, which puts the IPv4 address wherever the network likes
+        || ip.segments()[..3] == [0x64, 0xff9b, 1]
+}
+
+impl AddressPolicy {
+    pub fn from_env() -> Self {
+        AddressPolicy {
+            allow: std::env::var("XFISH_ALLOW").map(|rules| parse_rules(&rules)).unwrap_or_default(),
+            deny: std::env::var("XFISH_DENY").map(|rules| parse_rules(&rules)).unwrap_or_default(),
+        }
+    }
+
+    //Resolve the display address and make sure every address it points to is fair game
+    //This does DNS, so it blocks. Connect to what it hands back, looking the name up again could get somewhere else
+    pub fn check(&self, address: &str) -> Result<Vec<SocketAddr>, Error> {
+        let address = DisplayAddress::parse(address)?;
+        //No host means a local unix socket, which is our machine, not theirs
+        if address.is_local() {
+            return Err(FishError::BadParams("address needs a host".to_string()).into());
+        }
+        self.check_host(&address.host, address.tcp_port())
+    }
+
+    //Through a proxy it's the proxy we connect to, so that's what has to be fair game
+    //The display might only mean something on the proxy's side, so all it gets checked against is the deny list
+    //What comes back is the proxy's addresses
+    pub fn check_proxied(&self, address: &str, proxy: &Proxy) -> Result<Vec<SocketAddr>, Error> {
+        let address = DisplayAddress::parse(address)?;
+        if address.is_local() {
+            return Err(FishError::BadParams("address needs a host".to_string()).into());
+        }
+        let host = address.host.as_str();
+        let denied = match host.parse::<IpAddr>() {
+            Ok(ip) => self.deny.iter().any(|rule| rule.matches(host, ip)),
+            Err(_) => self.deny.iter().any(|rule| matches!(rule, Rule::Host(name) if name.eq_ignore_ascii_case(host))),
+        };
+        if denied {
+            return Err(FishError::AddressForbidden(format!("not allowed to connect to {}", host)).into());
+        }
+        self.check_host(&proxy.host, proxy.port)
+    }
+
+    //Anything else we'd connect to for a stranger, like the webhook they want to hear back on
+    pub fn check_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, Error> {
+        let addrs: Vec<SocketAddr> = (host, port)
+            .to_socket_addrs()
+            .map_err(|err| FishError::DnsFailure(format!("couldn't look up {}: {}", host, err)))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(FishError::DnsFailure(format!("{} doesn't resolve to anything", host)).into());
+        }
+
+        for ip in addrs.iter().map(SocketAddr::ip) {
+            if self.deny.iter().any(|rule| rule.matches(host, ip)) {
+                return Err(FishError::AddressForbidden(format!("not allowed to connect to {}", host)).into());
+            }
+            if is_private(ip) && !self.allow.iter().any(|rule| rule.matches(host, ip)) {
+                let msg = format!("{} is a private address, not allowed to connect to it", host);
+                return Err(FishError::AddressForbidden(msg).into());
+            }
+        }
+        Ok(addrs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &str, deny: &str) -> AddressPolicy {
+        AddressPolicy {
+            allow: parse_rules(allow),
+            deny: parse_rules(deny),
+        }
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let cases = [
+            ("10.0.0.0/8", Some(Rule::Network(ip("10.0.0.0"), 8))),
+            ("fd00::/8", Some(Rule::Network(ip("fd00::"), 8))),
+            ("192.168.1.7", Some(Rule::Network(ip("192.168.1.7"), 32))),
+            ("::1", Some(Rule::Network(ip("::1"), 128))),
+            (" Fish.Example.COM ", Some(Rule::Host("fish.example.com".to_string()))),
+            ("0.0.0.0/0", Some(Rule::Network(ip("0.0.0.0"), 0))),
+            ("", None),
+            ("   ", None),
+            ("10.0.0.0/33", None),
+            ("::/129", None),
+            ("10.0.0.0/x", None),
+            ("fish.example.com/8", None),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(Rule::parse(rule), expected, "{:?}", rule);
+        }
+        assert_eq!(parse_rules("10.0.0.0/8,,bad/99, fish").len(), 2);
+    }
+
+    #[test]
+    fn rules_match() {
+        let cases = [
+            ("10.0.0.0/8", "10.255.1.2", true),
+            ("10.0.0.0/8", "11.0.0.1", false),
+            ("0.0.0.0/0", "8.8.8.8", true),
+            ("192.168.1.7", "192.168.1.7", true),
+            ("192.168.1.7", "192.168.1.8", false),
+            ("fd00::/8", "fd12::1", true),
+            ("fd00::/8", "fe80::1", false),
+            //Families don't mix
+            ("10.0.0.0/8", "::ffff:10.0.0.1", false),
+            ("::/0", "10.0.0.1", false),
+        ];
+        for (rule, addr, expected) in cases {
+            let rule = Rule::parse(rule).unwrap();
+            assert_eq!(rule.matches("", ip(addr)), expected, "{:?} {}", rule, addr);
+        }
+        let host = Rule::parse("fish.example.com").unwrap();
+        assert!(host.matches("FISH.example.com", ip("8.8.8.8")));
+        assert!(!host.matches("other.example.com", ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn blocks_private_ranges() {
+        let private = [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.0.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "224.0.0.1",
+            "100.64.0.1",
+            "100.127.255.255",
+            "::1",
+            "::",
+            "ff02::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            //IPv4 compatible
+            "::127.0.0.1",
+            "::a9fe:a9fe",
+            //NAT64
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::10.0.0.1",
+            "64:ff9b:1::1",
+            //6to4
+            "2002:a9fe:a9fe::1",
+            "2002:7f00:1::",
+            "2002:c0a8:0101::1",
+        ];
+        for addr in private {
+            assert!(is_private(ip(addr)), "{} should be private", addr);
+        }
+    }
+
+    #[test]
+    fn lets_public_addresses_through() {
+        let public = [
+            "8.8.8.8",
+            "1.1.1.1",
+            "100.63.255.255",
+            "100.128.0.1",
+            "172.32.0.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+            "64:ff9b::8.8.8.8",
+            "2002:0808:0808::1",
+        ];
+        for addr in public {
+            assert!(!is_private(ip(addr)), "{} should be public", addr);
+        }
+    }
+
+    #[test]
+    fn check_host_blocks_private_unless_allowed() {
+        let strict = policy("", "");
+        assert!(strict.check_host("127.0.0.1", 6000).is_err());
+        assert!(strict.check_host("64:ff9b::a9fe:a9fe", 6000).is_err());
+        assert_eq!(strict.check_host("8.8.8.8", 6000).unwrap(), [SocketAddr::from(([8, 8, 8, 8], 6000))]);
+
+        let allowed = policy("127.0.0.0/8", "");
+        assert_eq!(allowed.check_host("127.0.0.1", 6001).unwrap(), [SocketAddr::from(([127, 0, 0, 1], 6001))]);
+    }
+
+    #[test]
+    fn deny_beats_allow() {
+        let policy = policy("8.8.8.8, 127.0.0.1", "8.8.0.0/16, 127.0.0.1");
+        assert!(policy.check_host("8.8.8.8", 6000).is_err());
+        assert!(policy.check_host("127.0.0.1", 6000).is_err());
+        assert!(policy.check_host("1.1.1.1", 6000).is_ok());
+    }
+
+    #[test]
+    fn check_needs_a_host() {
+        let policy = policy("", "");
+        assert!(policy.check(":0").is_err());
+        assert!(policy.check("127.0.0.1:0").is_err());
+        assert_eq!(policy.check("8.8.8.8:1").unwrap(), [SocketAddr::from(([8, 8, 8, 8], 6001))]);
+    }
+}
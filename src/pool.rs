@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::net::{Shutdown, TcpStream};
+use std::os::fd::AsFd;
+use std::sync::{mpsc, Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::ConnectionExt;
+use x11rb::rust_connection::RustConnection;
+
+//How long an idle connection is kept around for the next fish to the same display
+pub const POOL_TTL: Duration = Duration::from_secs(300);
+
+//Connections to displays we've sent fish to recently, so warm Lambdas don't reconnect every time
+//Connections are checked out while in use, so two sessions never read each other's events
+static POOL: LazyLock<Mutex<HashMap<String, Pooled>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+//Cookies go in the key hashed, with a key of its own per process so nobody can work one out from the other
+static COOKIE_HASHER: LazyLock<RandomState> = LazyLock::new(RandomState::new);
+
+struct Pooled {
+    conn: Arc<RustConnection>,
+    screen_num: usize,
+    returned: Instant,
+}
+
+//What a connection is pooled under, the address and the cookie it was let in with
+//Someone without the cookie gets a connection of their own, and finds out the display won't have them
+pub(crate) fn key(address: &str, cookie: Option<&[u8]>) -> String {
+    match cookie {
+        Some(cookie) => format!("{} {:016x}", address, COOKIE_HASHER.hash_one(cookie)),
+        None => address.to_string(),
+    }
+}
+
+//Check out a connection for this key, if there's one that's still alive
+pub(crate) fn take(key: &str, timeout: Duration) -> Option<(Arc<RustConnection>, usize)> {
+    let pooled = {
+        let mut pool = POOL.lock().ok()?;
+        pool.retain(|_, pooled| pooled.returned.elapsed() < POOL_TTL);
+        pool.remove(key)?
+    };
+    alive(&pooled.conn, timeout).then_some((pooled.conn, pooled.screen_num))
+}
+
+//Cheapest round trip there is, if it fails the connection is dead and just gets dropped
+//Same goes if it takes longer than connecting afresh would be allowed, shutting the socket down unsticks it
+fn alive(conn: &Arc<RustConnection>, timeout: Duration) -> bool {
+    let Ok(socket) = conn.stream().as_fd().try_clone_to_owned().map(TcpStream::from) else {
+        return false;
+    };
+    let (sender, receiver) = mpsc::channel();
+    let conn = conn.clone();
+    thread::spawn(move || {
+        let alive = conn.get_input_focus().ok().and_then(|cookie| cookie.reply().ok()).is_some();
+        let _ = sender.send(alive);
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(alive) => alive,
+        Err(_) => {
+            let _ = socket.shutdown(Shutdown::Both);
+            false
+        }
+    }
+}
+
+//Hand a connection back once the fish is done with it
+pub(crate) fn put(key: &str, conn: Arc<RustConnection>, screen_num: usize) {
+    if let Ok(mut pool) = POOL.lock() {
+        pool.insert(
+            key.to_string(),
+            Pooled {
+                conn,
+                screen_num,
+                returned: Instant::now(),
+            },
+        );
+    }
+}
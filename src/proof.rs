@@ -0,0 +1,41 @@
+use crate::{png, XFishSession};
+use x11rb::image::{Image, PixelLayout};
+use x11rb::protocol::xproto::{Drawable, Screen, VisualType, Visualid};
+
+fn find_visual(screen: &Screen, visual: Visualid) -> Option<&VisualType> {
+    screen
+        .allowed_depths
+        .iter()
+        .flat_map(|depth| depth.visuals.iter())
+        .find(|visual_type| visual_type.visual_id == visual)
+}
+
+impl XFishSession {
+    //Read back what ended up on the screen and turn it into a PNG, as proof the fish arrived
+    //Windows that are covered up or off screen can't be read, so the backing pixmap is the fallback
+    pub(crate) fn capture(
+        &self,
+        drawable: Drawable,
+        fallback: Option<Drawable>,
+        (width, height): (u16, u16),
+    ) -> Option<Vec<u8>> {
+        let conn = &*self.conn;
+        let screen = self.screen();
+
+        let (image, visual) = Image::get(conn, drawable, 0, 0, width, height)
+            .ok()
+            .or_else(|| Image::get(conn, fallback?, 0, 0, width, height).ok())?;
+        //Pixmaps don't have a visual, they're in the same format as the root window
+        let visual = if visual == 0 { screen.root_visual } else { visual };
+        let layout = PixelLayout::from_visual_type(*find_visual(screen, visual)?).ok()?;
+
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let (red, green, blue) = layout.decode(image.get_pixel(x, y));
+                rgba.extend_from_slice(&[(red >> 8) as u8, (green >> 8) as u8, (blue >> 8) as u8, 255]);
+            }
+        }
+        png::encode_rgba(width, height, rgba).ok()
+    }
+}
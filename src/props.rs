@@ -0,0 +1,82 @@
+use crate::{fish_bounds, fish_csv, Fish, FishError};
+use std::str::FromStr;
+
+//Scenery around the fish, so the window's a little scene rather than a lone fish
+//Each one is drawn in a 100 by 100 box in props/, which gets stretched to wherever it goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prop {
+    Seaweed,
+    Bubbles,
+    WaterLine,
+}
+
+impl FromStr for Prop {
+    type Err = FishError;
+
+    fn from_str(prop: &str) -> Result<Self, Self::Err> {
+        match prop.trim().to_ascii_lowercase().as_str() {
+            "seaweed" => Ok(Prop::Seaweed),
+            "bubbles" => Ok(Prop::Bubbles),
+            "waterline" | "water_line" => Ok(Prop::WaterLine),
+            _ => Err(FishError::BadParams(format!(
+                "don't know the {:?} prop, try seaweed, bubbles or waterline",
+                prop
+            ))),
+        }
+    }
+}
+
+//Comma separated, like "seaweed,bubbles"
+pub fn parse_props(props: &str) -> Result<Vec<Prop>, FishError> {
+    props
+        .split(',')
+        .filter(|prop| !prop.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+impl Prop {
+    fn csv(self) -> &'static str {
+        match self {
+            Prop::Seaweed => include_str!("../props/seaweed.csv"),
+            Prop::Bubbles => include_str!("../props/bubbles.csv"),
+            Prop::WaterLine => include_str!("../props/waterline.csv"),
+        }
+    }
+
+    //Left, top, width and height of the boxes it goes in, going by the fish's bounding box
+    fn places(self, (left, top): (f64, f64), (right, bottom): (f64, f64)) -> Vec<[f64; 4]> {
+        let (width, height) = (right - left, bottom - top);
+        match self {
+            //A clump on each side, growing up from a bit below the fish
+            Prop::Seaweed => {
+                let (clump_width, clump_height) = (height * 0.5, height * 1.3);
+                let clump_top = bottom + height * 0.2 - clump_height;
+                vec![
+                    [left - width * 0.05 - clump_width, clump_top, clump_width, clump_height],
+                    [right + width * 0.05, clump_top, clump_width, clump_height],
+                ]
+            }
+            //Rising off the fish's far end, in a square box so they stay round
+            Prop::Bubbles => vec![[right - height * 0.3, top - height * 0.6, height * 0.6, height * 0.6]],
+            //Rippling above it all, wider than the fish
+            Prop::WaterLine => vec![[left - width * 0.2, top - height * 0.75, width * 1.4, height * 0.1]],
+        }
+    }
+}
+
+//Add the props around the fish, in the order asked for, after the fish so they draw last
+pub fn arrange(fish: &mut Fish, props: &[Prop]) {
+    let Some((top_left, bottom_right)) = fish_bounds(fish) else {
+        return;
+    };
+    for &prop in props {
+        let lines = fish_csv::parse(prop.csv()).expect("bundled props are valid CSV");
+        for [x, y, width, height] in prop.places(top_left, bottom_right) {
+            let placed = lines
+                .iter()
+                .map(|line| line.iter().map(|&(px, py)| (x + px / 100.0 * width, y + py / 100.0 * height)).collect());
+            fish.extend(placed);
+        }
+    }
+}
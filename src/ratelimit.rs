@@ -0,0 +1,150 @@
+use crate::FishError;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+//One fish per display per minute is plenty of fish
+pub const DEFAULT_TARGET_COOLDOWN: Duration = Duration::from_secs(60);
+pub const DEFAULT_SOURCE_COOLDOWN: Duration = Duration::from_secs(10);
+
+//When each key last got a fish, only as good as the warm container it lives in
+static LAST_SENT: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+//Keeps people from spamming someone's X server with fish
+//XFISH_TARGET_COOLDOWN_SECS and XFISH_SOURCE_COOLDOWN_SECS set the cooldowns, 0 turns one off
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    pub target_cooldown: Duration,
+    pub source_cooldown: Duration,
+}
+
+fn cooldown_from_env(name: &str, default: Duration) -> Duration {
+    std::env::var(name)
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(default)
+}
+
+pub(crate) fn limited(what: &str, retry_after: Duration) -> FishError {
+    //Round up, telling someone to retry in 0 seconds is how you get retried at immediately
+    let retry_after = Duration::from_secs(retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64);
+    FishError::RateLimited(
+        format!("{} got a fish recently, try again in {}s", what, retry_after.as_secs()),
+        retry_after,
+    )
+}
+
+impl RateLimiter {
+    pub fn from_env() -> Self {
+        RateLimiter {
+            target_cooldown: cooldown_from_env("XFISH_TARGET_COOLDOWN_SECS", DEFAULT_TARGET_COOLDOWN),
+            source_cooldown: cooldown_from_env("XFISH_SOURCE_COOLDOWN_SECS", DEFAULT_SOURCE_COOLDOWN),
+        }
+    }
+
+    //Check both cooldowns, and if neither is running, start them both
+    pub fn check(&self, target: &str, source: Option<&str>) -> Result<(), FishError> {
+        self.start(Some(target), source)
+    }
+
+    //Only the sender's, for batches, where it's checked once up front for the whole lot
+    pub fn check_source(&self, source: &str) -> Result<(), FishError> {
+        self.start(None, Some(source))
+    }
+
+    fn start(&self, target: Option<&str>, source: Option<&str>) -> Result<(), FishError> {
+        let Ok(mut last_sent) = LAST_SENT.lock() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        last_sent.retain(|key, sent| now.duration_since(*sent) < self.cooldown_for(key));
+
+        let target_key = target.map(|target| format!("target:{}", target));
+        let source_key = source.map(|source| format!("source:{}", source));
+        for (key, what) in [(&target_key, target.unwrap_or_default()), (&source_key, "your address")] {
+            let Some(key) = key else {
+                continue;
+            };
+            if let Some(sent) = last_sent.get(key) {
+                let retry_after = self.cooldown_for(key).saturating_sub(now.duration_since(*sent));
+                return Err(limited(what, retry_after));
+            }
+        }
+
+        if let Some(target_key) = target_key.filter(|_| !self.target_cooldown.is_zero()) {
+            last_sent.insert(target_key, now);
+        }
+        if let Some(source_key) = source_key.filter(|_| !self.source_cooldown.is_zero()) {
+            last_sent.insert(source_key, now);
+        }
+        Ok(())
+    }
+
+    fn cooldown_for(&self, key: &str) -> Duration {
+        if key.starts_with("source:") {
+            self.source_cooldown
+        } else {
+            self.target_cooldown
+        }
+    }
+}
+
+//Shared cooldowns across every container, for deployments that set XFISH_RATE_LIMIT_TABLE
+//The table needs a string partition key called `key`, and works best with TTL on `expires`
+#[cfg(feature = "dynamodb")]
+pub mod dynamo {
+    use super::limited;
+    use crate::FishError;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use aws_sdk_dynamodb::Client;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub struct DynamoRateLimiter {
+        client: Client,
+        table: String,
+    }
+
+    impl DynamoRateLimiter {
+        pub async fn from_env() -> Option<Self> {
+            let table = std::env::var("XFISH_RATE_LIMIT_TABLE").ok()?;
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            Some(DynamoRateLimiter {
+                client: Client::new(&config),
+                table,
+            })
+        }
+
+        //Claim the key for the cooldown, unless someone else already has it
+        pub async fn claim(&self, key: &str, what: &str, cooldown: Duration) -> Result<(), FishError> {
+            if cooldown.is_zero() {
+                return Ok(());
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let expires = now + cooldown.as_secs();
+            let result = self
+                .client
+                .put_item()
+                .table_name(&self.table)
+                .item("key", AttributeValue::S(key.to_string()))
+                .item("expires", AttributeValue::N(expires.to_string()))
+                .condition_expression("attribute_not_exists(#key) OR #expires < :now")
+                .expression_attribute_names("#key", "key")
+                .expression_attribute_names("#expires", "expires")
+                .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+                .send()
+                .await;
+            match result {
+                Ok(_) => Ok(()),
+                Err(err) if err.as_service_error().is_some_and(|err| err.is_conditional_check_failed_exception()) => {
+                    Err(limited(what, cooldown))
+                }
+                //Rate limiting is a nice to have, a broken table shouldn't stop the fish
+                Err(err) => {
+                    println!("Couldn't check the rate limit table: {}", err);
+                    Ok(())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{color, Cap, DrawOptions};
+use crate::surface::Surface;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::render::{
+    self, ConnectionExt as _, CreatePictureAux, Fixed, PictOp, PictType, Pictformat, Picture, Pointfix, Triangle,
+};
+use x11rb::protocol::xproto::{Drawable, Point, Screen};
+
+//Triangles is 24 bytes of header and 24 bytes per triangle
+const TRIANGLES_HEADER: usize = 24;
+const TRIANGLE: usize = 24;
+//Same thickness as a core zero width line, just with soft edges
+const HAIRLINE_WIDTH: f64 = 1.0;
+
+//What RENDER needs to draw smooth lines: the color to paint with, and the formats to paint in
+//The fill picture is the caller's to free
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Brush {
+    pub fill: Picture,
+    //8 bit alpha, which is what makes the edges smooth
+    mask: Pictformat,
+    //Matches the window's visual, pixmaps of the same depth can use it too
+    target: Pictformat,
+    width: f64,
+    cap: Cap,
+}
+
+fn fixed(value: f64) -> Fixed {
+    (value * 65536.0).round() as Fixed
+}
+
+impl Brush {
+    //None if the server has no RENDER, or it can't draw in the window's visual
+    pub fn new(
+        conn: &impl Connection,
+        screen: &Screen,
+        surface: &Surface,
+        options: &DrawOptions,
+    ) -> Result<Option<Self>, ReplyOrIdError> {
+        if conn.extension_information(render::X11_EXTENSION_NAME)?.is_none() {
+            return Ok(None);
+        }
+        let formats = conn.render_query_pict_formats()?.reply()?;
+        let mask = formats.formats.iter().find(|format| {
+            format.type_ == PictType::DIRECT
+                && format.depth == 8
+                && format.direct.alpha_mask == 0xff
+                && format.direct.red_mask == 0
+                && format.direct.green_mask == 0
+                && format.direct.blue_mask == 0
+        });
+        let target = formats
+            .screens
+            .iter()
+            .flat_map(|screen| screen.depths.iter())
+            .flat_map(|depth| depth.visuals.iter())
+            .find(|visual| visual.visual == surface.visual);
+        let (Some(mask), Some(target)) = (mask, target) else {
+            return Ok(None);
+        };
+
+        let (red, green, blue) = color::lookup_rgb(conn, screen, options.color.as_deref());
+        let fill = conn.generate_id()?;
+        conn.render_create_solid_fill(
+            fill,
+            render::Color {
+                red,
+                green,
+                blue,
+                alpha: 0xffff,
+            },
+        )?;
+        Ok(Some(Brush {
+            fill,
+            mask: mask.id,
+            target: target.format,
+            width: (options.line_width as f64).max(HAIRLINE_WIDTH),
+            cap: options.cap,
+        }))
+    }
+
+    //Draw a line as a strip of triangles, which the server anti-aliases for us
+    pub fn line(&self, conn: &impl Connection, drawable: Drawable, points: &[Point]) -> Result<(), ReplyOrIdError> {
+        let triangles = triangulate(points, self.width / 2.0, self.cap);
+        if triangles.is_empty() {
+            return Ok(());
+        }
+        let picture = conn.generate_id()?;
+        conn.render_create_picture(picture, drawable, self.target, &CreatePictureAux::new())?;
+        let per_request = (conn.maximum_request_bytes() - TRIANGLES_HEADER) / TRIANGLE;
+        for chunk in triangles.chunks(per_request.max(1)) {
+            conn.render_triangles(PictOp::OVER, self.fill, picture, self.mask, 0, 0, chunk)?;
+        }
+        conn.render_free_picture(picture)?;
+        Ok(())
+    }
+}
+
+//Each segment becomes a rectangle, two triangles, stretched half a width past each end so corners don't show gaps
+//Butt caps stop flush at the very ends of the line, round ones get approximated by square ones
+fn triangulate(points: &[Point], half: f64, cap: Cap) -> Vec<Triangle> {
+    let last = points.len().saturating_sub(2);
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(1) * 2);
+    for (index, pair) in points.windows(2).enumerate() {
+        //Core lines go through pixel centers, RENDER puts those at +0.5
+        let (x0, y0) = (pair[0].x as f64 + 0.5, pair[0].y as f64 + 0.5);
+        let (x1, y1) = (pair[1].x as f64 + 0.5, pair[1].y as f64 + 0.5);
+        let length = (x1 - x0).hypot(y1 - y0);
+        //A segment that goes nowhere is a dot, so give it any direction
+        let (dx, dy) = if length == 0.0 {
+            (half, 0.0)
+        } else {
+            ((x1 - x0) / length * half, (y1 - y0) / length * half)
+        };
+        let flush = cap == Cap::Butt;
+        let start = if flush && index == 0 { 0.0 } else { 1.0 };
+        let end = if flush && index == last { 0.0 } else { 1.0 };
+        let corner = |x: f64, y: f64| Pointfix { x: fixed(x), y: fixed(y) };
+        let a = corner(x0 - dx * start - dy, y0 - dy * start + dx);
+        let b = corner(x0 - dx * start + dy, y0 - dy * start - dx);
+        let c = corner(x1 + dx * end + dy, y1 + dy * end - dx);
+        let d = corner(x1 + dx * end - dy, y1 + dy * end + dx);
+        triangles.push(Triangle { p1: a, p2: b, p3: c });
+        triangles.push(Triangle { p1: a, p2: c, p3: d });
+    }
+    triangles
+}
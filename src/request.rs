@@ -0,0 +1,379 @@
+use crate::ascii::{self, AsciiOptions};
+use crate::find::WindowQuery;
+use crate::overlay::OverlayChoice;
+use crate::props::{self, Prop};
+use crate::theme::Theme;
+use crate::transform::{self, Transform};
+use crate::{
+    caption, dial, hints, parse_class, parse_window_id, school, style, DrawOptions, FishError, Mode, Target, LINE_DELAY,
+    DEFAULT_MARGIN, MAX_LINE_DELAY, MAX_MARGIN, MAX_WINDOW_SIZE, MIN_WINDOW_SIZE, Proxy,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+//Everything a request can ask for, already checked
+//Comes from the query string, with a JSON body's "config" object filling in anything the query string doesn't say
+#[derive(Debug, Clone)]
+pub struct RequestConfig {
+    pub address: Option<String>,
+    //Same seed, same fish
+    pub seed: Option<u64>,
+    //Today's fish, the one everyone gets today
+    pub daily: bool,
+    pub fish_id: Option<String>,
+    //Addresses come in the body instead of the query string
+    pub batch: bool,
+    //IANA time zone to check for 11:11 in
+    pub tz: Option<String>,
+    //What clientside JS thought of the time, "bad" if it isn't 11:11
+    pub time: Option<String>,
+    //Hold the fish until then, an RFC 3339 time or the next 11:11 in tz
+    pub deliver_at: Option<String>,
+    pub at_1111: bool,
+    pub count: usize,
+    pub creature: Option<String>,
+    //Hats and bubbles on special days
+    pub overlay: OverlayChoice,
+    //Seaweed and such around the fish
+    pub props: Vec<Prop>,
+    //flip, rotate and scale
+    pub transform: Transform,
+    //Stretch whatever's drawn to fill the window, leaving margin percent empty on each side
+    pub fit: bool,
+    pub margin: f64,
+    //png or svg to get a picture back instead of a window
+    pub format: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    //Tries before giving up on a display that refuses or doesn't answer
+    pub connect_attempts: Option<u32>,
+    //socks5://, socks5h:// or http:// to reach the display through
+    pub proxy: Option<Proxy>,
+    //Log in to the display's host and draw from there, for X servers that don't listen on TCP
+    pub ssh: Option<SshRequest>,
+    pub cookie: Option<String>,
+    pub ttl: Option<Duration>,
+    pub all_screens: bool,
+    pub detach: bool,
+    //Gets POSTed to when the recipient closes the window
+    pub callback_url: Option<String>,
+    //For deployments that only draw from signed links, Unix seconds and the signature over address and expires
+    pub expires: Option<u64>,
+    pub sig: Option<String>,
+    pub options: DrawOptions,
+    //For fish that come back as text
+    pub ascii: AsciiOptions,
+}
+
+//Who to log in as, and which of the keys we keep to do it with
+#[derive(Debug, Clone)]
+pub struct SshRequest {
+    pub user: String,
+    pub port: u16,
+    pub key: String,
+}
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+//Pulls typed fields out of the params one at a time, keeping every complaint instead of stopping at the first
+struct Fields {
+    params: Map<String, Value>,
+    errors: Vec<String>,
+}
+
+impl Fields {
+    //Query strings only have strings, so "12" has to count as a number and "true" as a bool
+    fn get<T: DeserializeOwned>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let value = self.params.get(name)?.clone();
+        let parsed = match &value {
+            Value::String(text) => serde_json::from_value(value.clone())
+                .or_else(|_| serde_json::from_str(text.trim()))
+                .ok(),
+            _ => serde_json::from_value(value).ok(),
+        };
+        if parsed.is_none() {
+            self.errors.push(format!("{} should be {}", name, expected));
+        }
+        parsed
+    }
+
+    fn string(&mut self, name: &str) -> Option<String> {
+        match self.params.get(name)? {
+            Value::String(text) => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            _ => {
+                self.errors.push(format!("{} should be a string", name));
+                None
+            }
+        }
+    }
+
+    //true/false, or 1/0 like a checkbox would send
+    fn flag(&mut self, name: &str) -> bool {
+        self.flag_or(name, false)
+    }
+
+    //Same, for flags something else might already have turned on
+    fn flag_or(&mut self, name: &str, default: bool) -> bool {
+        let Some(value) = self.params.get(name) else {
+            return default;
+        };
+        match value {
+            Value::Bool(flag) => *flag,
+            Value::Number(number) if number.as_u64() == Some(1) => true,
+            Value::Number(number) if number.as_u64() == Some(0) => false,
+            Value::String(text) if matches!(text.trim(), "true" | "1") => true,
+            Value::String(text) if matches!(text.trim(), "false" | "0" | "") => false,
+            _ => {
+                self.errors.push(format!("{} should be true or false", name));
+                default
+            }
+        }
+    }
+
+    fn number<T>(&mut self, name: &str, range: RangeInclusive<T>) -> Option<T>
+    where
+        T: DeserializeOwned + PartialOrd + Display,
+    {
+        let expected = format!("a number from {} to {}", range.start(), range.end());
+        let number = self.get::<T>(name, &expected)?;
+        if range.contains(&number) {
+            Some(number)
+        } else {
+            self.errors.push(format!("{} should be {}", name, expected));
+            None
+        }
+    }
+
+    //Anything with its own parser, which already knows how to explain itself
+    fn parsed<T: FromStr<Err = FishError>>(&mut self, name: &str) -> Option<T> {
+        let text = self.string(name)?;
+        match text.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.errors.push(format!("{}: {}", name, err));
+                None
+            }
+        }
+    }
+
+    fn choice<T: Copy>(&mut self, name: &str, choices: &[(&str, T)], default: T) -> T {
+        let Some(text) = self.string(name) else {
+            return default;
+        };
+        match choices.iter().find(|(choice, _)| *choice == text.trim()) {
+            Some((_, value)) => *value,
+            None => {
+                let names: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+                self.errors.push(format!("{} should be one of {}", name, names.join(", ")));
+                default
+            }
+        }
+    }
+}
+
+impl RequestConfig {
+    //Query string params go first, body ones only count where the query string didn't say
+    pub fn from_params<'a>(
+        query: impl IntoIterator<Item = (&'a str, &'a str)>,
+        body: Option<&Value>,
+    ) -> Result<Self, FishError> {
+        let mut params = Map::new();
+        for (name, value) in query {
+            params
+                .entry(name.to_string())
+                .or_insert_with(|| Value::String(value.to_string()));
+        }
+        if let Some(Value::Object(config)) = body.and_then(|body| body.get("config")) {
+            for (name, value) in config {
+                params.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        let mut fields = Fields {
+            params,
+            errors: Vec::new(),
+        };
+        let config = Self::from_fields(&mut fields);
+        if fields.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(FishError::BadParams(format!("bad params: {}", fields.errors.join("; "))))
+        }
+    }
+
+    fn from_fields(fields: &mut Fields) -> Self {
+        let defaults = DrawOptions::default();
+
+        //Milliseconds between lines, more is slower
+        let line_delay = fields
+            .number::<u64>("speed", 0..=MAX_LINE_DELAY.as_millis() as u64)
+            .map_or(LINE_DELAY, Duration::from_millis);
+        let size = (
+            fields
+                .number("w", MIN_WINDOW_SIZE.0..=MAX_WINDOW_SIZE.0)
+                .unwrap_or(defaults.size.0),
+            fields
+                .number("h", MIN_WINDOW_SIZE.1..=MAX_WINDOW_SIZE.1)
+                .unwrap_or(defaults.size.1),
+        );
+        //Either one pins the window down, otherwise it goes in the middle of the monitor
+        let position = match (fields.get::<i16>("x", "a number"), fields.get::<i16>("y", "a number")) {
+            (None, None) => None,
+            (x, y) => Some((x.unwrap_or(0), y.unwrap_or(0))),
+        };
+        let target = fields.choice("target", &[("window", Target::Window), ("root", Target::Root)], Target::Window);
+        let mode = fields.choice(
+            "mode",
+            &[("still", Mode::Still), ("aquarium", Mode::Aquarium), ("xtest", Mode::XTest)],
+            Mode::Still,
+        );
+        let anti_alias = fields.choice("render", &[("auto", true), ("render", true), ("core", false)], true);
+        //Themes only fill in what wasn't picked by hand
+        //A hand picked background gets a fish color to match it, not the theme's
+        let theme: Theme = fields.parsed("theme").unwrap_or_default();
+        let background = fields.string("bg");
+        let color = fields
+            .string("color")
+            .or(theme.color.filter(|_| background.is_none()).map(str::to_string));
+        let background = background.or(theme.background.map(str::to_string));
+
+        let options = DrawOptions {
+            target,
+            mode,
+            color,
+            background,
+            line_delay,
+            instant: fields.flag("instant"),
+            proof: fields.flag("proof"),
+            transparent: fields.flag("transparent"),
+            shaped: fields.flag("shape"),
+            popup: fields.flag("popup"),
+            fullscreen: fields.flag("fullscreen"),
+            interactive: fields.flag("interactive"),
+            tray: fields.flag("tray"),
+            clipboard: fields.flag("clipboard"),
+            attention: fields.flag("attention"),
+            theme: theme.name,
+            anti_alias,
+            line_width: fields
+                .number("line_width", 0..=style::MAX_LINE_WIDTH)
+                .unwrap_or(theme.line_width),
+            dashes: fields
+                .string("dash")
+                .and_then(|dash| style::parse_dashes(&dash).map_err(|err| fields.errors.push(err.to_string())).ok())
+                .unwrap_or_else(|| theme.dashes.to_vec()),
+            cap: fields.parsed("cap_style").unwrap_or(theme.cap),
+            filled: fields.flag_or("fill", theme.filled),
+            palette: fields.parsed("palette").unwrap_or(theme.palette),
+            //Every fish comes with its name underneath, caption= with nothing after it turns that off
+            caption: Some(fields.string("caption").unwrap_or_else(|| caption::DEFAULT_CAPTION.to_string())),
+            font: fields.string("font"),
+            title: fields.string("title").unwrap_or_else(|| defaults.title.clone()),
+            class: fields
+                .string("class")
+                .map(|class| parse_class(&class))
+                .unwrap_or_else(|| defaults.class.clone()),
+            window_type: fields.parsed("window_type").unwrap_or_default(),
+            states: fields
+                .string("state")
+                .and_then(|state| hints::parse_states(&state).map_err(|err| fields.errors.push(err.to_string())).ok())
+                .unwrap_or_default(),
+            size,
+            position,
+            monitor: fields.get("monitor", "a monitor number"),
+            window: fields
+                .string("window")
+                .and_then(|window| parse_window_id(&window).map_err(|err| fields.errors.push(err.to_string())).ok()),
+            find: fields
+                .string("target_title")
+                .map(WindowQuery::Title)
+                .or_else(|| fields.string("target_class").map(WindowQuery::Class)),
+            ..defaults
+        };
+
+        let proxy = fields.parsed("proxy");
+        let ssh = match (fields.string("ssh_user"), fields.string("ssh_key")) {
+            (Some(user), Some(key)) => Some(SshRequest {
+                user,
+                port: fields.get("ssh_port", "a port number").unwrap_or(DEFAULT_SSH_PORT),
+                key,
+            }),
+            (None, None) => None,
+            _ => {
+                fields.errors.push("ssh_user and ssh_key go together".to_string());
+                None
+            }
+        };
+        if proxy.is_some() && ssh.is_some() {
+            fields.errors.push("proxy or ssh, not both".to_string());
+        }
+        let deliver_at = fields.string("deliver_at");
+        let at_1111 = fields.flag("at_1111");
+        if deliver_at.is_some() && at_1111 {
+            fields.errors.push("deliver_at or at_1111, not both".to_string());
+        }
+        if at_1111 && !fields.params.contains_key("tz") {
+            fields.errors.push("at_1111 needs tz, to know whose 11:11 it is".to_string());
+        }
+        let daily = fields.flag("daily");
+        if daily && fields.params.contains_key("seed") {
+            fields.errors.push("daily or seed, not both".to_string());
+        }
+        let callback_url = fields.string("callback_url");
+        if let Some(url) = &callback_url {
+            let lower = url.to_ascii_lowercase();
+            if !lower.starts_with("http://") && !lower.starts_with("https://") {
+                fields.errors.push(format!("callback_url should be an http:// or https:// URL, not {:?}", url));
+            }
+        }
+
+        RequestConfig {
+            address: fields.string("address"),
+            seed: fields.get("seed", "a number"),
+            daily,
+            fish_id: fields.string("fish_id"),
+            batch: fields.flag("batch"),
+            tz: fields.string("tz"),
+            time: fields.string("time"),
+            deliver_at,
+            at_1111,
+            count: fields.number("count", 1..=school::MAX_COUNT).unwrap_or(1),
+            creature: fields.string("creature"),
+            overlay: fields.parsed("overlay").unwrap_or_default(),
+            props: fields
+                .string("props")
+                .and_then(|list| props::parse_props(&list).map_err(|err| fields.errors.push(err.to_string())).ok())
+                .unwrap_or_default(),
+            transform: Transform {
+                flip: fields.parsed("flip"),
+                rotate: fields.number("rotate", -360.0..=360.0).unwrap_or(0.0),
+                scale: fields.number("scale", transform::MIN_SCALE..=transform::MAX_SCALE).unwrap_or(1.0),
+            },
+            //Off unless asked for, fish people already have shouldn't change shape under them
+            fit: fields.flag_or("fit", false),
+            margin: fields.number("margin", 0.0..=MAX_MARGIN).unwrap_or(DEFAULT_MARGIN),
+            format: fields.string("format"),
+            connect_timeout: fields.get("connect_timeout", "a number of milliseconds").map(Duration::from_millis),
+            connect_attempts: fields.number("connect_attempts", 1..=dial::MAX_CONNECT_ATTEMPTS),
+            proxy,
+            ssh,
+            cookie: fields.string("cookie"),
+            ttl: fields.get("ttl", "a number of seconds").map(Duration::from_secs),
+            all_screens: fields.flag("all_screens"),
+            detach: fields.flag("detach"),
+            callback_url,
+            expires: fields.get("expires", "a time in Unix seconds"),
+            sig: fields.string("sig"),
+            options,
+            ascii: AsciiOptions {
+                width: fields
+                    .number("ascii_width", ascii::MIN_ASCII_WIDTH..=ascii::MAX_ASCII_WIDTH)
+                    .unwrap_or(ascii::DEFAULT_ASCII_WIDTH),
+                charset: fields.parsed("charset").unwrap_or_default(),
+            },
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::Error;
+use aws_sdk_sqs::Client;
+use chrono::{DateTime, Utc};
+
+//Fish waiting for their moment on an SQS queue, for deployments that set XFISH_SCHEDULE_QUEUE_URL
+//The queue should be the one the sqs build reads from, so held fish come back through the same handler
+pub struct ScheduleQueue {
+    client: Client,
+    queue_url: String,
+}
+
+//The longest SQS will hold a message back for
+const MAX_DELAY_SECONDS: i64 = 15 * 60;
+
+impl ScheduleQueue {
+    pub async fn from_env() -> Option<Self> {
+        let queue_url = std::env::var("XFISH_SCHEDULE_QUEUE_URL").ok()?;
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Some(ScheduleQueue {
+            client: Client::new(&config),
+            queue_url,
+        })
+    }
+
+    //Put the request back on the queue until nearer the time
+    //Anything more than 15 minutes off comes back early and gets held again, as many times as it takes
+    pub async fn hold(&self, body: &serde_json::Value, until: DateTime<Utc>) -> Result<(), Error> {
+        let delay = (until - Utc::now()).num_seconds().clamp(0, MAX_DELAY_SECONDS);
+        self.client
+            .send_message()
+            .queue_url(&self.queue_url)
+            .message_body(body.to_string())
+            .delay_seconds(delay as i32)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{Fish, FISH_CANVAS};
+
+//More than this and they're too small to be fish
+pub const MAX_COUNT: usize = 16;
+
+//Lay several fish out in a grid on one canvas, so they can be drawn like one big fish
+//Lines are interleaved, one from each fish in turn, so they all appear to draw at the same time
+pub fn arrange(school: &[Fish]) -> Fish {
+    if school.len() <= 1 {
+        return school.first().cloned().unwrap_or_default();
+    }
+
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let cols = (school.len() as f64).sqrt().ceil() as usize;
+    let rows = school.len().div_ceil(cols);
+    let cell_width = canvas_width / cols as f64;
+    let cell_height = canvas_height / rows as f64;
+    let scale = (cell_width / canvas_width).min(cell_height / canvas_height);
+
+    let placed: Vec<Fish> = school
+        .iter()
+        .enumerate()
+        .map(|(i, fish)| {
+            //Centered in its cell
+            let left = (i % cols) as f64 * cell_width + (cell_width - canvas_width * scale) / 2.0;
+            let top = (i / cols) as f64 * cell_height + (cell_height - canvas_height * scale) / 2.0;
+            fish.iter()
+                .map(|line| line.iter().map(|&(x, y)| (x * scale + left, y * scale + top)).collect())
+                .collect()
+        })
+        .collect();
+
+    let longest = placed.iter().map(|fish| fish.len()).max().unwrap_or_default();
+    (0..longest)
+        .flat_map(|i| placed.iter().filter_map(move |fish| fish.get(i).cloned()))
+        .collect()
+}
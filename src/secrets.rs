@@ -0,0 +1,19 @@
+use crate::Error;
+use aws_sdk_secretsmanager::Client;
+
+//A secret straight from the environment, or the ID of one in Secrets Manager under the same name with _SECRET_ID on
+//The environment wins if both are set, none if neither is
+pub async fn from_env(name: &str) -> Result<Option<String>, Error> {
+    if let Ok(value) = std::env::var(name) {
+        return Ok(Some(value));
+    }
+    let Ok(id) = std::env::var(format!("{}_SECRET_ID", name)) else {
+        return Ok(None);
+    };
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let secret = Client::new(&config).get_secret_value().secret_id(&id).send().await?;
+    match secret.secret_string() {
+        Some(value) => Ok(Some(value.to_string())),
+        None => Err(format!("secret {} isn't text", id).into()),
+    }
+}
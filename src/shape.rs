@@ -0,0 +1,56 @@
+use crate::{poly_line, DrawOptions};
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::shape::{self, ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::{
+    CapStyle, ChangeGCAux, ConnectionExt, CoordMode, CreateGCAux, JoinStyle, Point, PolyShape, Rectangle, Window,
+};
+
+//How much window to keep around a line, so the fish isn't cut right through the middle of its lines
+const OUTLINE_WIDTH: u32 = 5;
+//Extra on top of the line's own width, for thick lines
+const OUTLINE_MARGIN: u32 = 4;
+
+//Cut the window down to just the fish, for servers without a compositor to do transparency
+//Servers without SHAPE keep their rectangle, the fish is still there, just with a background
+pub(crate) fn fit_window(
+    conn: &impl Connection,
+    win_id: Window,
+    lines: &[Vec<Point>],
+    (width, height): (u16, u16),
+    options: &DrawOptions,
+) -> Result<(), ReplyOrIdError> {
+    if conn.extension_information(shape::X11_EXTENSION_NAME)?.is_none() {
+        return Ok(());
+    }
+
+    //1 bit pixmap where set bits are the parts of the window that stay
+    let mask = conn.generate_id()?;
+    conn.create_pixmap(1, mask, win_id, width, height)?;
+    let gc = conn.generate_id()?;
+    conn.create_gc(
+        gc,
+        mask,
+        &CreateGCAux::default()
+            .foreground(0)
+            .line_width(OUTLINE_WIDTH.max(options.line_width as u32 + OUTLINE_MARGIN))
+            .cap_style(CapStyle::ROUND)
+            .join_style(JoinStyle::ROUND)
+            .graphics_exposures(0),
+    )?;
+    conn.poly_fill_rectangle(mask, gc, &[Rectangle { x: 0, y: 0, width, height }])?;
+    conn.change_gc(gc, &ChangeGCAux::new().foreground(1))?;
+    for line in lines {
+        //Filled fish keep the inside of every shape too, holes and all
+        if options.filled {
+            conn.fill_poly(mask, gc, PolyShape::COMPLEX, CoordMode::ORIGIN, line)?;
+        }
+        poly_line(conn, mask, gc, line)?;
+    }
+    conn.shape_mask(SO::SET, SK::BOUNDING, win_id, 0, 0, mask)?;
+
+    //The server keeps its own copy of the shape, the mask can go
+    conn.free_gc(gc)?;
+    conn.free_pixmap(mask)?;
+    Ok(())
+}
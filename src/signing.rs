@@ -0,0 +1,116 @@
+use crate::{Error, FishError};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+//Fish links that only work for one display, for deployments that set XFISH_SIGNING_SECRET
+//The link carries `expires` (Unix seconds) and `sig`, the URL safe base64 of HMAC-SHA256 over "<address>\n<expires>"
+//Whoever has the secret can hand out links for their own display without the whole internet getting to draw on it
+pub struct LinkSigner {
+    secret: Vec<u8>,
+}
+
+impl LinkSigner {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        LinkSigner { secret: secret.into() }
+    }
+
+    pub fn sign(&self, address: &str, expires: u64) -> Result<String, Error> {
+        Ok(BASE64.encode(self.mac(address, expires)?))
+    }
+
+    fn mac(&self, address: &str, expires: u64) -> Result<Vec<u8>, Error> {
+        let key = PKey::hmac(&self.secret)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(format!("{}\n{}", address, expires).as_bytes())?;
+        Ok(signer.sign_to_vec()?)
+    }
+
+    //Missing a signature is a 401, one that's wrong or too old is a 403
+    pub fn verify(&self, address: &str, expires: Option<u64>, sig: Option<&str>, now: u64) -> Result<(), Error> {
+        let (Some(expires), Some(sig)) = (expires, sig) else {
+            let msg = "this fish service only draws on displays from signed links, this one needs expires and sig";
+            return Err(FishError::AuthRejected(msg.to_string()).into());
+        };
+        if expires < now {
+            return Err(FishError::AccessDenied("this fish link has expired".to_string()).into());
+        }
+        let expected = self.mac(address, expires)?;
+        let given = BASE64.decode(sig.trim()).unwrap_or_default();
+        //memcmp::eq takes the same time whatever the bytes are, but wants them the same length
+        if given.len() != expected.len() || !openssl::memcmp::eq(&given, &expected) {
+            return Err(FishError::AccessDenied("that sig isn't for this address".to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn signer() -> LinkSigner {
+        LinkSigner::new("fish secret")
+    }
+
+    fn rejection(result: Result<(), Error>) -> FishError {
+        match *result.expect_err("should have been rejected").downcast::<FishError>().unwrap() {
+            err @ (FishError::AuthRejected(_) | FishError::AccessDenied(_)) => err,
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn accepts_its_own_links() {
+        let sig = signer().sign("fish.example.com:0", NOW + 60).unwrap();
+        assert!(signer().verify("fish.example.com:0", Some(NOW + 60), Some(&sig), NOW).is_ok());
+        //Right up to the second it runs out
+        assert!(signer().verify("fish.example.com:0", Some(NOW + 60), Some(&sig), NOW + 60).is_ok());
+        assert!(signer().verify("fish.example.com:0", Some(NOW + 60), Some(&format!(" {} ", sig)), NOW).is_ok());
+    }
+
+    #[test]
+    fn missing_signatures_are_unauthorized() {
+        let sig = signer().sign("fish.example.com:0", NOW + 60).unwrap();
+        let missing = [(None, Some(sig.as_str())), (Some(NOW + 60), None), (None, None)];
+        for (expires, sig) in missing {
+            let result = signer().verify("fish.example.com:0", expires, sig, NOW);
+            assert!(matches!(rejection(result), FishError::AuthRejected(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_expired_links() {
+        let sig = signer().sign("fish.example.com:0", NOW - 1).unwrap();
+        let result = signer().verify("fish.example.com:0", Some(NOW - 1), Some(&sig), NOW);
+        assert!(matches!(rejection(result), FishError::AccessDenied(_)));
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let sig = signer().sign("fish.example.com:0", NOW + 60).unwrap();
+        let mut flipped = sig.clone().into_bytes();
+        flipped[0] = if flipped[0] == b'A' { b'B' } else { b'A' };
+        let flipped = String::from_utf8(flipped).unwrap();
+        let other_secret = LinkSigner::new("another secret").sign("fish.example.com:0", NOW + 60).unwrap();
+        let cases = [
+            //Someone else's display
+            ("other.example.com:0", NOW + 60, sig.as_str()),
+            //Stretching the expiry
+            ("fish.example.com:0", NOW + 3600, sig.as_str()),
+            ("fish.example.com:0", NOW + 60, flipped.as_str()),
+            ("fish.example.com:0", NOW + 60, other_secret.as_str()),
+            ("fish.example.com:0", NOW + 60, &sig[..sig.len() - 2]),
+            ("fish.example.com:0", NOW + 60, ""),
+            ("fish.example.com:0", NOW + 60, "not base64!"),
+        ];
+        for (address, expires, sig) in cases {
+            let result = signer().verify(address, Some(expires), Some(sig), NOW);
+            assert!(matches!(rejection(result), FishError::AccessDenied(_)), "{} {} {:?}", address, expires, sig);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::Atoms;
+use std::time::Instant;
+use x11rb::protocol::xproto::{Atom, ClientMessageEvent, ExposeEvent, Rectangle, Window};
+use x11rb::protocol::Event;
+use x11rb::x11_utils::X11Error;
+
+//What the still window's event loop should do next
+//Working it out doesn't need the connection, doing it does
+#[derive(Debug)]
+pub(crate) enum Action {
+    Nothing,
+    //First expose, so on goes the fish, slowly unless it's instant
+    Animate,
+    //Fish is already up, put back these bits of it
+    Repair(Vec<Rectangle>),
+    //New size, so the fish needs fitting again
+    Resize((u16, u16)),
+    //Window manager wants to know we're alive
+    Ping(ClientMessageEvent),
+    //Done, and why
+    Close(&'static str),
+    //The recipient closed the window, which means they saw the fish
+    Deleted,
+    //Closed unless the window manager just iconified it, which only the server knows
+    Unmapped,
+    Destroyed,
+    Fail(X11Error),
+    //Not something the state machine cares about, the loop might
+    Other(Event),
+}
+
+//Everything about a still window that decides when it redraws and when it closes
+pub(crate) struct SessionState {
+    win_id: Window,
+    deadline: Instant,
+    size: (u16, u16),
+    //The slow drawing effect only happens the first time
+    animated: bool,
+    //Bits of the window that need painting again, collected until the last expose of a batch
+    damage: Vec<Rectangle>,
+    protocols: Atom,
+    delete_window: Atom,
+    ping: Atom,
+}
+
+impl SessionState {
+    pub fn new(atoms: &Atoms, win_id: Window, size: (u16, u16), deadline: Instant) -> Self {
+        SessionState {
+            win_id,
+            deadline,
+            size,
+            animated: false,
+            damage: Vec::new(),
+            protocols: atoms.WM_PROTOCOLS,
+            delete_window: atoms.WM_DELETE_WINDOW,
+            ping: atoms._NET_WM_PING,
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.animated
+    }
+
+    //The whole fish is on, from here on exposes get patched from the pixmap
+    pub fn animated(&mut self) {
+        self.animated = true;
+    }
+
+    //A new fish gets drawn slowly like the first one
+    pub fn restart(&mut self) {
+        self.animated = false;
+        self.damage.clear();
+    }
+
+    //Nothing came in, so it's down to the clock
+    pub fn idle(&self, now: Instant, cancelled: bool) -> Action {
+        if now >= self.deadline {
+            Action::Close("Ran out of time, taking the fish back")
+        } else if cancelled {
+            Action::Close("Drawing was cancelled, taking the fish back")
+        } else {
+            Action::Nothing
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event) -> Action {
+        match event {
+            //More exposes on the way, so wait and do them all in one go
+            Event::Expose(event) if event.count > 0 => {
+                self.damage.push(exposed(&event));
+                Action::Nothing
+            }
+            //Window is visible, so the fish can be drawn, all of it
+            Event::Expose(_) if !self.animated => {
+                self.damage.clear();
+                Action::Animate
+            }
+            Event::Expose(event) => {
+                self.damage.push(exposed(&event));
+                Action::Repair(std::mem::take(&mut self.damage))
+            }
+            Event::ConfigureNotify(event) if (event.width, event.height) != self.size => {
+                self.size = (event.width, event.height);
+                Action::Resize(self.size)
+            }
+            Event::ConfigureNotify(_) | Event::MapNotify(_) | Event::VisibilityNotify(_) => Action::Nothing,
+            Event::ClientMessage(event) if event.format == 32 && event.type_ == self.protocols => {
+                let data = event.data.as_data32();
+                if data[0] == self.ping {
+                    Action::Ping(event)
+                } else if event.window == self.win_id && data[0] == self.delete_window {
+                    Action::Deleted
+                } else {
+                    Action::Nothing
+                }
+            }
+            Event::ClientMessage(_) => Action::Nothing,
+            //For window managers that never heard of WM_DELETE_WINDOW, and close things however they like
+            Event::DestroyNotify(event) if event.window == self.win_id => Action::Destroyed,
+            Event::UnmapNotify(event) if event.window == self.win_id => Action::Unmapped,
+            Event::UnmapNotify(_) => Action::Nothing,
+            Event::Error(err) => Action::Fail(err),
+            event => Action::Other(event),
+        }
+    }
+}
+
+//The part of the window an expose says needs painting again
+pub(crate) fn exposed(event: &ExposeEvent) -> Rectangle {
+    Rectangle {
+        x: event.x as i16,
+        y: event.y as i16,
+        width: event.width,
+        height: event.height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use x11rb::protocol::xproto::{ConfigureNotifyEvent, DestroyNotifyEvent, UnmapNotifyEvent};
+
+    const WINDOW: Window = 0x1e00003;
+    const PROTOCOLS: Atom = 300;
+    const DELETE_WINDOW: Atom = 301;
+    const PING: Atom = 302;
+
+    fn state(deadline: Instant) -> SessionState {
+        SessionState {
+            win_id: WINDOW,
+            deadline,
+            size: (520, 320),
+            animated: false,
+            damage: Vec::new(),
+            protocols: PROTOCOLS,
+            delete_window: DELETE_WINDOW,
+            ping: PING,
+        }
+    }
+
+    fn later() -> Instant {
+        Instant::now() + Duration::from_secs(60)
+    }
+
+    fn expose(x: u16, y: u16, count: u16) -> Event {
+        Event::Expose(ExposeEvent {
+            response_type: 12,
+            sequence: 0,
+            window: WINDOW,
+            x,
+            y,
+            width: 10,
+            height: 10,
+            count,
+        })
+    }
+
+    fn message(window: Window, type_: Atom, protocol: Atom) -> Event {
+        Event::ClientMessage(ClientMessageEvent::new(32, window, type_, [protocol, 0, 0, 0, 0]))
+    }
+
+    fn configure(width: u16, height: u16) -> Event {
+        Event::ConfigureNotify(ConfigureNotifyEvent {
+            response_type: 22,
+            sequence: 0,
+            event: WINDOW,
+            window: WINDOW,
+            above_sibling: 0,
+            x: 0,
+            y: 0,
+            width,
+            height,
+            border_width: 0,
+            override_redirect: false,
+        })
+    }
+
+    #[test]
+    fn first_expose_animates() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(expose(0, 0, 0)), Action::Animate));
+    }
+
+    #[test]
+    fn waits_for_the_last_expose() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(expose(0, 0, 1)), Action::Nothing));
+        assert!(matches!(state.handle_event(expose(0, 0, 0)), Action::Animate));
+    }
+
+    #[test]
+    fn later_exposes_repair_everything_that_was_damaged() {
+        let mut state = state(later());
+        state.handle_event(expose(0, 0, 0));
+        state.animated();
+        assert!(matches!(state.handle_event(expose(0, 0, 2)), Action::Nothing));
+        assert!(matches!(state.handle_event(expose(20, 0, 1)), Action::Nothing));
+        let Action::Repair(damage) = state.handle_event(expose(40, 0, 0)) else {
+            panic!("expected a repair");
+        };
+        let xs: Vec<i16> = damage.iter().map(|rect| rect.x).collect();
+        assert_eq!(xs, [0, 20, 40]);
+        //And it's all been handed over, the next one starts fresh
+        let Action::Repair(damage) = state.handle_event(expose(60, 0, 0)) else {
+            panic!("expected a repair");
+        };
+        assert_eq!(damage.len(), 1);
+    }
+
+    #[test]
+    fn restart_animates_again() {
+        let mut state = state(later());
+        state.animated();
+        state.restart();
+        assert!(!state.is_animated());
+        assert!(matches!(state.handle_event(expose(0, 0, 0)), Action::Animate));
+    }
+
+    #[test]
+    fn resizes_only_when_the_size_changes() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(configure(520, 320)), Action::Nothing));
+        assert!(matches!(state.handle_event(configure(800, 600)), Action::Resize((800, 600))));
+        assert_eq!(state.size(), (800, 600));
+    }
+
+    #[test]
+    fn closes_on_delete_window() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(message(WINDOW, PROTOCOLS, DELETE_WINDOW)), Action::Deleted));
+    }
+
+    #[test]
+    fn ignores_delete_window_for_other_windows() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(message(0x42, PROTOCOLS, DELETE_WINDOW)), Action::Nothing));
+        assert!(matches!(state.handle_event(message(WINDOW, 999, DELETE_WINDOW)), Action::Nothing));
+    }
+
+    #[test]
+    fn answers_pings() {
+        let mut state = state(later());
+        assert!(matches!(state.handle_event(message(WINDOW, PROTOCOLS, PING)), Action::Ping(_)));
+    }
+
+    #[test]
+    fn destroy_and_unmap_of_our_window() {
+        let mut state = state(later());
+        let destroy = Event::DestroyNotify(DestroyNotifyEvent {
+            response_type: 17,
+            sequence: 0,
+            event: WINDOW,
+            window: WINDOW,
+        });
+        assert!(matches!(state.handle_event(destroy), Action::Destroyed));
+        let unmap = |window| {
+            Event::UnmapNotify(UnmapNotifyEvent {
+                response_type: 18,
+                sequence: 0,
+                event: window,
+                window,
+                from_configure: false,
+            })
+        };
+        assert!(matches!(state.handle_event(unmap(WINDOW)), Action::Unmapped));
+        assert!(matches!(state.handle_event(unmap(0x42)), Action::Nothing));
+    }
+
+    #[test]
+    fn closes_at_the_deadline() {
+        let now = Instant::now();
+        let state = state(now + Duration::from_secs(1));
+        assert!(matches!(state.idle(now, false), Action::Nothing));
+        assert!(matches!(state.idle(now + Duration::from_secs(1), false), Action::Close(_)));
+    }
+
+    #[test]
+    fn closes_when_cancelled() {
+        let state = state(later());
+        assert!(matches!(state.idle(Instant::now(), true), Action::Close(_)));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+//How many fish have gone out, for deployments that set XFISH_STATS_TABLE, so the project page can show a live count
+//Counts pile up in memory and get flushed as one atomic ADD per request, so every container adds to the same row
+//The table only needs a "key" string, everything lives on the one row
+pub struct FishCounter {
+    client: Client,
+    table: String,
+    //Outcome to how many since the last flush
+    pending: Mutex<HashMap<&'static str, u64>>,
+}
+
+const TOTALS_KEY: &str = "totals";
+//Outcomes are columns on the totals row, prefixed so they can't bump into anything else on it
+const OUTCOME_PREFIX: &str = "outcome_";
+//How many kinds of failure the stats bother listing
+const TOP_FAILURES: usize = 5;
+
+//The numbers as of the last flush
+#[derive(Debug, Clone, Default)]
+pub struct FishStats {
+    pub total: u64,
+    pub drawn: u64,
+    //Most common first
+    pub failures: Vec<(String, u64)>,
+}
+
+impl FishStats {
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.drawn as f64 / self.total as f64)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let failures: Vec<serde_json::Value> = self
+            .failures
+            .iter()
+            .map(|(code, count)| serde_json::json!({ "code": code, "count": count }))
+            .collect();
+        serde_json::json!({
+            "total": self.total,
+            "drawn": self.drawn,
+            "success_rate": self.success_rate(),
+            "top_failures": failures,
+        })
+    }
+}
+
+impl FishCounter {
+    pub async fn from_env() -> Option<Self> {
+        let table = std::env::var("XFISH_STATS_TABLE").ok()?;
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Some(FishCounter {
+            client: Client::new(&config),
+            table,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    //"drawn", or the error code for whatever went wrong
+    pub fn count(&self, outcome: &'static str) {
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending.entry(outcome).or_default() += 1;
+        }
+    }
+
+    //Everything counted so far onto the totals row, in one go
+    pub async fn flush(&self) -> Result<(), Error> {
+        let pending = match self.pending.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => return Ok(()),
+        };
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let total: u64 = pending.values().sum();
+        let mut adds = vec!["#total :total".to_string()];
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(&self.table)
+            .key("key", AttributeValue::S(TOTALS_KEY.to_string()))
+            .expression_attribute_names("#total", "total")
+            .expression_attribute_values(":total", AttributeValue::N(total.to_string()));
+        for (index, (outcome, count)) in pending.iter().enumerate() {
+            adds.push(format!("#o{} :o{}", index, index));
+            request = request
+                .expression_attribute_names(format!("#o{}", index), format!("{}{}", OUTCOME_PREFIX, outcome))
+                .expression_attribute_values(format!(":o{}", index), AttributeValue::N(count.to_string()));
+        }
+        let result = request.update_expression(format!("ADD {}", adds.join(", "))).send().await;
+        //Put them back for next time rather than lose them
+        if let Err(err) = result {
+            if let Ok(mut unflushed) = self.pending.lock() {
+                for (outcome, count) in pending {
+                    *unflushed.entry(outcome).or_default() += count;
+                }
+            }
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    pub async fn stats(&self) -> Result<FishStats, Error> {
+        let item = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("key", AttributeValue::S(TOTALS_KEY.to_string()))
+            .send()
+            .await?
+            .item
+            .unwrap_or_default();
+        let number = |value: &AttributeValue| value.as_n().ok().and_then(|number| number.parse::<u64>().ok());
+        let mut stats = FishStats {
+            total: item.get("total").and_then(number).unwrap_or(0),
+            ..FishStats::default()
+        };
+        for (name, value) in &item {
+            let (Some(outcome), Some(count)) = (name.strip_prefix(OUTCOME_PREFIX), number(value)) else {
+                continue;
+            };
+            if outcome == "drawn" {
+                stats.drawn = count;
+            } else {
+                stats.failures.push((outcome.to_string(), count));
+            }
+        }
+        stats.failures.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        stats.failures.truncate(TOP_FAILURES);
+        Ok(stats)
+    }
+}
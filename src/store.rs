@@ -0,0 +1,99 @@
+use crate::{fish_csv, stable_hash, Error, Fish, FishError};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+
+//Fish kept in S3 so they can be shared and drawn again, for deployments that set XFISH_FISH_BUCKET
+//Each fish is fish/<id>.csv, with an SVG next to it for people without an X server
+//XFISH_FISH_URL_BASE points share links somewhere nicer than the bucket, like a CDN in front of it
+pub struct FishStore {
+    client: Client,
+    bucket: String,
+    url_base: String,
+}
+
+//The same fish always gets the same ID
+fn fish_id_for(fish_str: &str) -> String {
+    stable_hash(fish_str.as_bytes())
+}
+
+//IDs end up in S3 keys, so only ever let through ones we could have made
+fn check_fish_id(fish_id: &str) -> Result<(), FishError> {
+    if fish_id.len() == 16 && fish_id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(FishError::BadParams(format!("{:?} isn't a fish id", fish_id)))
+    }
+}
+
+impl FishStore {
+    pub async fn from_env() -> Option<Self> {
+        let bucket = std::env::var("XFISH_FISH_BUCKET").ok()?;
+        let url_base = std::env::var("XFISH_FISH_URL_BASE")
+            .unwrap_or_else(|_| format!("https://{}.s3.amazonaws.com", bucket));
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Some(FishStore {
+            client: Client::new(&config),
+            bucket,
+            url_base: url_base.trim_end_matches('/').to_string(),
+        })
+    }
+
+    //Where people can look at a stored fish
+    pub fn url_for(&self, fish_id: &str) -> String {
+        format!("{}/fish/{}.svg", self.url_base, fish_id)
+    }
+
+    //Keep the fish, and the SVG of it if there is one, returning its ID
+    pub async fn save(&self, fish: &Fish, svg: Option<String>) -> Result<String, Error> {
+        let fish_str = fish_csv::write(fish);
+        let fish_id = fish_id_for(&fish_str);
+        self.put(&format!("fish/{}.csv", fish_id), fish_str.into_bytes(), "text/csv")
+            .await?;
+        if let Some(svg) = svg {
+            self.put(&format!("fish/{}.svg", fish_id), svg.into_bytes(), "image/svg+xml")
+                .await?;
+        }
+        Ok(fish_id)
+    }
+
+    //Get a fish back by the ID save gave out
+    pub async fn load(&self, fish_id: &str) -> Result<Fish, Error> {
+        check_fish_id(fish_id)?;
+        let result = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(format!("fish/{}.csv", fish_id))
+            .send()
+            .await;
+        let object = match result {
+            Ok(object) => object,
+            Err(err) if err.as_service_error().is_some_and(|err| err.is_no_such_key()) => {
+                return Err(FishError::NotFound(format!("there's no fish {}", fish_id)).into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let bytes = object.body.collect().await?.into_bytes();
+        Ok(fish_csv::parse(&String::from_utf8_lossy(&bytes))?)
+    }
+
+    //Keep an animation of the fish next to it, returning where people can watch it
+    pub async fn save_gif(&self, fish: &Fish, gif: Vec<u8>) -> Result<String, Error> {
+        let fish_id = fish_id_for(&fish_csv::write(fish));
+        let key = format!("fish/{}.gif", fish_id);
+        self.put(&key, gif, "image/gif").await?;
+        Ok(format!("{}/{}", self.url_base, key))
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<(), Error> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
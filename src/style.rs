@@ -0,0 +1,76 @@
+use crate::FishError;
+use std::str::FromStr;
+
+//Thicker than this and the fish is mostly line
+pub const MAX_LINE_WIDTH: u16 = 32;
+//What dash=true gets, 4 pixels on and 4 off
+pub const DEFAULT_DASHES: [u8; 2] = [4, 4];
+
+//How the ends of lines look, only shows on lines thicker than a hairline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cap {
+    //Stops right at the end point, same as X's default
+    #[default]
+    Butt,
+    Round,
+    //Sticks out half the line width past the end
+    Square,
+}
+
+impl FromStr for Cap {
+    type Err = FishError;
+
+    fn from_str(cap: &str) -> Result<Self, Self::Err> {
+        match cap.trim().to_ascii_lowercase().as_str() {
+            "butt" => Ok(Cap::Butt),
+            "round" => Ok(Cap::Round),
+            "square" | "projecting" => Ok(Cap::Square),
+            _ => Err(FishError::BadParams(format!("don't know the {:?} cap style, try butt, round or square", cap))),
+        }
+    }
+}
+
+//"true" for plain on/off dashes, or lengths like "8,4,2,4" for a pattern of on, off, on, off
+pub fn parse_dashes(dash: &str) -> Result<Vec<u8>, FishError> {
+    match dash.trim() {
+        "true" | "1" | "on_off" => return Ok(DEFAULT_DASHES.to_vec()),
+        "false" | "0" | "" => return Ok(Vec::new()),
+        _ => {}
+    }
+    let dashes = dash
+        .split(',')
+        .map(|length| length.trim().parse::<u8>().ok().filter(|&length| length > 0))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| FishError::BadParams(format!("{:?} isn't a dash pattern, try 8,4", dash)))?;
+    Ok(dashes)
+}
+
+//How many colors the rainbow goes through before it starts over
+pub const RAINBOW_STEPS: usize = 12;
+
+//Which colors the lines come in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Palette {
+    //Every line in the fish's color
+    #[default]
+    Solid,
+    //Each line the next color around the color wheel
+    Rainbow,
+}
+
+impl FromStr for Palette {
+    type Err = FishError;
+
+    fn from_str(palette: &str) -> Result<Self, Self::Err> {
+        match palette.trim().to_ascii_lowercase().as_str() {
+            "solid" => Ok(Palette::Solid),
+            "rainbow" => Ok(Palette::Rainbow),
+            _ => Err(FishError::BadParams(format!("don't know the {:?} palette, try rainbow", palette))),
+        }
+    }
+}
+
+//Hue in degrees for the nth line of a rainbow fish
+pub fn rainbow_hue(index: usize) -> f64 {
+    (index % RAINBOW_STEPS) as f64 * 360.0 / RAINBOW_STEPS as f64
+}
@@ -0,0 +1,72 @@
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::xproto::{Colormap, ColormapAlloc, ConnectionExt, Screen, VisualClass, VisualType, Visualid};
+
+//What the window's pixels are made of, and what counts as background
+//Usually that's whatever the root window uses with a white (or asked for) background, but
+//transparent windows need a 32 bit visual with alpha and a colormap to match
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Surface {
+    pub depth: u8,
+    pub visual: Visualid,
+    //Only set when it isn't the screen's default one
+    pub colormap: Option<Colormap>,
+    pub background: u32,
+    //Bits to set in every pixel we draw so it isn't see-through
+    alpha: u32,
+}
+
+impl Surface {
+    pub fn opaque(screen: &Screen, background: u32) -> Self {
+        Surface {
+            depth: screen.root_depth,
+            visual: screen.root_visual,
+            colormap: None,
+            background,
+            alpha: 0,
+        }
+    }
+
+    //Whatever someone else's window is made of, for drawing on windows that aren't ours
+    pub fn of_window(depth: u8, visual: Visualid, background: u32) -> Self {
+        Surface {
+            depth,
+            visual,
+            colormap: None,
+            background,
+            alpha: 0,
+        }
+    }
+
+    //A fully see-through background, if the server has a visual with alpha
+    //Only looks like anything with a compositor running, otherwise the background comes out black
+    //The colormap is the caller's to free
+    pub fn transparent(conn: &impl Connection, screen: &Screen) -> Result<Option<Self>, ReplyOrIdError> {
+        let Some(visual) = find_argb_visual(screen) else {
+            return Ok(None);
+        };
+        let colormap = conn.generate_id()?;
+        conn.create_colormap(ColormapAlloc::NONE, colormap, screen.root, visual.visual_id)?;
+        Ok(Some(Surface {
+            depth: 32,
+            visual: visual.visual_id,
+            colormap: Some(colormap),
+            background: 0,
+            alpha: !(visual.red_mask | visual.green_mask | visual.blue_mask),
+        }))
+    }
+
+    //Pixels from the default colormap have no alpha bits, which would make them invisible here
+    pub fn pixel(&self, pixel: u32) -> u32 {
+        pixel | self.alpha
+    }
+}
+
+fn find_argb_visual(screen: &Screen) -> Option<&VisualType> {
+    screen
+        .allowed_depths
+        .iter()
+        .filter(|depth| depth.depth == 32)
+        .flat_map(|depth| depth.visuals.iter())
+        .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+}
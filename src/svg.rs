@@ -0,0 +1,77 @@
+use crate::{color, style, Cap, DrawOptions, Fish, Palette, FISH_CANVAS};
+use std::fmt::Write;
+
+//Browsers know the same color names X does (mostly), so names can go straight through
+//Anything that isn't a plain name or hex gets turned away, it's going into markup
+fn svg_color(color: Option<&str>, default: &str) -> String {
+    match color {
+        Some(color) if color::parse_hex(color).is_some() => format!("#{}", color.trim_start_matches('#')),
+        Some(color) if !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()) => color.to_string(),
+        _ => default.to_string(),
+    }
+}
+
+//A fish that scales to whatever size the browser wants
+pub fn render_svg(fish: &Fish, options: &DrawOptions) -> String {
+    let (width, height) = options.size;
+    let (canvas_width, canvas_height) = FISH_CANVAS;
+    let mut svg = String::new();
+
+    //Writing to a String can't fail, so the results are safe to ignore
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, canvas_width, canvas_height
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        svg_color(options.background.as_deref(), "white")
+    );
+    //Without a color, pick one that shows up on the background, if we can tell what it is
+    let contrast = options
+        .background
+        .as_deref()
+        .and_then(color::parse_basic)
+        .map_or("black", color::contrasting);
+    //Strokes don't scale with the fish, so widths and dashes are in screen pixels like they are in X
+    let cap = match options.cap {
+        Cap::Butt => "butt",
+        Cap::Round => "round",
+        Cap::Square => "square",
+    };
+    let dashes: Vec<String> = options.dashes.iter().map(|length| length.to_string()).collect();
+    let dasharray = if dashes.is_empty() {
+        String::new()
+    } else {
+        format!(r#" stroke-dasharray="{}""#, dashes.join(" "))
+    };
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke="{}" stroke-width="{}" stroke-linecap="{}" stroke-linejoin="round"{}>"#,
+        svg_color(options.color.as_deref(), contrast),
+        options.line_width.max(1),
+        cap,
+        dasharray
+    );
+    for (index, line) in fish.iter().enumerate().filter(|(_, line)| !line.is_empty()) {
+        let points: Vec<String> = line.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+        //Rainbow lines each bring their own color, overriding the group's
+        let stroke = match options.palette {
+            Palette::Solid => String::new(),
+            Palette::Rainbow => {
+                let (red, green, blue) = color::hsv(style::rainbow_hue(index), 1.0, 1.0);
+                format!(r##" stroke="#{:02x}{:02x}{:02x}""##, red >> 8, green >> 8, blue >> 8)
+            }
+        };
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}"{} vector-effect="non-scaling-stroke"/>"#,
+            points.join(" "),
+            stroke
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
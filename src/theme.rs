@@ -0,0 +1,107 @@
+use crate::{Cap, DrawOptions, FishError, Palette};
+use std::str::FromStr;
+
+//A whole look in one word, anything asked for by hand still wins over it
+//Colors are hex so PNGs and GIFs get them too, not just X servers with a color database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub color: Option<&'static str>,
+    pub background: Option<&'static str>,
+    pub line_width: u16,
+    pub dashes: &'static [u8],
+    pub cap: Cap,
+    pub filled: bool,
+    pub palette: Palette,
+}
+
+impl Theme {
+    //Everything the theme has an opinion on, whether or not it was picked by hand before
+    pub fn apply(&self, options: &mut DrawOptions) {
+        options.theme = self.name;
+        options.color = self.color.map(str::to_string);
+        options.background = self.background.map(str::to_string);
+        options.line_width = self.line_width;
+        options.dashes = self.dashes.to_vec();
+        options.cap = self.cap;
+        options.filled = self.filled;
+        options.palette = self.palette;
+    }
+
+    //The one after this, going back to the start after the last one
+    pub fn next(name: &str) -> Theme {
+        let index = THEMES.iter().position(|theme| theme.name == name).map_or(0, |index| index + 1);
+        THEMES[index % THEMES.len()]
+    }
+
+    const fn plain(name: &'static str) -> Self {
+        Theme {
+            name,
+            color: None,
+            background: None,
+            line_width: 0,
+            dashes: &[],
+            cap: Cap::Butt,
+            filled: false,
+            palette: Palette::Solid,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        THEMES[0]
+    }
+}
+
+//The first one is what everyone gets without asking
+pub const THEMES: &[Theme] = &[
+    //Black fish on white, same as always
+    Theme::plain("light"),
+    //Dark theme is just a black background with a white fish
+    Theme {
+        color: Some("white"),
+        background: Some("black"),
+        ..Theme::plain("dark")
+    },
+    //Solid orange in a murky pond
+    Theme {
+        color: Some("#f26b1d"),
+        background: Some("#1e3a4c"),
+        line_width: 3,
+        cap: Cap::Round,
+        filled: true,
+        ..Theme::plain("koi")
+    },
+    //Outline of a goldfish in a clean bowl
+    Theme {
+        color: Some("#f5a300"),
+        background: Some("#d8eefc"),
+        line_width: 2,
+        cap: Cap::Round,
+        ..Theme::plain("goldfish")
+    },
+    //Thick grey lines in deep water
+    Theme {
+        color: Some("#8a969e"),
+        background: Some("#0a1f33"),
+        line_width: 4,
+        cap: Cap::Square,
+        ..Theme::plain("shark")
+    },
+];
+
+pub fn names() -> Vec<&'static str> {
+    THEMES.iter().map(|theme| theme.name).collect()
+}
+
+impl FromStr for Theme {
+    type Err = FishError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let wanted = name.trim().to_ascii_lowercase();
+        THEMES.iter().find(|theme| theme.name == wanted).copied().ok_or_else(|| {
+            FishError::BadParams(format!("don't know the {:?} theme, try one of: {}", name, names().join(", ")))
+        })
+    }
+}
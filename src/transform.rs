@@ -0,0 +1,76 @@
+use crate::{fish_bounds, Fish, FishError};
+use std::str::FromStr;
+
+//Turning the fish around before it's drawn, while it's still floats
+//Everything happens around the middle of the fish, so it stays where it was on the canvas
+
+//Smallest and biggest the fish can be made, any smaller and it's a dot, any bigger and it's off the canvas
+pub const MIN_SCALE: f64 = 0.1;
+pub const MAX_SCALE: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    //Mirrored left to right, so it swims the other way
+    Horizontal,
+    //Upside down
+    Vertical,
+}
+
+impl FromStr for Flip {
+    type Err = FishError;
+
+    fn from_str(flip: &str) -> Result<Self, Self::Err> {
+        match flip.trim().to_ascii_lowercase().as_str() {
+            "h" | "horizontal" => Ok(Flip::Horizontal),
+            "v" | "vertical" => Ok(Flip::Vertical),
+            _ => Err(FishError::BadParams(format!("don't know the {:?} flip, try h or v", flip))),
+        }
+    }
+}
+
+//Flipped first, then rotated, then scaled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub flip: Option<Flip>,
+    //Degrees clockwise, since y goes down
+    pub rotate: f64,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            flip: None,
+            rotate: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn is_identity(&self) -> bool {
+        self.flip.is_none() && self.rotate % 360.0 == 0.0 && self.scale == 1.0
+    }
+
+    pub fn apply(&self, fish: &mut Fish) {
+        if self.is_identity() {
+            return;
+        }
+        let Some(((left, top), (right, bottom))) = fish_bounds(fish) else {
+            return;
+        };
+        let (center_x, center_y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        let (flip_x, flip_y) = match self.flip {
+            Some(Flip::Horizontal) => (-1.0, 1.0),
+            Some(Flip::Vertical) => (1.0, -1.0),
+            None => (1.0, 1.0),
+        };
+        let (sin, cos) = self.rotate.to_radians().sin_cos();
+        for (x, y) in fish.iter_mut().flatten() {
+            let (dx, dy) = ((*x - center_x) * flip_x, (*y - center_y) * flip_y);
+            let (dx, dy) = (dx * cos - dy * sin, dx * sin + dy * cos);
+            *x = center_x + dx * self.scale;
+            *y = center_y + dy * self.scale;
+        }
+    }
+}
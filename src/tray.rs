@@ -0,0 +1,129 @@
+use crate::guard::{GcGuard, PictureGuard, WindowGuard};
+use crate::surface::Surface;
+use crate::{
+    caption, color, create_line_gc, DrawOptions, DrawReport, Ending, Error, Fish, FishError, Pen, Scene, XFishSession,
+};
+use std::borrow::Cow;
+use std::time::Instant;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, PropMode, WindowClass,
+};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+//The one system tray opcode we need, from the freedesktop system tray spec
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+//_XEMBED_INFO says which version of XEmbed we speak, and that we'd like to be shown straight away
+const XEMBED_VERSION: u32 = 0;
+const XEMBED_MAPPED: u32 = 1;
+//What most trays give an icon, the ones that want something else resize it
+const TRAY_ICON_SIZE: u16 = 24;
+
+impl XFishSession {
+    //A tiny fish swimming in the recipient's system tray until the deadline
+    //The tray does the reparenting and mapping, all we do is ask to be docked and then swim
+    pub(crate) fn draw_tray(
+        &self,
+        fish: &Fish,
+        options: &DrawOptions,
+        deadline: Instant,
+    ) -> Result<DrawReport, Error> {
+        let conn = &*self.conn;
+        let screen = self.screen();
+        let atoms = &self.atoms;
+
+        //Whoever owns the selection for this screen is the tray
+        let selection_name = format!("_NET_SYSTEM_TRAY_S{}", self.screen_num);
+        let selection = conn.intern_atom(false, selection_name.as_bytes())?.reply()?.atom;
+        let tray = conn.get_selection_owner(selection)?.reply()?.owner;
+        if tray == NONE {
+            return Err(FishError::NotFound(format!("there's no system tray on screen {}", self.screen_num)).into());
+        }
+
+        //No room for a caption in a tray icon, and nothing to swim after
+        let options = DrawOptions {
+            size: (TRAY_ICON_SIZE, TRAY_ICON_SIZE),
+            caption: None,
+            interactive: false,
+            shaped: false,
+            ..options.clone()
+        };
+        let background = color::alloc_pixel(conn, screen, options.background.as_deref(), screen.white_pixel);
+        let surface = Surface::opaque(screen, background);
+        let window = WindowGuard::new(conn, conn.generate_id()?);
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window.id,
+            screen.root,
+            0,
+            0,
+            TRAY_ICON_SIZE,
+            TRAY_ICON_SIZE,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new()
+                .event_mask(EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY)
+                .background_pixel(background),
+        )?;
+        //Trays show the name as a tooltip
+        conn.change_property8(
+            PropMode::REPLACE,
+            window.id,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            options.title.as_bytes(),
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window.id,
+            atoms._XEMBED_INFO,
+            atoms._XEMBED_INFO,
+            &[XEMBED_VERSION, XEMBED_MAPPED],
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window.id,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            &caption::latin1(&options.title),
+        )?;
+        let dock = ClientMessageEvent::new(
+            32,
+            tray,
+            atoms._NET_SYSTEM_TRAY_OPCODE,
+            [CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, window.id, 0, 0],
+        );
+        conn.send_event(false, tray, EventMask::NO_EVENT, dock)?;
+        conn.flush()?;
+
+        let gc = GcGuard::new(conn, conn.generate_id()?);
+        let foreground = color::alloc_pixel(conn, screen, options.color.as_deref(), screen.black_pixel);
+        create_line_gc(conn, gc.id, window.id, foreground, &options)?;
+        let eraser = self.eraser(&options, &surface, window.id)?;
+        let brush = self.brush(&options, &surface)?;
+        let _fill = brush.map(|brush| PictureGuard::new(conn, brush.fill));
+        let pen = Pen {
+            gc: gc.id,
+            brush,
+            eraser: eraser.as_ref().map(|eraser| eraser.id),
+            palette: self.palette(&options, &surface),
+            caption: None,
+        };
+        let mut scene = Scene {
+            fish: Cow::Borrowed(fish),
+            report: None,
+            clipboard: None,
+            //Trays are an EWMH thing, anything with one speaks it
+            ewmh: true,
+            opened: Instant::now(),
+            closed_after: None,
+        };
+        //Nobody can press n in a tray, so however the swim ends, it's over
+        if let Ending::Destroyed = self.swim(&mut scene, &options, deadline, &surface, window.id, &pen)? {
+            window.forget();
+        }
+        Ok(scene.into_report())
+    }
+}
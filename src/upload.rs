@@ -0,0 +1,40 @@
+use crate::{fish_csv, Error, Fish, FishError};
+
+//Big enough for any reasonable line art, small enough that nobody can make us draw forever
+pub const MAX_BODY_BYTES: usize = 256 * 1024;
+pub const MAX_POINTS: usize = 20_000;
+
+//Turn a user's own drawing into something we can draw
+//Either fish CSV, or JSON like [[[x, y], [x, y]], [[x, y], ...]] with one list of points per line
+pub fn parse(body: &[u8], content_type: Option<&str>) -> Result<Fish, Error> {
+    let bad = |msg: String| -> Error { FishError::BadParams(msg).into() };
+
+    if body.len() > MAX_BODY_BYTES {
+        return Err(bad(format!("drawing is {} bytes, the most we take is {}", body.len(), MAX_BODY_BYTES)));
+    }
+    let text = std::str::from_utf8(body).map_err(|_| bad("drawing has to be text".to_string()))?;
+
+    //Go by the content type if there is one, otherwise JSON is the one that starts with [
+    let is_json = match content_type {
+        Some(content_type) => content_type.starts_with("application/json"),
+        None => text.trim_start().starts_with('['),
+    };
+    let fish: Fish = if is_json {
+        let fish: Fish = serde_json::from_str(text).map_err(|err| bad(format!("bad drawing JSON: {}", err)))?;
+        fish.into_iter().filter(|line| !line.is_empty()).collect()
+    } else {
+        fish_csv::parse(text)?
+    };
+
+    let points: usize = fish.iter().map(|line| line.len()).sum();
+    if points == 0 {
+        return Err(bad("there are no lines to draw".to_string()));
+    }
+    if points > MAX_POINTS {
+        return Err(bad(format!("drawing has {} points, the most we take is {}", points, MAX_POINTS)));
+    }
+    if fish.iter().flatten().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+        return Err(bad("drawing has points that aren't numbers".to_string()));
+    }
+    Ok(fish)
+}
@@ -0,0 +1,83 @@
+use crate::{fit_fish, DrawOptions, DrawReport, Error, Fish, FishError, XFishSession, MAX_LINE_DELAY};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::errors::ConnectionError;
+use x11rb::protocol::xproto::{
+    ConnectionExt, InputFocus, Point, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+
+//Left mouse button, what every paint program draws with
+const BUTTON: u8 = 1;
+//Apps only look at the pointer so often, too fast and they join the dots with straight lines or miss them
+const POINT_DELAY: Duration = Duration::from_millis(4);
+//0 means now, as far as XTEST is concerned
+const CURRENT_TIME: u32 = 0;
+
+impl XFishSession {
+    //Move the recipient's actual pointer along the fish with the button held down,
+    //so it gets drawn in whatever paint program they've got focused
+    //The pointer goes back where it was afterwards, the drawing stays
+    pub(crate) fn draw_xtest(
+        &self,
+        fish: &Fish,
+        options: &DrawOptions,
+        deadline: Instant,
+    ) -> Result<DrawReport, Error> {
+        let conn = &*self.conn;
+        let root = self.screen().root;
+        if conn.extension_information(xtest::X11_EXTENSION_NAME)?.is_none() {
+            let msg = "display doesn't have XTEST, so there's no hand to draw with".to_string();
+            return Err(FishError::BadParams(msg).into());
+        }
+
+        //Into the focused window if there is one, otherwise wherever a fish window would have gone
+        let focus = conn.get_input_focus()?.reply()?.focus;
+        let focused = if focus == u32::from(InputFocus::NONE) || focus == u32::from(InputFocus::POINTER_ROOT) {
+            None
+        } else {
+            let geometry = conn.get_geometry(focus)?.reply()?;
+            let origin = conn.translate_coordinates(focus, root, 0, 0)?.reply()?;
+            Some(((origin.dst_x, origin.dst_y), (geometry.width, geometry.height)))
+        };
+        let ((left, top), size) = focused.unwrap_or((options.position.unwrap_or_default(), options.size));
+        let lines = fit_fish(fish, size);
+        let on_screen = |point: &Point| (left.saturating_add(point.x), top.saturating_add(point.y));
+
+        let pointer = conn.query_pointer(root)?.reply()?;
+        let fake = |type_: u8, detail: u8, (x, y): (i16, i16)| -> Result<(), ConnectionError> {
+            conn.xtest_fake_input(type_, detail, CURRENT_TIME, root, x, y, 0)?;
+            Ok(())
+        };
+        let _span = tracing::info_span!("animate", mode = "xtest").entered();
+        'lines: for line in &lines {
+            let mut points = line.iter().map(on_screen);
+            let Some(start) = points.next() else {
+                continue;
+            };
+            fake(MOTION_NOTIFY_EVENT, 0, start)?;
+            fake(BUTTON_PRESS_EVENT, BUTTON, start)?;
+            for point in points {
+                if self.cancelled() || Instant::now() >= deadline {
+                    //Never leave the button held down, whatever happens
+                    fake(BUTTON_RELEASE_EVENT, BUTTON, point)?;
+                    break 'lines;
+                }
+                fake(MOTION_NOTIFY_EVENT, 0, point)?;
+                conn.flush()?;
+                thread::sleep(POINT_DELAY);
+            }
+            fake(BUTTON_RELEASE_EVENT, BUTTON, line.last().map_or(start, on_screen))?;
+            conn.flush()?;
+            thread::sleep(options.line_delay.min(MAX_LINE_DELAY));
+        }
+        fake(MOTION_NOTIFY_EVENT, 0, (pointer.root_x, pointer.root_y))?;
+        conn.flush()?;
+
+        //Nothing of ours to take a picture of, the fish is in somebody else's app now
+        let report = DrawReport::default();
+        self.drawn(&report);
+        Ok(report)
+    }
+}
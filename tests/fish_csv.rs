@@ -0,0 +1,100 @@
+//Whatever gets thrown at the CSV parser, it gives back a fish worth drawing or an error, never a panic
+
+use proptest::prelude::*;
+use x11_make_a_fish::fish_csv::{self, ParseError};
+use x11_make_a_fish::Fish;
+
+//Every fish the parser lets through has to be drawable
+fn assert_drawable(fish: &Fish) {
+    assert!(!fish.is_empty());
+    for line in fish {
+        assert!(!line.is_empty());
+        assert!(line.iter().all(|&(x, y)| x.is_finite() && y.is_finite()));
+    }
+}
+
+//Errors have to point somewhere in the input
+fn assert_in_bounds(err: &ParseError, fish_str: &str) {
+    let lines = fish_str.lines().count();
+    match err {
+        ParseError::NotANumber { line, column, .. } => {
+            assert!((1..=lines).contains(line));
+            assert!(*column >= 1);
+        }
+        ParseError::OddRow { line, items } => {
+            assert!((1..=lines).contains(line));
+            assert!(items % 2 == 1);
+        }
+        ParseError::Empty => {}
+    }
+}
+
+fn check(fish_str: &str) {
+    match fish_csv::parse(fish_str) {
+        Ok(fish) => assert_drawable(&fish),
+        Err(err) => assert_in_bounds(&err, fish_str),
+    }
+}
+
+//Rows that are mostly numbers, with the kind of junk spreadsheets and people put in them
+fn almost_csv() -> impl Strategy<Value = String> {
+    let item = prop_oneof![
+        any::<f64>().prop_map(|number| number.to_string()),
+        (-1000i32..1000).prop_map(|number| number.to_string()),
+        Just(String::new()),
+        Just(" ".to_string()),
+        Just("NaN".to_string()),
+        Just("inf".to_string()),
+        Just("-inf".to_string()),
+        Just("1e999".to_string()),
+        Just("1.2.3".to_string()),
+        Just("\u{feff}1".to_string()),
+        "[0-9eE+.,-]{0,8}",
+    ];
+    let row = prop::collection::vec(item, 0..12).prop_map(|items| items.join(","));
+    let ending = prop_oneof![Just("\n"), Just("\r\n"), Just(",\n"), Just("\n\n")];
+    prop::collection::vec((row, ending), 0..8)
+        .prop_map(|rows| rows.into_iter().map(|(row, ending)| row + ending).collect())
+}
+
+fn fish() -> impl Strategy<Value = Fish> {
+    let point = (-1e6f64..1e6, -1e6f64..1e6);
+    prop::collection::vec(prop::collection::vec(point, 1..20), 1..10)
+}
+
+proptest! {
+    #[test]
+    fn never_panics_on_bytes(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+        check(&String::from_utf8_lossy(&bytes));
+    }
+
+    #[test]
+    fn never_panics_on_text(fish_str in "\\PC{0,256}") {
+        check(&fish_str);
+    }
+
+    #[test]
+    fn never_panics_on_almost_csv(fish_str in almost_csv()) {
+        check(&fish_str);
+    }
+
+    #[test]
+    fn written_fish_parse_back(fish in fish()) {
+        let parsed = fish_csv::parse(&fish_csv::write(&fish)).unwrap();
+        prop_assert_eq!(parsed.len(), fish.len());
+        for (parsed, line) in parsed.iter().zip(&fish) {
+            prop_assert_eq!(parsed.len(), line.len());
+            for (&(px, py), &(x, y)) in parsed.iter().zip(line) {
+                //Written to the hundredth, give or take a float's worth
+                prop_assert!((px - x).abs() <= 0.005 + 1e-6);
+                prop_assert!((py - y).abs() <= 0.005 + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn huge_numbers_survive_a_round_trip(x in any::<f64>().prop_filter("finite", |x| x.is_finite())) {
+        let fish = vec![vec![(x, x)]];
+        assert_drawable(&fish_csv::parse(&fish_csv::write(&fish)).unwrap());
+    }
+}
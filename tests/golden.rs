@@ -0,0 +1,130 @@
+//Renders fixed fish through the PNG backend and compares them with the pictures in tests/golden
+//The pictures are checked in, a missing one is a failure, not something to quietly write and pass
+//New cases and changing how fish look on purpose mean running with XFISH_BLESS=1 and committing the new pictures
+//A mismatch leaves the new picture next to the old one as .actual.png for eyeballing
+#![cfg(all(feature = "png", feature = "generator"))]
+
+use std::fs;
+use std::path::PathBuf;
+use tiny_skia::Pixmap;
+use x11_make_a_fish::creature::{self, CreatureGenerator};
+use x11_make_a_fish::theme::Theme;
+use x11_make_a_fish::{fish_csv, png, DrawOptions, Fish, Palette};
+
+//Any seed will do, as long as it never changes
+const SEED: u64 = 1111;
+//Anti-aliasing can shift a little between tiny-skia versions and CPUs, that's not what we're after
+const CHANNEL_TOLERANCE: u8 = 8;
+//Out of a thousand pixels
+const PIXEL_TOLERANCE: usize = 5;
+
+fn golden_path(name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.{}", name, extension))
+}
+
+fn creature(name: &str) -> Fish {
+    let creature = creature::find(name).expect("no such creature");
+    fish_csv::parse(&creature.generate_csv_blocking(SEED).expect("creature wouldn't draw")).expect("bad creature CSV")
+}
+
+fn comeback() -> Fish {
+    fish_csv::parse(include_str!("../comeback.csv")).expect("bad comeback CSV")
+}
+
+fn themed(name: &str) -> DrawOptions {
+    let mut options = DrawOptions::default();
+    name.parse::<Theme>().expect("no such theme").apply(&mut options);
+    options
+}
+
+fn check(name: &str, fish: &Fish, options: &DrawOptions) {
+    let rendered = png::render_png(fish, options).expect("couldn't render");
+    let path = golden_path(name, "png");
+    if std::env::var_os("XFISH_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &rendered).unwrap();
+        println!("Wrote {}", path.display());
+        return;
+    }
+    if !path.exists() {
+        panic!("there's no {} to compare {} with, run with XFISH_BLESS=1 and commit it", path.display(), name);
+    }
+
+    let expected = Pixmap::decode_png(&fs::read(&path).unwrap()).expect("golden isn't a PNG");
+    let actual = Pixmap::decode_png(&rendered).unwrap();
+    let differing = if (expected.width(), expected.height()) == (actual.width(), actual.height()) {
+        expected
+            .pixels()
+            .iter()
+            .zip(actual.pixels())
+            .filter(|(expected, actual)| {
+                let channels = |pixel: &tiny_skia::PremultipliedColorU8| {
+                    [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+                };
+                channels(expected)
+                    .iter()
+                    .zip(channels(actual))
+                    .any(|(&expected, actual)| expected.abs_diff(actual) > CHANNEL_TOLERANCE)
+            })
+            .count()
+    } else {
+        usize::MAX
+    };
+    let allowed = expected.pixels().len() * PIXEL_TOLERANCE / 1000;
+    if differing > allowed {
+        let actual_path = golden_path(name, "actual.png");
+        fs::write(&actual_path, &rendered).unwrap();
+        panic!(
+            "{} doesn't look like it used to, {} pixels are off (new picture in {})",
+            name,
+            differing,
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn comeback_fish() {
+    check("comeback", &comeback(), &DrawOptions::default());
+}
+
+#[test]
+fn creatures() {
+    for name in creature::names() {
+        //The fish comes from the remote generator, which isn't something a test should depend on
+        if name == "fish" {
+            continue;
+        }
+        check(name, &creature(name), &DrawOptions::default());
+    }
+}
+
+#[test]
+fn themes() {
+    for name in ["dark", "koi", "goldfish", "shark"] {
+        check(&format!("comeback-{}", name), &comeback(), &themed(name));
+    }
+}
+
+#[test]
+fn rainbow_dashes() {
+    let options = DrawOptions {
+        line_width: 3,
+        dashes: vec![6, 3],
+        palette: Palette::Rainbow,
+        ..DrawOptions::default()
+    };
+    check("comeback-rainbow-dashes", &comeback(), &options);
+}
+
+#[test]
+fn small() {
+    let options = DrawOptions {
+        size: (104, 64),
+        ..DrawOptions::default()
+    };
+    check("comeback-small", &comeback(), &options);
+}
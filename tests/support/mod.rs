@@ -0,0 +1,110 @@
+//A throwaway Xvfb for tests that need a real X server, taken down again when it's dropped
+//Machines without Xvfb skip those tests instead of failing them
+
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::image::Image;
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+//Well clear of anyone's real display, and spread out by PID so parallel test binaries don't fight over them
+const FIRST_DISPLAY: u32 = 140;
+//How long Xvfb gets to make its socket before we decide it's not happening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const SCREEN: &str = "640x480x24";
+
+static NEXT_DISPLAY: AtomicU32 = AtomicU32::new(0);
+
+pub struct Xvfb {
+    child: Child,
+    pub display: String,
+}
+
+impl Xvfb {
+    //None if there's no Xvfb to run
+    pub fn start() -> Option<Xvfb> {
+        for _ in 0..16 {
+            let number = FIRST_DISPLAY + (std::process::id() % 64) * 16 + NEXT_DISPLAY.fetch_add(1, Ordering::Relaxed);
+            //Somebody else's server, or one that didn't clean up after itself
+            if Path::new(&format!("/tmp/.X{}-lock", number)).exists() {
+                continue;
+            }
+            let child = Command::new("Xvfb")
+                .arg(format!(":{}", number))
+                .args(["-screen", "0", SCREEN, "-nolisten", "tcp"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            let Ok(child) = child else {
+                println!("No Xvfb, skipping");
+                return None;
+            };
+            let mut xvfb = Xvfb {
+                child,
+                display: format!(":{}", number),
+            };
+            if xvfb.wait_for_socket(number) {
+                return Some(xvfb);
+            }
+        }
+        panic!("couldn't get an Xvfb to start on any display");
+    }
+
+    fn wait_for_socket(&mut self, number: u32) -> bool {
+        let socket = format!("/tmp/.X11-unix/X{}", number);
+        let started = Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            //Exited already, most likely the display was taken after all
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return false;
+            }
+            if Path::new(&socket).exists() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    pub fn connect(&self) -> RustConnection {
+        x11rb::connect(Some(&self.display)).expect("couldn't connect to Xvfb").0
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+//How many pixels in the rectangle the test is happy with
+pub fn count_pixels(
+    conn: &impl Connection,
+    window: Window,
+    (x, y, width, height): (i16, i16, u16, u16),
+    wanted: impl Fn(u32) -> bool,
+) -> usize {
+    let (image, _) = Image::get(conn, window, x, y, width, height).expect("couldn't read the screen back");
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        //Depth 24 pixels come in 32 bits, and the spare byte could be anything
+        .filter(|&(x, y)| wanted(image.get_pixel(x, y) & 0x00ff_ffff))
+        .count()
+}
+
+//Wait for something the drawing thread is going to do
+pub fn wait_until(timeout: Duration, done: impl Fn() -> bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
@@ -0,0 +1,87 @@
+//The whole draw path against a real X server, run headless under Xvfb
+//Skips itself when Xvfb isn't installed
+
+mod support;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use support::{count_pixels, wait_until, Xvfb};
+use x11_make_a_fish::{DrawOptions, Fish, Target, XFishSession};
+use x11rb::connection::Connection;
+
+//Red on a 24 bit TrueColor screen, which is what Xvfb gives us
+const RED: u32 = 0xff0000;
+const WHITE: u32 = 0xffffff;
+const WINDOW: (i16, i16, u16, u16) = (0, 0, 260, 160);
+
+//Not much of a fish, but it crosses most of the window whatever size it gets fitted to
+fn fish() -> Fish {
+    vec![
+        vec![(60.0, 60.0), (460.0, 60.0), (460.0, 260.0), (60.0, 260.0), (60.0, 60.0)],
+        vec![(60.0, 60.0), (460.0, 260.0)],
+    ]
+}
+
+#[test]
+fn draws_a_fish_in_a_window() {
+    let Some(xvfb) = Xvfb::start() else {
+        return;
+    };
+    let drawn = Arc::new(AtomicBool::new(false));
+    let cancel = Arc::new(AtomicBool::new(false));
+    let on_drawn = drawn.clone();
+    let session = XFishSession::connect(&xvfb.display)
+        .expect("couldn't connect")
+        .with_cancel(cancel.clone())
+        .with_on_drawn(Arc::new(move |_| on_drawn.store(true, Ordering::Relaxed)));
+    //No window manager under Xvfb, but popups go exactly where they're put anyway
+    let options = DrawOptions {
+        size: (WINDOW.2, WINDOW.3),
+        position: Some((WINDOW.0, WINDOW.1)),
+        popup: true,
+        instant: true,
+        color: Some("red".to_string()),
+        background: Some("white".to_string()),
+        ..DrawOptions::default()
+    };
+    let fish = fish();
+
+    thread::scope(|scope| {
+        let drawing = scope.spawn(|| session.draw(&fish, &options, Instant::now() + Duration::from_secs(30)));
+        assert!(wait_until(Duration::from_secs(10), || drawn.load(Ordering::Relaxed)), "fish never got drawn");
+
+        let conn = xvfb.connect();
+        let root = conn.setup().roots[0].root;
+        let background = count_pixels(&conn, root, WINDOW, |pixel| pixel == WHITE);
+        let fish = count_pixels(&conn, root, WINDOW, |pixel| pixel != WHITE);
+        assert!(background > 0, "window's background never showed up");
+        assert!(fish > 0, "nothing but background where the fish should be");
+
+        cancel.store(true, Ordering::Relaxed);
+        drawing.join().unwrap().expect("drawing failed");
+    });
+}
+
+#[test]
+fn draws_a_fish_on_the_root_window() {
+    let Some(xvfb) = Xvfb::start() else {
+        return;
+    };
+    let session = XFishSession::connect(&xvfb.display).expect("couldn't connect");
+    //Core lines, so the fish comes out exactly red instead of shades of it
+    let options = DrawOptions {
+        target: Target::Root,
+        anti_alias: false,
+        color: Some("red".to_string()),
+        background: Some("white".to_string()),
+        ..DrawOptions::default()
+    };
+    session.draw(&fish(), &options, Instant::now() + Duration::from_secs(5)).expect("drawing failed");
+
+    let conn = xvfb.connect();
+    let screen = &conn.setup().roots[0];
+    let whole = (0, 0, screen.width_in_pixels, screen.height_in_pixels);
+    assert!(count_pixels(&conn, screen.root, whole, |pixel| pixel == RED) > 0, "no red fish on the desktop");
+}